@@ -0,0 +1,35 @@
+//! Per-message key setup vs. the batch API on many tiny messages.
+//!
+//! Run with `cargo bench --bench batch`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use sqep_lite::ZeroshieldCipher;
+
+const MESSAGES: usize = 10_000;
+
+fn main() {
+    let cipher = ZeroshieldCipher::new();
+    let msgs: Vec<[u8; 16]> = (0..MESSAGES as u32)
+        .map(|i| {
+            let mut m = [0u8; 16];
+            m[..4].copy_from_slice(&i.to_le_bytes());
+            m
+        })
+        .collect();
+    let refs: Vec<&[u8]> = msgs.iter().map(|m| m.as_slice()).collect();
+
+    let start = Instant::now();
+    for msg in &refs {
+        black_box(cipher.encrypt_with_meta(msg).unwrap());
+    }
+    let single = start.elapsed();
+
+    let start = Instant::now();
+    black_box(cipher.encrypt_many(&refs).unwrap());
+    let batch = start.elapsed();
+
+    println!("encrypt_with_meta x{}: {:?} ({:?}/msg)", MESSAGES, single, single / MESSAGES as u32);
+    println!("encrypt_many      x{}: {:?} ({:?}/msg)", MESSAGES, batch, batch / MESSAGES as u32);
+}
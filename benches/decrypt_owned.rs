@@ -0,0 +1,54 @@
+//! Allocations and time of `decrypt` vs. `decrypt_owned` on one frame.
+//!
+//! Run with `cargo bench --bench decrypt_owned`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use sqep_lite::ZeroshieldCipher;
+
+const ROUNDS: usize = 1_000;
+const MESSAGE_LEN: usize = 64 * 1024;
+
+fn main() {
+    let cipher = ZeroshieldCipher::new();
+    let (ct, _meta) = cipher.encrypt_with_meta(vec![0x5A; MESSAGE_LEN]).unwrap();
+    let frames: Vec<Vec<u8>> = (0..ROUNDS).map(|_| ct.clone()).collect();
+
+    let (borrowed, allocations) = common::measure(|| {
+        let start = Instant::now();
+        for frame in &frames {
+            black_box(cipher.decrypt(frame).unwrap());
+        }
+        start.elapsed()
+    });
+    let borrowed_allocs = allocations.count;
+
+    let (owned, allocations) = common::measure(|| {
+        let start = Instant::now();
+        for frame in frames {
+            black_box(cipher.decrypt_owned(frame).unwrap());
+        }
+        start.elapsed()
+    });
+    let owned_allocs = allocations.count;
+
+    let per = |n: usize| n as f64 / ROUNDS as f64;
+    println!(
+        "decrypt       x{}: {:?} ({:?}/msg, {:.1} allocs/msg)",
+        ROUNDS,
+        borrowed,
+        borrowed / ROUNDS as u32,
+        per(borrowed_allocs)
+    );
+    println!(
+        "decrypt_owned x{}: {:?} ({:?}/msg, {:.1} allocs/msg)",
+        ROUNDS,
+        owned,
+        owned / ROUNDS as u32,
+        per(owned_allocs)
+    );
+}
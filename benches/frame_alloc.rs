@@ -0,0 +1,50 @@
+//! Heap allocations and time of `encrypt_with_meta` on mid-size payloads.
+//!
+//! Compact frames are sealed in place in one buffer of the final size; TLV
+//! frames are still assembled from parts and serve as the comparison.
+//!
+//! Run with `cargo bench --bench frame_alloc`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sqep_lite::{FrameEncoding, ZeroshieldCipher};
+
+const ROUNDS: u32 = 200;
+
+fn main() {
+    let compact = ZeroshieldCipher::new();
+    let tlv = compact.clone().with_frame_encoding(FrameEncoding::Tlv);
+    for len in [4 * 1024, 64 * 1024, 512 * 1024] {
+        let data = vec![0x5au8; len];
+        println!("{} KiB payloads x{}:", len >> 10, ROUNDS);
+        for (name, cipher) in [("compact", &compact), ("tlv    ", &tlv)] {
+            let (allocs, bytes, elapsed) = measure(|| {
+                black_box(cipher.encrypt_with_meta(black_box(&data)).unwrap());
+            });
+            println!(
+                "  {} {:>3} allocs, {:>7} bytes ({:.2}x payload), {:?}/frame",
+                name,
+                allocs / ROUNDS as usize,
+                bytes / ROUNDS as usize,
+                bytes as f64 / ROUNDS as f64 / len as f64,
+                elapsed / ROUNDS
+            );
+        }
+    }
+}
+
+/// Allocation count, bytes allocated and time over `ROUNDS` calls of `op`
+fn measure(mut op: impl FnMut()) -> (usize, usize, Duration) {
+    let (elapsed, allocations) = common::measure(|| {
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            op();
+        }
+        start.elapsed()
+    });
+    (allocations.count, allocations.bytes, elapsed)
+}
@@ -0,0 +1,40 @@
+//! `encrypt_with_meta` on large frames with and without `SealMeta.hash`.
+//!
+//! Run with `cargo bench --bench meta_hash`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sqep_lite::ZeroshieldCipher;
+
+const FRAME_LEN: usize = 16 * 1024 * 1024;
+const ROUNDS: u32 = 8;
+
+fn main() {
+    let hashed = ZeroshieldCipher::new();
+    let unhashed = hashed.clone().with_meta_hash(false);
+    let data = vec![0x5au8; FRAME_LEN];
+
+    let with_hash = time(|| {
+        black_box(hashed.encrypt_with_meta(black_box(&data)).unwrap());
+    });
+    let without_hash = time(|| {
+        black_box(unhashed.encrypt_with_meta(black_box(&data)).unwrap());
+    });
+
+    println!("{} MiB frames x{}:", FRAME_LEN >> 20, ROUNDS);
+    println!("  with hash    {:?}/frame", with_hash / ROUNDS);
+    println!(
+        "  without hash {:?}/frame ({:.1}% faster)",
+        without_hash / ROUNDS,
+        100.0 * (1.0 - without_hash.as_secs_f64() / with_hash.as_secs_f64())
+    );
+}
+
+fn time(mut op: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        op();
+    }
+    start.elapsed()
+}
@@ -0,0 +1,43 @@
+//! Cost of building a `SystemRandom` per nonce vs. reusing one, next to a
+//! million tiny `encrypt_with_meta` calls.
+//!
+//! Run with `cargo bench --bench nonce_rng`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use sqep_lite::ZeroshieldCipher;
+
+const ROUNDS: u32 = 1_000_000;
+
+fn main() {
+    let fresh = time(|| {
+        let mut nonce = [0u8; 12];
+        SystemRandom::new().fill(&mut nonce).unwrap();
+        black_box(nonce);
+    });
+    let shared = SystemRandom::new();
+    let cached = time(|| {
+        let mut nonce = [0u8; 12];
+        shared.fill(&mut nonce).unwrap();
+        black_box(nonce);
+    });
+    let cipher = ZeroshieldCipher::new().with_meta_hash(false);
+    let encrypt = time(|| {
+        black_box(cipher.encrypt_with_meta(black_box(b"tiny")).unwrap());
+    });
+
+    println!("x{}:", ROUNDS);
+    println!("  nonce, new SystemRandom    {:?}/op", fresh / ROUNDS);
+    println!("  nonce, cached SystemRandom {:?}/op", cached / ROUNDS);
+    println!("  encrypt_with_meta (4 B)    {:?}/op", encrypt / ROUNDS);
+}
+
+fn time(mut op: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        op();
+    }
+    start.elapsed()
+}
@@ -0,0 +1,52 @@
+//! Per-call AEAD key setup vs. a `PreparedCipher` reusing one key.
+//!
+//! Run with `cargo bench --bench prepared`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sqep_lite::ZeroshieldCipher;
+
+const MESSAGES: usize = 20_000;
+
+fn main() {
+    let cipher = ZeroshieldCipher::new();
+    let prepared = cipher.prepare().unwrap();
+    let msg = [0x42u8; 64];
+    let (frame, _meta) = cipher.encrypt_with_meta(msg).unwrap();
+
+    let per_call = time(|| {
+        black_box(cipher.encrypt_with_meta(black_box(&msg)).unwrap());
+    });
+    let reused = time(|| {
+        black_box(prepared.encrypt(black_box(&msg)).unwrap());
+    });
+    report("encrypt", per_call, reused);
+
+    let per_call = time(|| {
+        black_box(cipher.decrypt(black_box(&frame)).unwrap());
+    });
+    let reused = time(|| {
+        black_box(prepared.decrypt(black_box(&frame)).unwrap());
+    });
+    report("decrypt", per_call, reused);
+}
+
+fn time(mut op: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..MESSAGES {
+        op();
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, per_call: Duration, reused: Duration) {
+    println!(
+        "{} x{}: per-call key {:?}/msg, prepared {:?}/msg ({:.2}x)",
+        name,
+        MESSAGES,
+        per_call / MESSAGES as u32,
+        reused / MESSAGES as u32,
+        per_call.as_secs_f64() / reused.as_secs_f64()
+    );
+}
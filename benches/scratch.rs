@@ -0,0 +1,54 @@
+//! Allocations and time of `encrypt_with_meta` vs. `encrypt_reusing`.
+//!
+//! Run with `cargo bench --bench scratch`.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use sqep_lite::{ScratchBuffers, ZeroshieldCipher};
+
+const ROUNDS: usize = 1_000;
+const MESSAGE_LEN: usize = 64 * 1024;
+
+fn main() {
+    let cipher = ZeroshieldCipher::new();
+    let message = vec![0x5A; MESSAGE_LEN];
+
+    let (fresh, allocations) = common::measure(|| {
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            black_box(cipher.encrypt_with_meta(black_box(&message)).unwrap());
+        }
+        start.elapsed()
+    });
+    let fresh_allocs = allocations.count;
+
+    let mut scratch = ScratchBuffers::new();
+    let (reused, allocations) = common::measure(|| {
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            black_box(cipher.encrypt_reusing(black_box(&message), &mut scratch).unwrap());
+        }
+        start.elapsed()
+    });
+    let reused_allocs = allocations.count;
+
+    let per = |n: usize| n as f64 / ROUNDS as f64;
+    println!(
+        "encrypt_with_meta x{}: {:?} ({:?}/msg, {:.1} allocs/msg)",
+        ROUNDS,
+        fresh,
+        fresh / ROUNDS as u32,
+        per(fresh_allocs)
+    );
+    println!(
+        "encrypt_reusing   x{}: {:?} ({:?}/msg, {:.1} allocs/msg)",
+        ROUNDS,
+        reused,
+        reused / ROUNDS as u32,
+        per(reused_allocs)
+    );
+}
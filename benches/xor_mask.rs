@@ -0,0 +1,41 @@
+//! Throughput of a 64 MiB encrypt/decrypt round, which includes the keyed
+//! XOR mask on both sides.
+//!
+//! Run with `cargo bench --bench xor_mask`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use sqep_lite::ZeroshieldCipher;
+
+const FRAME_LEN: usize = 64 * 1024 * 1024;
+const ROUNDS: u32 = 4;
+
+fn main() {
+    let cipher = ZeroshieldCipher::new().with_meta_hash(false);
+    let data = vec![0x5au8; FRAME_LEN];
+    let (frame, _meta) = cipher.encrypt_with_meta(&data).unwrap();
+
+    let encrypt = time(|| {
+        black_box(cipher.encrypt_with_meta(black_box(&data)).unwrap());
+    });
+    let decrypt = time(|| {
+        black_box(cipher.decrypt(black_box(&frame)).unwrap());
+    });
+
+    println!("{} MiB frames x{}:", FRAME_LEN >> 20, ROUNDS);
+    println!("  encrypt {:?}/frame ({:.0} MiB/s)", encrypt / ROUNDS, mib_per_sec(encrypt));
+    println!("  decrypt {:?}/frame ({:.0} MiB/s)", decrypt / ROUNDS, mib_per_sec(decrypt));
+}
+
+fn mib_per_sec(elapsed: Duration) -> f64 {
+    f64::from(ROUNDS) * (FRAME_LEN >> 20) as f64 / elapsed.as_secs_f64()
+}
+
+fn time(mut op: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        op();
+    }
+    start.elapsed()
+}
@@ -0,0 +1,258 @@
+//! AEAD algorithm selection.
+//!
+//! The default ChaCha20-Poly1305 cipher writes the classic `SQEP4.0-LITE`
+//! frame. Any other algorithm writes a `SQEP4.1-LITE` frame that records the
+//! algorithm id right after the magic (and authenticates that header via
+//! AAD). `decrypt` checks that byte against its own configuration and
+//! reports `AlgorithmMismatch` instead of a bare authentication failure.
+
+use ring::aead::{self, Aad, LessSafeKey, Nonce, Tag, UnboundKey};
+
+use crate::error::SqepError;
+use crate::frame::{NONCE_LEN, TAG_LEN};
+use crate::lite::KEY_LEN;
+
+/// AEAD used for the sealed body.
+///
+/// Non-exhaustive: feature flags add variants, and any crate in the build
+/// enabling one adds it for everyone, so downstream matches need a `_` arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// ChaCha20-Poly1305 via `ring` (default)
+    #[default]
+    ChaCha20Poly1305,
+    /// AES-256-GCM-SIV (nonce-misuse resistant) via the `aes-gcm-siv` crate.
+    ///
+    /// Accidental nonce reuse only reveals whether two messages are equal,
+    /// instead of leaking their XOR and enabling forgeries.
+    #[cfg(feature = "aes-gcm-siv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "aes-gcm-siv")))]
+    Aes256GcmSiv,
+}
+
+impl Algorithm {
+    /// Id byte recorded in `SQEP4.1-LITE` frames
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::ChaCha20Poly1305 => 0x01,
+            #[cfg(feature = "aes-gcm-siv")]
+            Algorithm::Aes256GcmSiv => 0x02,
+        }
+    }
+
+    /// Look up an algorithm by frame id; ids of algorithms not compiled
+    /// into this build return `UnsupportedAlgorithm`.
+    pub fn from_id(id: u8) -> Result<Self, SqepError> {
+        match id {
+            0x01 => Ok(Algorithm::ChaCha20Poly1305),
+            #[cfg(feature = "aes-gcm-siv")]
+            0x02 => Ok(Algorithm::Aes256GcmSiv),
+            other => Err(SqepError::UnsupportedAlgorithm(other)),
+        }
+    }
+}
+
+/// Ready-to-use AEAD key for one algorithm
+#[allow(clippy::large_enum_variant)] // short-lived, built once per operation
+pub(crate) enum AeadKey {
+    ChaCha(LessSafeKey),
+    #[cfg(feature = "aes-gcm-siv")]
+    AesSiv(aes_gcm_siv::Aes256GcmSiv),
+}
+
+impl AeadKey {
+    pub(crate) fn new(algorithm: Algorithm, key: &[u8; KEY_LEN]) -> Result<Self, SqepError> {
+        match algorithm {
+            Algorithm::ChaCha20Poly1305 => {
+                let unbound =
+                    UnboundKey::new(&aead::CHACHA20_POLY1305, key).map_err(|_| SqepError::AeadInit)?;
+                Ok(AeadKey::ChaCha(LessSafeKey::new(unbound)))
+            }
+            #[cfg(feature = "aes-gcm-siv")]
+            Algorithm::Aes256GcmSiv => {
+                use aes_gcm_siv::aead::KeyInit;
+                let cipher =
+                    aes_gcm_siv::Aes256GcmSiv::new_from_slice(key).map_err(|_| SqepError::AeadInit)?;
+                Ok(AeadKey::AesSiv(cipher))
+            }
+        }
+    }
+
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        match self {
+            AeadKey::ChaCha(_) => Algorithm::ChaCha20Poly1305,
+            #[cfg(feature = "aes-gcm-siv")]
+            AeadKey::AesSiv(_) => Algorithm::Aes256GcmSiv,
+        }
+    }
+
+    /// Encrypt `in_out` in place, returning the 16-byte tag
+    pub(crate) fn seal_in_place_detached(
+        &self,
+        nonce: &[u8; NONCE_LEN],
+        aad: &[u8],
+        in_out: &mut [u8],
+    ) -> Result<[u8; TAG_LEN], SqepError> {
+        let mut tag = [0u8; TAG_LEN];
+        match self {
+            AeadKey::ChaCha(key) => {
+                let nonce = Nonce::assume_unique_for_key(*nonce);
+                let sealed = key
+                    .seal_in_place_separate_tag(nonce, Aad::from(aad), in_out)
+                    .map_err(|_| SqepError::SealFailed)?;
+                tag.copy_from_slice(sealed.as_ref());
+            }
+            #[cfg(feature = "aes-gcm-siv")]
+            AeadKey::AesSiv(key) => {
+                use aes_gcm_siv::aead::AeadInPlace;
+                let sealed = key
+                    .encrypt_in_place_detached(nonce.into(), aad, in_out)
+                    .map_err(|_| SqepError::SealFailed)?;
+                tag.copy_from_slice(&sealed);
+            }
+        }
+        Ok(tag)
+    }
+
+    /// Verify and decrypt `in_out` in place, truncating it to the plaintext
+    pub(crate) fn open_in_place(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        in_out: &mut Vec<u8>,
+    ) -> Result<(), SqepError> {
+        match self {
+            AeadKey::ChaCha(key) => {
+                let nonce =
+                    Nonce::try_assume_unique_for_key(nonce).map_err(|_| SqepError::InvalidNonce)?;
+                let len = key
+                    .open_in_place(nonce, Aad::from(aad), in_out)
+                    .map_err(|_| SqepError::DecryptionFailed)?
+                    .len();
+                in_out.truncate(len);
+                Ok(())
+            }
+            #[cfg(feature = "aes-gcm-siv")]
+            AeadKey::AesSiv(key) => {
+                use aes_gcm_siv::aead::AeadInPlace;
+                if nonce.len() != NONCE_LEN {
+                    return Err(SqepError::InvalidNonce);
+                }
+                key.decrypt_in_place(aes_gcm_siv::Nonce::from_slice(nonce), aad, in_out)
+                    .map_err(|_| SqepError::DecryptionFailed)
+            }
+        }
+    }
+
+    /// Verify `tag` and decrypt `in_out` in place, without allocating
+    pub(crate) fn open_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        in_out: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), SqepError> {
+        match self {
+            AeadKey::ChaCha(key) => {
+                let nonce =
+                    Nonce::try_assume_unique_for_key(nonce).map_err(|_| SqepError::InvalidNonce)?;
+                let tag = Tag::try_from(tag).map_err(|_| SqepError::DecryptionFailed)?;
+                key.open_in_place_separate_tag(nonce, Aad::from(aad), tag, in_out, 0..)
+                    .map(|_| ())
+                    .map_err(|_| SqepError::DecryptionFailed)
+            }
+            #[cfg(feature = "aes-gcm-siv")]
+            AeadKey::AesSiv(key) => {
+                use aes_gcm_siv::aead::AeadInPlace;
+                if nonce.len() != NONCE_LEN || tag.len() != 16 {
+                    return Err(SqepError::DecryptionFailed);
+                }
+                let nonce = aes_gcm_siv::Nonce::from_slice(nonce);
+                let tag = aes_gcm_siv::Tag::from_slice(tag);
+                key.decrypt_in_place_detached(nonce, aad, in_out, tag)
+                    .map_err(|_| SqepError::DecryptionFailed)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "aes-gcm-siv")]
+    use crate::lite::ZeroshieldCipher;
+
+    #[test]
+    fn unknown_algorithm_id_is_rejected() {
+        assert_eq!(Algorithm::from_id(0xEE), Err(SqepError::UnsupportedAlgorithm(0xEE)));
+        assert_eq!(
+            Algorithm::from_id(Algorithm::ChaCha20Poly1305.id()),
+            Ok(Algorithm::ChaCha20Poly1305)
+        );
+    }
+
+    #[cfg(feature = "aes-gcm-siv")]
+    #[test]
+    fn aes_gcm_siv_roundtrip() {
+        let key = [7u8; KEY_LEN];
+        let aes = ZeroshieldCipher::from_key(key).with_algorithm(Algorithm::Aes256GcmSiv);
+        let (ct, _m) = aes.encrypt_with_meta(b"misuse resistant").expect("encrypt");
+        assert!(ct.starts_with(b"SQEP4.1-LITE"));
+        assert_eq!(ct[12], Algorithm::Aes256GcmSiv.id());
+
+        assert_eq!(aes.decrypt(&ct).expect("decrypt"), b"misuse resistant");
+
+        // The algorithm byte is authenticated
+        let mut swapped = ct.clone();
+        swapped[12] = Algorithm::ChaCha20Poly1305.id();
+        let chacha = ZeroshieldCipher::from_key(key);
+        assert_eq!(chacha.decrypt(&swapped), Err(SqepError::DecryptionFailed));
+    }
+
+    #[cfg(feature = "aes-gcm-siv")]
+    #[test]
+    fn configured_algorithm_must_match_the_frame() {
+        let key = [7u8; KEY_LEN];
+        let aes = ZeroshieldCipher::from_key(key).with_algorithm(Algorithm::Aes256GcmSiv);
+        let (ct, _m) = aes.encrypt_with_meta(b"sealed with AES").expect("encrypt");
+
+        let err = ZeroshieldCipher::from_key(key).decrypt(&ct).unwrap_err();
+        assert_eq!(
+            err,
+            SqepError::AlgorithmMismatch {
+                expected: Algorithm::ChaCha20Poly1305,
+                found: Algorithm::Aes256GcmSiv,
+            }
+        );
+        assert!(err.to_string().contains("Aes256GcmSiv"));
+
+        // Classic frames carry no algorithm byte and stay ChaCha20-Poly1305
+        let (classic, _m) = ZeroshieldCipher::from_key(key).encrypt_with_meta(b"4.0").unwrap();
+        assert_eq!(aes.decrypt(&classic).unwrap(), b"4.0");
+    }
+
+    #[cfg(feature = "aes-gcm-siv")]
+    #[test]
+    fn aes_gcm_siv_survives_nonce_reuse() {
+        let nonce = [9u8; NONCE_LEN];
+        let (p1, p2) = (b"attack at dawn!!", b"attack at dusk!!");
+        let xor = |a: &[u8], b: &[u8]| -> Vec<u8> { a.iter().zip(b).map(|(x, y)| x ^ y).collect() };
+
+        // ChaCha20-Poly1305: a reused nonce leaks p1 ^ p2 through the body
+        let chacha = ZeroshieldCipher::from_key([3u8; KEY_LEN]);
+        let c1 = chacha.seal_frame_with_nonce(nonce, p1, &[]).expect("seal");
+        let c2 = chacha.seal_frame_with_nonce(nonce, p2, &[]).expect("seal");
+        let body = 12 + NONCE_LEN;
+        assert_eq!(xor(&c1[body..body + 16], &c2[body..body + 16]), xor(p1, p2));
+
+        // AES-256-GCM-SIV: same nonce, no XOR leak, and both still open
+        let aes = chacha.clone().with_algorithm(Algorithm::Aes256GcmSiv);
+        let c1 = aes.seal_frame_with_nonce(nonce, p1, &[]).expect("seal");
+        let c2 = aes.seal_frame_with_nonce(nonce, p2, &[]).expect("seal");
+        let body = 13 + NONCE_LEN;
+        assert_ne!(xor(&c1[body..body + 16], &c2[body..body + 16]), xor(p1, p2));
+        assert_eq!(aes.decrypt(&c1).expect("decrypt"), p1);
+        assert_eq!(aes.decrypt(&c2).expect("decrypt"), p2);
+    }
+}
@@ -0,0 +1,85 @@
+//! Many small records sealed together as one frame.
+//!
+//! ```text
+//! ARCHIVE   = FRAME(PLAINTEXT, aad = "SQEP:LITE:ARCHIVE:v1")
+//! PLAINTEXT = (LEN (u32 BE) || RECORD)*
+//! ```
+//!
+//! The records share one nonce, one tag and one header, so an archive of
+//! small records is much shorter than a frame per record, and records can't
+//! be dropped, reordered or swapped between archives without failing the
+//! tag. The AAD keeps an archive from opening as a plain frame and vice
+//! versa. The archive is opened as a whole; it is not meant for records
+//! that need to be read one at a time.
+
+use zeroize::Zeroizing;
+
+use crate::error::SqepError;
+use crate::frame::{read_len, write_len, LEN_SIZE};
+use crate::lite::ZeroshieldCipher;
+
+const ARCHIVE_DOMAIN: &[u8] = b"SQEP:LITE:ARCHIVE:v1";
+
+impl ZeroshieldCipher {
+    /// Seal `records` into one archive frame
+    pub fn encrypt_archive(&self, records: &[&[u8]]) -> Result<Vec<u8>, SqepError> {
+        let packed_len = records.iter().map(|record| LEN_SIZE + record.len()).sum();
+        let mut packed = Zeroizing::new(Vec::with_capacity(packed_len));
+        for record in records {
+            packed.extend_from_slice(&write_len(record.len())?);
+            packed.extend_from_slice(record);
+        }
+        self.seal_frame(&packed, ARCHIVE_DOMAIN)
+    }
+
+    /// Open an archive from `encrypt_archive` and split it into its
+    /// records, in order
+    pub fn decrypt_archive(&self, archive: impl AsRef<[u8]>) -> Result<Vec<Vec<u8>>, SqepError> {
+        let archive = archive.as_ref();
+        let packed = Zeroizing::new(self.open_frame(archive, ARCHIVE_DOMAIN)?);
+        let mut rest = &packed[..];
+        let mut records = Vec::new();
+        while !rest.is_empty() {
+            let end = LEN_SIZE.saturating_add(read_len(rest).map_err(|_| malformed())?);
+            let record = rest.get(LEN_SIZE..end).ok_or_else(malformed)?;
+            records.push(record.to_vec());
+            rest = &rest[end..];
+        }
+        Ok(records)
+    }
+}
+
+/// An authenticated archive whose records don't parse, which only a holder
+/// of the key could have sealed
+fn malformed() -> SqepError {
+    SqepError::Serialization("malformed archive records".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_records_roundtrip() {
+        let cipher = ZeroshieldCipher::new();
+        let large = vec![0x5a; 70_000];
+        let records: [&[u8]; 4] = [b"first", b"", &large, b"last"];
+
+        let archive = cipher.encrypt_archive(&records).expect("encrypt");
+        assert_eq!(cipher.decrypt_archive(&archive).unwrap(), records);
+        assert_eq!(archive.len(), cipher.frame_overhead() + 4 * LEN_SIZE + 70_009);
+
+        let empty = cipher.encrypt_archive(&[]).expect("encrypt");
+        assert!(cipher.decrypt_archive(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn archives_and_plain_frames_do_not_mix() {
+        let cipher = ZeroshieldCipher::new();
+        let archive = cipher.encrypt_archive(&[b"record"]).expect("encrypt");
+        assert_eq!(cipher.decrypt(&archive), Err(SqepError::DecryptionFailed));
+
+        let (frame, _m) = cipher.encrypt_with_meta(b"\0\0\0\x01x").expect("encrypt");
+        assert_eq!(cipher.decrypt_archive(&frame), Err(SqepError::DecryptionFailed));
+    }
+}
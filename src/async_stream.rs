@@ -0,0 +1,368 @@
+//! `tokio` adapters for the chunked stream format (feature `tokio`).
+//!
+//! `AsyncEncryptWriter` and `AsyncDecryptReader` produce and consume the
+//! same bytes as `EncryptWriter` and `DecryptReader` (see `stream`), so a
+//! stream sealed by one side opens with the other. Both readers default to
+//! the same `AuthPolicy`.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::error::SqepError;
+use crate::lite::ZeroshieldCipher;
+use crate::stream::{
+    invalid, open_sealed, other, AuthPolicy, ChunkCodec, DEFAULT_CHUNK_SIZE, HEADER_LEN,
+    MAX_CHUNK_SIZE,
+};
+
+/// `AsyncWrite` adapter that encrypts into a chunked stream.
+///
+/// A sealed chunk is written out before more plaintext is accepted, so a
+/// slow peer applies backpressure through `poll_write`. `poll_shutdown`
+/// (e.g. `AsyncWriteExt::shutdown`) seals the final chunk and MAC, then
+/// shuts the inner writer down; a stream that is never shut down fails to
+/// decrypt. Writes after shutdown fail with `ErrorKind::BrokenPipe`.
+pub struct AsyncEncryptWriter<W> {
+    inner: W,
+    codec: ChunkCodec,
+    chunk_size: usize,
+    /// Plaintext of the chunk being filled
+    buf: Vec<u8>,
+    /// Sealed bytes not yet accepted by `inner`
+    pending: Vec<u8>,
+    pending_pos: usize,
+    header_written: bool,
+    sealed_last: bool,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEncryptWriter<W> {
+    /// Start a stream over `inner` with `DEFAULT_CHUNK_SIZE` and no final MAC
+    pub fn new(cipher: &ZeroshieldCipher, inner: W) -> Result<Self, SqepError> {
+        Ok(Self {
+            inner,
+            codec: ChunkCodec::for_new_stream(cipher)?,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            buf: Vec::new(),
+            pending: Vec::new(),
+            pending_pos: 0,
+            header_written: false,
+            sealed_last: false,
+        })
+    }
+
+    /// Plaintext bytes per chunk (call before writing).
+    ///
+    /// # Panics
+    /// Panics if `chunk_size` is 0 or above `MAX_CHUNK_SIZE`.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(
+            (1..=MAX_CHUNK_SIZE).contains(&chunk_size),
+            "chunk_size must be between 1 and MAX_CHUNK_SIZE"
+        );
+        let final_mac = self.codec.has_final_mac();
+        self.set_header(chunk_size, final_mac);
+        self
+    }
+
+    /// Append a whole-stream HMAC after the last chunk (call before writing)
+    pub fn with_final_mac(mut self, enabled: bool) -> Self {
+        let chunk_size = self.chunk_size;
+        self.set_header(chunk_size, enabled);
+        self
+    }
+
+    /// The inner writer, e.g. once the stream has been shut down
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn set_header(&mut self, chunk_size: usize, final_mac: bool) {
+        assert!(
+            !self.header_written && self.buf.is_empty(),
+            "stream settings must be chosen before writing"
+        );
+        self.codec.configure(chunk_size, final_mac);
+        self.chunk_size = chunk_size;
+    }
+
+    /// Queue `plaintext` as the next sealed chunk (after the header, first time)
+    fn seal(&mut self, plaintext: &[u8], last: bool) -> io::Result<()> {
+        self.pending.clear();
+        self.pending_pos = 0;
+        if !self.header_written {
+            self.pending.extend_from_slice(&self.codec.header);
+            self.header_written = true;
+        }
+        let body = if last {
+            self.codec.seal_last(plaintext)
+        } else {
+            self.codec.seal(plaintext, false)
+        };
+        self.pending.extend_from_slice(&body.map_err(other)?);
+        Ok(())
+    }
+
+    /// Write out the queued sealed bytes, however many calls `inner` needs
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let rest = &self.pending[self.pending_pos..];
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, rest))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncEncryptWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.sealed_last {
+            let closed = io::Error::new(io::ErrorKind::BrokenPipe, "write after shutdown");
+            return Poll::Ready(Err(closed));
+        }
+        ready!(this.poll_drain(cx))?;
+
+        let take = data.len().min(this.chunk_size - this.buf.len());
+        this.buf.extend_from_slice(&data[..take]);
+
+        // A full buffer is never the final chunk: that one is sealed on shutdown
+        if this.buf.len() == this.chunk_size {
+            let chunk = std::mem::take(&mut this.buf);
+            this.seal(&chunk, false)?;
+            this.buf = chunk;
+            this.buf.clear();
+        }
+        Poll::Ready(Ok(take))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        if !this.sealed_last {
+            let last = std::mem::take(&mut this.buf);
+            this.seal(&last, true)?;
+            this.sealed_last = true;
+            ready!(this.poll_drain(cx))?;
+        }
+        ready!(Pin::new(&mut this.inner).poll_flush(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// `AsyncRead` adapter that decrypts a chunked stream.
+///
+/// As with `DecryptReader`, by default (`AuthPolicy::BufferThenAuth`)
+/// nothing is returned until the entire stream, final MAC included, has
+/// authenticated. `AuthPolicy::StreamUnverified` releases plaintext chunk
+/// by chunk as each one authenticates.
+pub struct AsyncDecryptReader<R> {
+    inner: R,
+    cipher: ZeroshieldCipher,
+    policy: AuthPolicy,
+    header: [u8; HEADER_LEN],
+    header_filled: usize,
+    codec: Option<ChunkCodec>,
+    lookahead: Vec<u8>,
+    /// Verified plaintext held back under `BufferThenAuth`
+    held: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    done: bool,
+    failed: Option<SqepError>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecryptReader<R> {
+    /// Decrypt the stream read from `inner`
+    pub fn new(cipher: &ZeroshieldCipher, inner: R) -> Self {
+        Self {
+            inner,
+            cipher: cipher.clone(),
+            policy: AuthPolicy::default(),
+            header: [0u8; HEADER_LEN],
+            header_filled: 0,
+            codec: None,
+            lookahead: Vec::new(),
+            held: Vec::new(),
+            out: Vec::new(),
+            out_pos: 0,
+            done: false,
+            failed: None,
+        }
+    }
+
+    /// Choose when plaintext is released (call before reading)
+    pub fn with_auth_policy(mut self, policy: AuthPolicy) -> Self {
+        assert!(self.header_filled == 0, "auth policy must be chosen before reading");
+        self.policy = policy;
+        self
+    }
+
+    /// Read and check the header on first use
+    fn poll_codec(&mut self, cx: &mut Context<'_>) -> Poll<Result<&mut ChunkCodec, SqepError>> {
+        while self.codec.is_none() {
+            let mut rb = ReadBuf::new(&mut self.header[self.header_filled..]);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut rb))?;
+            let n = rb.filled().len();
+            if n == 0 {
+                return Poll::Ready(Err(SqepError::CiphertextTooShort));
+            }
+            self.header_filled += n;
+            if self.header_filled == HEADER_LEN {
+                self.codec = Some(ChunkCodec::from_header(&self.cipher, self.header)?);
+            }
+        }
+        Poll::Ready(Ok(self.codec.as_mut().expect("codec initialised above")))
+    }
+
+    /// Fill the lookahead window and decrypt the next chunk into `out`, or
+    /// into `held` until the last one under `BufferThenAuth`
+    fn poll_next_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SqepError>> {
+        let window = ready!(self.poll_codec(cx))?.window();
+        while self.lookahead.len() < window {
+            let filled = self.lookahead.len();
+            self.lookahead.resize(window, 0);
+            let mut rb = ReadBuf::new(&mut self.lookahead[filled..]);
+            let polled = Pin::new(&mut self.inner).poll_read(cx, &mut rb);
+            let n = rb.filled().len();
+            self.lookahead.truncate(filled + n);
+            ready!(polled)?;
+            if n == 0 {
+                break;
+            }
+        }
+
+        let codec = self.codec.as_mut().expect("codec initialised above");
+        let chunk = codec.split_chunk(&mut self.lookahead)?;
+        let mut plaintext = open_sealed(&codec.cipher, &codec.key, &chunk)?;
+        self.done = chunk.last;
+        if self.policy == AuthPolicy::BufferThenAuth {
+            self.held.append(&mut plaintext);
+            if !self.done {
+                return Poll::Ready(Ok(()));
+            }
+            plaintext = std::mem::take(&mut self.held);
+        }
+        self.out = plaintext;
+        self.out_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecryptReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(e) = &this.failed {
+                return Poll::Ready(Err(invalid(e.clone())));
+            }
+            if this.out_pos < this.out.len() {
+                let n = buf.remaining().min(this.out.len() - this.out_pos);
+                buf.put_slice(&this.out[this.out_pos..this.out_pos + n]);
+                this.out_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            if this.done {
+                return Poll::Ready(Ok(()));
+            }
+            if let Err(e) = ready!(this.poll_next_chunk(cx)) {
+                this.failed = Some(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::stream::EncryptWriter;
+
+    #[tokio::test]
+    async fn duplex_roundtrip_with_backpressure() {
+        let cipher = ZeroshieldCipher::new();
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+
+        // A 64-byte pipe forces partial writes and reads throughout
+        let (tx, rx) = tokio::io::duplex(64);
+        let mut enc = AsyncEncryptWriter::new(&cipher, tx)
+            .expect("writer")
+            .with_chunk_size(1000)
+            .with_final_mac(true);
+        let mut dec = AsyncDecryptReader::new(&cipher, rx);
+        let write = async {
+            for piece in data.chunks(777) {
+                enc.write_all(piece).await.expect("write");
+            }
+            enc.shutdown().await.expect("shutdown");
+        };
+        let mut out = Vec::new();
+        let (_, read) = tokio::join!(write, dec.read_to_end(&mut out));
+        assert_eq!(read.expect("decrypt"), data.len());
+        assert_eq!(out, data);
+        let err = enc.write_all(b"late").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        // Same format as the blocking writer, and tampering is caught
+        let mut sync = EncryptWriter::new(&cipher, Vec::new()).expect("writer");
+        sync.write_all(&data[..5000]).expect("write");
+        let mut stream = sync.finish().expect("finish");
+        let mut out = Vec::new();
+        AsyncDecryptReader::new(&cipher, &stream[..]).read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, data[..5000]);
+
+        stream[HEADER_LEN + 3] ^= 1;
+        let err = AsyncDecryptReader::new(&cipher, &stream[..])
+            .read_to_end(&mut Vec::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn bad_final_mac_releases_nothing_by_default() {
+        let cipher = ZeroshieldCipher::new();
+        let data = vec![5u8; 1000];
+        let mut sync = EncryptWriter::new(&cipher, Vec::new())
+            .expect("writer")
+            .with_chunk_size(64)
+            .with_final_mac(true);
+        sync.write_all(&data).expect("write");
+        let mut stream = sync.finish().expect("finish");
+        *stream.last_mut().unwrap() ^= 1;
+
+        let mut buf = [0u8; 16];
+        let err = AsyncDecryptReader::new(&cipher, &stream[..]).read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut out = Vec::new();
+        let unverified = AsyncDecryptReader::new(&cipher, &stream[..])
+            .with_auth_policy(AuthPolicy::StreamUnverified)
+            .read_to_end(&mut out)
+            .await;
+        assert!(unverified.is_err());
+        assert!(!out.is_empty());
+        assert_eq!(out, data[..out.len()]);
+    }
+}
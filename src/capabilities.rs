@@ -0,0 +1,125 @@
+//! Capability exchange for agreeing on a protocol version and algorithm.
+//!
+//! ```text
+//! CAPS = "SQEP4.0-CAPS" (12) || N (1) || VERSION_0 .. VERSION_N-1
+//!        || M (1) || ALG_ID_0 .. ALG_ID_M-1
+//! ```
+//!
+//! Each side sends its `Capabilities` and calls `negotiate` on both sets.
+//! The choice depends only on the two sets, not on which side is local, so
+//! both ends agree without another round trip.
+
+use crate::algorithm::Algorithm;
+use crate::error::SqepError;
+
+const CAPS_MAGIC: &[u8] = b"SQEP4.0-CAPS";
+
+/// Protocol versions and AEADs one side of a session supports
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    /// Application protocol versions, in any order
+    pub versions: Vec<u8>,
+    /// AEADs this side can seal and open, in any order
+    pub algorithms: Vec<Algorithm>,
+}
+
+impl Capabilities {
+    /// Wire form for the handshake (see the module docs).
+    ///
+    /// # Panics
+    /// Panics if either list has more than 255 entries.
+    pub fn encode(&self) -> Vec<u8> {
+        assert!(
+            self.versions.len() <= 255 && self.algorithms.len() <= 255,
+            "at most 255 versions and 255 algorithms"
+        );
+        let mut out = CAPS_MAGIC.to_vec();
+        out.push(self.versions.len() as u8);
+        out.extend_from_slice(&self.versions);
+        out.push(self.algorithms.len() as u8);
+        out.extend(self.algorithms.iter().map(|a| a.id()));
+        out
+    }
+
+    /// Parse a peer's `encode` output (`InvalidHeader` if malformed).
+    ///
+    /// Algorithm ids this build doesn't know are skipped, so a newer peer's
+    /// extra algorithms simply don't take part in `negotiate`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, SqepError> {
+        let rest = bytes.strip_prefix(CAPS_MAGIC).ok_or(SqepError::InvalidHeader)?;
+        let (versions, rest) = split_list(rest)?;
+        let (ids, rest) = split_list(rest)?;
+        if !rest.is_empty() {
+            return Err(SqepError::InvalidHeader);
+        }
+        Ok(Self {
+            versions: versions.to_vec(),
+            algorithms: ids.iter().filter_map(|&id| Algorithm::from_id(id).ok()).collect(),
+        })
+    }
+}
+
+/// Highest version and highest-id algorithm both sides support, or `None`
+/// if they share no version or no algorithm. Symmetric in its arguments.
+pub fn negotiate(local: &Capabilities, remote: &Capabilities) -> Option<(u8, Algorithm)> {
+    let version = local.versions.iter().filter(|v| remote.versions.contains(v)).max()?;
+    let algorithm = local
+        .algorithms
+        .iter()
+        .filter(|a| remote.algorithms.contains(a))
+        .max_by_key(|a| a.id())?;
+    Some((*version, *algorithm))
+}
+
+/// Split a `COUNT (u8) || ITEMS` list off the front of `bytes`
+fn split_list(bytes: &[u8]) -> Result<(&[u8], &[u8]), SqepError> {
+    let (&count, rest) = bytes.split_first().ok_or(SqepError::InvalidHeader)?;
+    if rest.len() < count as usize {
+        return Err(SqepError::InvalidHeader);
+    }
+    Ok(rest.split_at(count as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(versions: &[u8]) -> Capabilities {
+        Capabilities {
+            versions: versions.to_vec(),
+            algorithms: vec![Algorithm::ChaCha20Poly1305],
+        }
+    }
+
+    #[test]
+    fn overlapping_sets_pick_the_highest_shared_version() {
+        let (old, new) = (caps(&[1, 2, 3]), caps(&[5, 3, 2, 4]));
+        assert_eq!(negotiate(&old, &new), Some((3, Algorithm::ChaCha20Poly1305)));
+        assert_eq!(negotiate(&new, &old), negotiate(&old, &new));
+
+        let wire = Capabilities::decode(&new.encode()).expect("decode");
+        assert_eq!(wire, new);
+        assert_eq!(negotiate(&old, &wire), Some((3, Algorithm::ChaCha20Poly1305)));
+    }
+
+    #[test]
+    fn identical_sets_agree_on_their_maximum() {
+        let both = caps(&[2, 7, 4]);
+        assert_eq!(negotiate(&both, &both.clone()), Some((7, Algorithm::ChaCha20Poly1305)));
+    }
+
+    #[test]
+    fn disjoint_sets_and_malformed_input_fail() {
+        assert_eq!(negotiate(&caps(&[1, 2]), &caps(&[3, 4])), None);
+        let no_algorithms = Capabilities { versions: vec![1], algorithms: vec![] };
+        assert_eq!(negotiate(&caps(&[1]), &no_algorithms), None);
+
+        // Unknown algorithm ids are dropped, truncation and trailing bytes are not
+        let mut wire = caps(&[1]).encode();
+        *wire.last_mut().unwrap() = 0xee;
+        assert_eq!(Capabilities::decode(&wire).unwrap(), no_algorithms);
+        assert_eq!(Capabilities::decode(&wire[..wire.len() - 1]), Err(SqepError::InvalidHeader));
+        wire.push(0);
+        assert_eq!(Capabilities::decode(&wire), Err(SqepError::InvalidHeader));
+    }
+}
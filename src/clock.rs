@@ -0,0 +1,32 @@
+//! Time source for `SealMeta` timestamps, expiry and token checks.
+//!
+//! Every cipher reads the time through a `Clock`, `SystemClock` unless
+//! replaced with `ZeroshieldCipher::with_clock`, so tests can pin the time
+//! and embedded users can plug in an RTC.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current UNIX time in seconds
+pub trait Clock: Send + Sync {
+    fn now_unix_secs(&self) -> u64;
+}
+
+/// The operating system's wall clock (the default)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_unix_secs(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+}
+
+/// A clock stopped at a fixed UNIX time, e.g. for tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now_unix_secs(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,74 @@
+//! A frame split into separately storable parts.
+//!
+//! `EncryptedComponents` is a regular frame without its header: the nonce,
+//! the masked ciphertext and the AEAD tag, e.g. for separate database
+//! columns. The header isn't stored, so it is rebuilt from the decrypting
+//! cipher's settings; both sides must agree on `Config`.
+
+use crate::error::SqepError;
+use crate::frame::{Frame, NONCE_LEN, TAG_LEN};
+use crate::lite::{random_nonce, ZeroshieldCipher};
+
+/// Nonce, ciphertext and tag of one sealed message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedComponents {
+    pub nonce: [u8; NONCE_LEN],
+    /// XOR-masked, AEAD-encrypted plaintext (same length as the plaintext)
+    pub ciphertext: Vec<u8>,
+    pub tag: [u8; TAG_LEN],
+}
+
+impl ZeroshieldCipher {
+    /// Encrypt `plaintext` like `encrypt_with_meta`, returning its parts
+    pub fn encrypt_components(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<EncryptedComponents, SqepError> {
+        let plaintext = plaintext.as_ref();
+        let frame = self.seal_compact_using(&self.aead_key()?, random_nonce(), plaintext, &[])?;
+        let parsed = Frame::decode(&frame)?;
+        let (ciphertext, tag) = parsed.split_tag();
+
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(parsed.nonce());
+        let mut tag_arr = [0u8; TAG_LEN];
+        tag_arr.copy_from_slice(tag);
+        Ok(EncryptedComponents {
+            nonce,
+            ciphertext: ciphertext.to_vec(),
+            tag: tag_arr,
+        })
+    }
+
+    /// Reassemble the frame behind `components` and decrypt it
+    pub fn decrypt_components(
+        &self,
+        components: &EncryptedComponents,
+    ) -> Result<Vec<u8>, SqepError> {
+        let mut body = [&components.ciphertext[..], &components.tag].concat();
+        self.config().tag_position.place(&mut body);
+        let frame = Frame::encode(&self.frame_header(), &components.nonce, &body);
+        self.open_frame(&frame, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components_roundtrip_and_reassemble_into_a_frame() {
+        let cipher = ZeroshieldCipher::new();
+        let parts = cipher.encrypt_components(b"stored in three columns").expect("encrypt");
+        assert_eq!(parts.ciphertext.len(), b"stored in three columns".len());
+        assert_eq!(cipher.decrypt_components(&parts).unwrap(), b"stored in three columns");
+
+        let body = [&parts.ciphertext[..], &parts.tag].concat();
+        let frame = Frame::encode(&cipher.frame_header(), &parts.nonce, &body);
+        assert_eq!(cipher.decrypt(&frame).unwrap(), b"stored in three columns");
+
+        let mut forged = parts.clone();
+        forged.tag[0] ^= 1;
+        assert_eq!(cipher.decrypt_components(&forged), Err(SqepError::DecryptionFailed));
+    }
+}
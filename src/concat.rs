@@ -0,0 +1,208 @@
+//! Length-prefixed frames for concatenated storage (e.g. append-only logs).
+//!
+//! A bare frame can't be located inside a byte stream, so concatenated
+//! frames are written as `LEN (u32 BE) || FRAME`, back to back.
+
+use std::io::{ErrorKind, Read};
+
+use crate::error::SqepError;
+use crate::frame::{read_len, write_len, LEN_SIZE};
+use crate::lite::ZeroshieldCipher;
+
+impl ZeroshieldCipher {
+    /// Encrypt into a `LEN || FRAME` record that can be appended to a log
+    pub fn encrypt_length_prefixed(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, SqepError> {
+        let plaintext = plaintext.as_ref();
+        let frame = self.seal_frame(plaintext, &[])?;
+        Ok([&write_len(frame.len())?[..], &frame].concat())
+    }
+
+    /// Decrypt the `LEN || FRAME` record at the front of `cursor` and
+    /// advance it past the record, leaving whatever follows for the caller.
+    ///
+    /// A frame that fails authentication still advances the cursor, since
+    /// its bounds are known; a truncated record leaves it untouched.
+    pub fn decrypt_from_cursor(&self, cursor: &mut &[u8]) -> Result<Vec<u8>, SqepError> {
+        self.decrypt(take_record(cursor)?)
+    }
+
+    /// `decrypt_from_cursor` for offset-based parsers: decrypt the
+    /// `LEN || FRAME` record at the front of `input` and return the bytes
+    /// it took up (prefix included), so parsing can continue at
+    /// `input[consumed..]`.
+    ///
+    /// Outer error: a truncated record (`CiphertextTooShort`), whose length
+    /// is unknown. Inner: decryption of a complete record, which still
+    /// reports `consumed` so a frame failing authentication can be skipped.
+    pub fn decrypt_consuming(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<(Result<Vec<u8>, SqepError>, usize), SqepError> {
+        let input = input.as_ref();
+        let mut cursor = input;
+        let frame = take_record(&mut cursor)?;
+        Ok((self.decrypt(frame), input.len() - cursor.len()))
+    }
+
+    /// Lazily decrypt back-to-back length-prefixed frames from `reader`.
+    ///
+    /// Iteration ends cleanly at EOF on a frame boundary. A frame that fails
+    /// authentication yields an error and iteration continues; a truncated
+    /// record or I/O failure yields an error and then stops.
+    pub fn decrypt_frames<'a, R: Read + 'a>(
+        &'a self,
+        reader: R,
+    ) -> impl Iterator<Item = Result<Vec<u8>, SqepError>> + 'a {
+        FrameIter {
+            cipher: self,
+            reader,
+            done: false,
+        }
+    }
+}
+
+/// Split the `LEN || FRAME` record off the front of `cursor`, returning
+/// the frame; a truncated record leaves the cursor untouched
+fn take_record<'a>(cursor: &mut &'a [u8]) -> Result<&'a [u8], SqepError> {
+    let len = read_len(cursor)?;
+    let record_len = len.saturating_add(LEN_SIZE);
+    let record = cursor.get(..record_len).ok_or(SqepError::CiphertextTooShort)?;
+    *cursor = &cursor[record.len()..];
+    Ok(&record[LEN_SIZE..])
+}
+
+struct FrameIter<'a, R> {
+    cipher: &'a ZeroshieldCipher,
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> FrameIter<'_, R> {
+    /// Outer error: framing/I-O failure. Inner: decryption of one frame.
+    fn next_frame(&mut self) -> Result<Option<Result<Vec<u8>, SqepError>>, SqepError> {
+        let mut len_bytes = [0u8; LEN_SIZE];
+        if !read_exact_or_eof(&mut self.reader, &mut len_bytes)? {
+            return Ok(None);
+        }
+        let len = read_len(&len_bytes)? as u64;
+
+        // Don't trust `len` for allocation: read at most that many bytes
+        let mut frame = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut frame).map_err(SqepError::from)?;
+        if (frame.len() as u64) < len {
+            return Err(SqepError::CiphertextTooShort);
+        }
+        Ok(Some(self.cipher.decrypt(&frame)))
+    }
+}
+
+impl<R: Read> Iterator for FrameIter<'_, R> {
+    type Item = Result<Vec<u8>, SqepError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_frame() {
+            // A frame that fails to open doesn't desynchronise the stream
+            Ok(Some(result)) => Some(result),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                // A broken length prefix does: stop after reporting it
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Fill `buf`; `Ok(false)` on a clean EOF before the first byte
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, SqepError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(SqepError::CiphertextTooShort),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterates_three_frames() {
+        let cipher = ZeroshieldCipher::new();
+        let mut log = Vec::new();
+        for msg in [&b"first"[..], b"", b"third entry"] {
+            log.extend(cipher.encrypt_length_prefixed(msg).expect("encrypt"));
+        }
+
+        let out: Vec<Vec<u8>> = cipher
+            .decrypt_frames(std::io::Cursor::new(log))
+            .collect::<Result<_, _>>()
+            .expect("decrypt");
+        assert_eq!(out, vec![b"first".to_vec(), Vec::new(), b"third entry".to_vec()]);
+    }
+
+    #[test]
+    fn cursor_reads_frames_in_turn() {
+        let cipher = ZeroshieldCipher::new();
+        let mut buf = cipher.encrypt_length_prefixed(b"first").expect("encrypt");
+        buf.extend(cipher.encrypt_length_prefixed(b"second").expect("encrypt"));
+        buf.extend_from_slice(b"\x01\x02rest");
+
+        let mut cursor = &buf[..];
+        assert_eq!(cipher.decrypt_from_cursor(&mut cursor).unwrap(), b"first");
+        assert_eq!(cipher.decrypt_from_cursor(&mut cursor).unwrap(), b"second");
+        assert_eq!(cursor, b"\x01\x02rest");
+
+        // Not enough bytes for the record the prefix announces
+        assert_eq!(cipher.decrypt_from_cursor(&mut cursor), Err(SqepError::CiphertextTooShort));
+        assert_eq!(cursor, b"\x01\x02rest");
+    }
+
+    #[test]
+    fn consumed_excludes_trailing_bytes() {
+        let cipher = ZeroshieldCipher::new();
+        let record = cipher.encrypt_length_prefixed(b"embedded").expect("encrypt");
+        let input = [&record[..], b"trailing"].concat();
+
+        let (plaintext, consumed) = cipher.decrypt_consuming(&input).expect("complete record");
+        assert_eq!((plaintext, consumed), (Ok(b"embedded".to_vec()), record.len()));
+        assert_eq!(&input[consumed..], b"trailing");
+
+        let partial = cipher.decrypt_consuming(&record[..record.len() - 1]);
+        assert_eq!(partial, Err(SqepError::CiphertextTooShort));
+
+        // A forged record is reported but can still be skipped
+        let mut forged = record.clone();
+        *forged.last_mut().unwrap() ^= 1;
+        let (plaintext, consumed) = cipher.decrypt_consuming(&forged).expect("complete record");
+        assert_eq!((plaintext, consumed), (Err(SqepError::DecryptionFailed), record.len()));
+    }
+
+    #[test]
+    fn truncated_log_reports_error() {
+        let cipher = ZeroshieldCipher::new();
+        let mut log = cipher.encrypt_length_prefixed(b"complete").expect("encrypt");
+        let second = cipher.encrypt_length_prefixed(b"cut short").expect("encrypt");
+        log.extend_from_slice(&second[..second.len() - 3]);
+
+        let mut frames = cipher.decrypt_frames(std::io::Cursor::new(log));
+        assert_eq!(frames.next(), Some(Ok(b"complete".to_vec())));
+        assert_eq!(frames.next(), Some(Err(SqepError::CiphertextTooShort)));
+        assert_eq!(frames.next(), None);
+    }
+}
@@ -0,0 +1,98 @@
+//! Cipher settings that shape new frames.
+
+use crate::algorithm::Algorithm;
+use crate::frame::{
+    TagPosition, EXT_MAGIC, HEADER_MAGIC, KDF_EXT_MAGIC, MASK_EXT_MAGIC, TAG_EXT_MAGIC,
+};
+use crate::kdf::{KdfHash, MaskParams, MaskStrength};
+use crate::tlv::FrameEncoding;
+
+/// Longest caller AAD accepted unless configured otherwise (64 KiB)
+pub const DEFAULT_MAX_AAD_LEN: usize = 64 * 1024;
+
+/// Settings of a `ZeroshieldCipher` other than its key.
+///
+/// Non-exhaustive, so new settings aren't breaking changes: start from
+/// `Config::default()` (or `cipher.config().clone()`) and set fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// AEAD of new frames, and the one extended frames must name to decrypt
+    pub algorithm: Algorithm,
+    /// HKDF hash deriving the XOR mask seed of new frames
+    pub kdf_hash: KdfHash,
+    /// Keystream generator of the XOR mask of new frames
+    pub mask_strength: MaskStrength,
+    /// Where the AEAD tag goes in the body of new frames
+    pub tag_position: TagPosition,
+    /// Byte layout of the `SQEP4.x-LITE` frames the cipher writes
+    pub frame_encoding: FrameEncoding,
+    /// Longest context AAD or authenticated trailer accepted, encrypting or
+    /// decrypting
+    pub max_aad_len: usize,
+    /// Shortest plaintext the `SealMeta`-returning methods accept
+    pub min_plaintext_len: usize,
+    /// Whether `SealMeta.hash` is computed (empty string when off)
+    pub hash_meta: bool,
+    /// Whether the plaintext length is appended to the AAD of every body
+    pub bind_length: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            kdf_hash: KdfHash::default(),
+            mask_strength: MaskStrength::default(),
+            tag_position: TagPosition::default(),
+            frame_encoding: FrameEncoding::default(),
+            max_aad_len: DEFAULT_MAX_AAD_LEN,
+            min_plaintext_len: 0,
+            hash_meta: true,
+            bind_length: false,
+        }
+    }
+}
+
+impl Config {
+    /// Magic of new frames and the id bytes after it (the first `n` of
+    /// `ALG || KDF || MASK || TAG_POS`): the oldest format that records
+    /// every non-default setting. The one place the header layout is chosen.
+    fn header_fields(&self) -> (&'static [u8], [u8; 4], usize) {
+        let ids = [
+            self.algorithm.id(),
+            self.kdf_hash.id(),
+            self.mask_strength.id(),
+            self.tag_position.id(),
+        ];
+        if self.tag_position != TagPosition::Append {
+            (TAG_EXT_MAGIC, ids, 4)
+        } else if self.mask_strength != MaskStrength::ChaCha20 {
+            (MASK_EXT_MAGIC, ids, 3)
+        } else if self.kdf_hash != KdfHash::Sha256 {
+            (KDF_EXT_MAGIC, ids, 2)
+        } else if self.algorithm != Algorithm::ChaCha20Poly1305 {
+            (EXT_MAGIC, ids, 1)
+        } else {
+            (HEADER_MAGIC, ids, 0)
+        }
+    }
+
+    /// Append the frame header of these settings to `out`
+    pub(crate) fn write_header(&self, out: &mut Vec<u8>) {
+        let (magic, ids, n) = self.header_fields();
+        out.extend_from_slice(magic);
+        out.extend_from_slice(&ids[..n]);
+    }
+
+    /// Length of the frame header written under these settings
+    pub(crate) fn header_len(&self) -> usize {
+        let (magic, _, n) = self.header_fields();
+        magic.len() + n
+    }
+
+    /// Seed hash and generator of the XOR mask of new frames
+    pub(crate) fn mask(&self) -> MaskParams {
+        MaskParams { hash: self.kdf_hash, strength: self.mask_strength }
+    }
+}
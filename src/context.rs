@@ -0,0 +1,236 @@
+//! Typed context binding via AAD.
+//!
+//! A context value (any `Serialize` type) is turned into a canonical byte
+//! string and used as the AEAD associated data. The encoding is
+//! type-tagged and length-prefixed, and map/struct keys are emitted in
+//! sorted order, so equal contexts always produce identical AAD and no two
+//! different contexts can collide.
+//!
+//! Encoding (`LEN`/`COUNT` are u32 big-endian):
+//!
+//! ```text
+//! null    0x00
+//! bool    0x01 || 0x00|0x01
+//! number  0x02 || LEN || decimal text
+//! string  0x03 || LEN || UTF-8 bytes
+//! array   0x04 || COUNT || item*
+//! object  0x05 || COUNT || (key string, value)*   (keys sorted)
+//! ```
+
+use serde::Serialize;
+use serde_json::Value;
+use zeroize::Zeroizing;
+
+use crate::error::SqepError;
+use crate::frame::write_len;
+use crate::lite::{SealMeta, ZeroshieldCipher};
+
+const CONTEXT_DOMAIN: &[u8] = b"SQEP:LITE:CTX:v1";
+
+impl ZeroshieldCipher {
+    /// Encrypt with `ctx` canonically serialized into the AAD
+    pub fn encrypt_with_context<C: Serialize>(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        ctx: &C,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let aad = context_aad(ctx)?;
+        self.check_aad_len(&aad)?;
+        self.seal_frame_with_meta(plaintext, &aad)
+    }
+
+    /// Decrypt a frame sealed by `encrypt_with_context`; `ctx` must be equal
+    /// to the context used at encryption time.
+    pub fn decrypt_with_context<C: Serialize>(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        ctx: &C,
+    ) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let aad = context_aad(ctx)?;
+        self.check_aad_len(&aad)?;
+        self.open_frame(ciphertext, &aad)
+    }
+
+    /// Move a frame sealed by `encrypt_with_context` from `old_ctx` to
+    /// `new_ctx` when the context changes but the payload doesn't.
+    ///
+    /// The tag covers the AAD and takes the key to recompute, so this is
+    /// effectively re-encryption: the frame is opened under `old_ctx`
+    /// (`DecryptionFailed` if that isn't its context) and sealed again
+    /// under `new_ctx` with a fresh nonce. The plaintext is in memory
+    /// meanwhile and zeroized before returning.
+    pub fn reauthenticate<C: Serialize, D: Serialize>(
+        &self,
+        frame: impl AsRef<[u8]>,
+        old_ctx: &C,
+        new_ctx: &D,
+    ) -> Result<Vec<u8>, SqepError> {
+        let frame = frame.as_ref();
+        let (old_aad, new_aad) = (context_aad(old_ctx)?, context_aad(new_ctx)?);
+        self.check_aad_len(&old_aad)?;
+        self.check_aad_len(&new_aad)?;
+        let plaintext = Zeroizing::new(self.open_frame(frame, &old_aad)?);
+        self.seal_frame(&plaintext, &new_aad)
+    }
+}
+
+/// AAD = DOMAIN || canonical(ctx)
+fn context_aad<C: Serialize>(ctx: &C) -> Result<Vec<u8>, SqepError> {
+    let value = serde_json::to_value(ctx).map_err(|e| SqepError::Serialization(e.to_string()))?;
+    let mut out = CONTEXT_DOMAIN.to_vec();
+    encode_canonical(&value, &mut out)?;
+    Ok(out)
+}
+
+/// `FrameTooLarge` if a string or collection is over `u32::MAX` long
+fn encode_canonical(value: &Value, out: &mut Vec<u8>) -> Result<(), SqepError> {
+    match value {
+        Value::Null => out.push(0x00),
+        Value::Bool(b) => {
+            out.push(0x01);
+            out.push(u8::from(*b));
+        }
+        Value::Number(n) => {
+            out.push(0x02);
+            push_bytes(n.to_string().as_bytes(), out)?;
+        }
+        Value::String(s) => {
+            out.push(0x03);
+            push_bytes(s.as_bytes(), out)?;
+        }
+        Value::Array(items) => {
+            out.push(0x04);
+            push_count(items.len(), out)?;
+            for item in items {
+                encode_canonical(item, out)?;
+            }
+        }
+        Value::Object(map) => {
+            out.push(0x05);
+            push_count(map.len(), out)?;
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, item) in entries {
+                out.push(0x03);
+                push_bytes(key.as_bytes(), out)?;
+                encode_canonical(item, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn push_count(count: usize, out: &mut Vec<u8>) -> Result<(), SqepError> {
+    out.extend_from_slice(&write_len(count)?);
+    Ok(())
+}
+
+fn push_bytes(bytes: &[u8], out: &mut Vec<u8>) -> Result<(), SqepError> {
+    push_count(bytes.len(), out)?;
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Ctx {
+        user_id: u64,
+        purpose: String,
+    }
+
+    #[test]
+    fn context_roundtrip() {
+        let cipher = ZeroshieldCipher::new();
+        let ctx = Ctx {
+            user_id: 42,
+            purpose: "session".into(),
+        };
+
+        let (ct, _m) = cipher.encrypt_with_context(b"secret", &ctx).expect("encrypt");
+        assert_eq!(cipher.decrypt_with_context(&ct, &ctx).expect("decrypt"), b"secret");
+        // Without the context the frame doesn't open
+        assert_eq!(cipher.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn differing_context_field_fails() {
+        let cipher = ZeroshieldCipher::new();
+        let ctx = Ctx {
+            user_id: 42,
+            purpose: "session".into(),
+        };
+        let (ct, _m) = cipher.encrypt_with_context(b"secret", &ctx).expect("encrypt");
+
+        let other_user = Ctx {
+            user_id: 43,
+            purpose: "session".into(),
+        };
+        let other_purpose = Ctx {
+            user_id: 42,
+            purpose: "export".into(),
+        };
+        assert_eq!(cipher.decrypt_with_context(&ct, &other_user), Err(SqepError::DecryptionFailed));
+        assert_eq!(
+            cipher.decrypt_with_context(&ct, &other_purpose),
+            Err(SqepError::DecryptionFailed)
+        );
+    }
+
+    #[test]
+    fn reauthenticated_frame_opens_only_under_the_new_context() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, _m) = cipher.encrypt_with_context(b"payload", &"owner:alice").expect("encrypt");
+
+        let moved = cipher.reauthenticate(&ct, &"owner:alice", &"owner:bob").expect("rewrap");
+        assert_ne!(moved[..], ct[..]);
+        assert_eq!(cipher.decrypt_with_context(&moved, &"owner:bob").unwrap(), b"payload");
+        let err = cipher.decrypt_with_context(&moved, &"owner:alice");
+        assert_eq!(err, Err(SqepError::DecryptionFailed));
+
+        // The old context is checked before anything is re-sealed
+        let err = cipher.reauthenticate(&ct, &"owner:mallory", &"owner:bob");
+        assert_eq!(err, Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn aad_limit_applies_at_encrypt_and_decrypt() {
+        // A string context adds a 5-byte type/length prefix after the domain
+        let overhead = CONTEXT_DOMAIN.len() + 5;
+        let cipher = ZeroshieldCipher::new().with_max_aad_len(100);
+        let at_limit = "x".repeat(100 - overhead);
+        let over = "x".repeat(101 - overhead);
+
+        let (ct, _m) = cipher.encrypt_with_context(b"secret", &at_limit).expect("encrypt");
+        assert_eq!(cipher.decrypt_with_context(&ct, &at_limit).unwrap(), b"secret");
+        let too_large = SqepError::AadTooLarge { len: 101, max: 100 };
+        assert_eq!(cipher.encrypt_with_context(b"secret", &over).unwrap_err(), too_large);
+        assert_eq!(cipher.decrypt_with_context(&ct, &over), Err(too_large));
+
+        let strict = cipher.clone().with_max_aad_len(99);
+        let err = strict.decrypt_with_context(&ct, &at_limit).unwrap_err();
+        assert_eq!(err, SqepError::AadTooLarge { len: 100, max: 99 });
+    }
+
+    #[test]
+    fn encoding_is_unambiguous() {
+        // ["ab", "c"] and ["a", "bc"] must not produce the same AAD
+        let a = context_aad(&vec!["ab", "c"]).expect("aad");
+        let b = context_aad(&vec!["a", "bc"]).expect("aad");
+        assert_ne!(a, b);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn oversized_lengths_are_an_error() {
+        let mut out = Vec::new();
+        let too_many = u32::MAX as usize + 1;
+        assert_eq!(push_count(too_many, &mut out), Err(SqepError::FrameTooLarge));
+        assert!(out.is_empty());
+    }
+}
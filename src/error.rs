@@ -0,0 +1,255 @@
+//! Error type shared by all SQEP Lite operations.
+
+use std::fmt;
+
+use crate::algorithm::Algorithm;
+use crate::frame::FormatVersion;
+
+/// Errors returned by `ZeroshieldCipher` operations.
+///
+/// New variants may appear in minor releases, so matches need a `_` arm:
+///
+/// ```
+/// use sqep_lite::{SqepError, ZeroshieldCipher};
+///
+/// let cipher = ZeroshieldCipher::new();
+/// let (mut ct, _meta) = cipher.encrypt_with_meta(b"hello").unwrap();
+/// *ct.last_mut().unwrap() ^= 1;
+///
+/// let outcome = match cipher.decrypt(&ct) {
+///     Ok(_) => "opened",
+///     Err(e) if e.is_auth_failure() => "tampered or wrong key",
+///     Err(SqepError::CiphertextTooShort | SqepError::InvalidHeader) => "not a frame",
+///     Err(_) => "other error",
+/// };
+/// assert_eq!(outcome, "tampered or wrong key");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SqepError {
+    /// Input is shorter than the smallest valid frame
+    CiphertextTooShort,
+    /// Frame does not start with a known magic header
+    InvalidHeader,
+    /// AEAD key could not be constructed
+    AeadInit,
+    /// AEAD sealing failed
+    SealFailed,
+    /// Frame names an algorithm id this build doesn't support
+    UnsupportedAlgorithm(u8),
+    /// Frame records `found`, but the cipher is configured for `expected`
+    AlgorithmMismatch { expected: Algorithm, found: Algorithm },
+    /// Nonce bytes could not be parsed
+    InvalidNonce,
+    /// AEAD authentication failed (wrong key, tampered data or AAD)
+    DecryptionFailed,
+    /// None of the candidate keys authenticated the frame
+    NoKeyMatched,
+    /// Frame names a key id the keyring doesn't hold
+    UnknownKeyId { key_id: u32 },
+    /// Frame expired at `expires_at` (UNIX seconds)
+    Expired { expires_at: u64 },
+    /// Decrypted bytes are not valid UTF-8
+    InvalidUtf8,
+    /// Frame is followed by `extra` unexpected bytes
+    TrailingBytes { extra: usize },
+    /// Decrypted output would exceed the caller's `max` bytes
+    OutputTooLarge { max: usize },
+    /// Frame is too large for its `u32` length prefix
+    FrameTooLarge,
+    /// Underlying I/O failure
+    Io(String),
+    /// Trailer length prefix points outside the frame
+    InvalidTrailerLength,
+    /// No fragments were supplied to `reassemble`
+    NoFragments,
+    /// Fragment at `index` is missing, reordered or from another message
+    FragmentInvalid { index: usize },
+    /// A value could not be serialized or deserialized
+    Serialization(String),
+    /// Plaintext does not fit in a fixed-size record
+    RecordTooLarge { max: usize },
+    /// Sealed record does not have the configured size
+    RecordSizeMismatch { expected: usize, found: usize },
+    /// Input to `encrypt_checked` already starts with a frame magic
+    LikelyDoubleEncrypt,
+    /// Input is not valid (URL-safe) base64
+    InvalidBase64,
+    /// Stream has more chunks than its 32-bit counter allows
+    StreamTooLong,
+    /// Input is neither a frame nor a base64-encoded frame
+    UnrecognizedInput,
+    /// Frame header is bound via AAD, so it can't be rewritten in place;
+    /// `ZeroshieldCipher::rekey` re-encrypts the frame instead
+    HeaderAuthenticated,
+    /// Output buffer is shorter than the `needed` bytes
+    BufferTooSmall { needed: usize },
+    /// Input file is `size` bytes, over the `max` accepted
+    FileTooLarge { size: u64, max: u64 },
+    /// AAD is `len` bytes, over the configured `max`
+    AadTooLarge { len: usize, max: usize },
+    /// Plaintext is `len` bytes, under the configured `min`
+    PlaintextTooShort { len: usize, min: usize },
+    /// `Keyring::add_key` found `key_id` taken, or its fingerprint already
+    /// used, by the key under `existing_id`
+    FingerprintCollision { key_id: u32, existing_id: u32 },
+    /// The operation was cancelled through its cancel flag
+    Cancelled,
+    /// Input given as a key is an SQEP frame (raw or base64)
+    NotAKey,
+    /// Decoded key is `len` bytes instead of 32
+    InvalidKeyLength { len: usize },
+    /// The system RNG failed to produce key material
+    RngUnavailable,
+    /// Frame magic names format `found` (major, minor), newer than the
+    /// `max_supported` this build reads
+    UnsupportedVersion { found: (u8, u8), max_supported: FormatVersion },
+}
+
+impl SqepError {
+    /// True if the data failed authentication: tampered, truncated inside
+    /// the body, or sealed under another key or AAD
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(self, SqepError::DecryptionFailed | SqepError::NoKeyMatched)
+    }
+
+    /// Variant name, used as the `error` label on failure metrics
+    #[cfg(feature = "metrics")]
+    pub(crate) fn variant_name(&self) -> &'static str {
+        match self {
+            SqepError::CiphertextTooShort => "CiphertextTooShort",
+            SqepError::InvalidHeader => "InvalidHeader",
+            SqepError::AeadInit => "AeadInit",
+            SqepError::SealFailed => "SealFailed",
+            SqepError::UnsupportedAlgorithm(_) => "UnsupportedAlgorithm",
+            SqepError::AlgorithmMismatch { .. } => "AlgorithmMismatch",
+            SqepError::InvalidNonce => "InvalidNonce",
+            SqepError::DecryptionFailed => "DecryptionFailed",
+            SqepError::NoKeyMatched => "NoKeyMatched",
+            SqepError::UnknownKeyId { .. } => "UnknownKeyId",
+            SqepError::Expired { .. } => "Expired",
+            SqepError::InvalidUtf8 => "InvalidUtf8",
+            SqepError::TrailingBytes { .. } => "TrailingBytes",
+            SqepError::OutputTooLarge { .. } => "OutputTooLarge",
+            SqepError::FrameTooLarge => "FrameTooLarge",
+            SqepError::Io(_) => "Io",
+            SqepError::InvalidTrailerLength => "InvalidTrailerLength",
+            SqepError::NoFragments => "NoFragments",
+            SqepError::FragmentInvalid { .. } => "FragmentInvalid",
+            SqepError::Serialization(_) => "Serialization",
+            SqepError::RecordTooLarge { .. } => "RecordTooLarge",
+            SqepError::RecordSizeMismatch { .. } => "RecordSizeMismatch",
+            SqepError::LikelyDoubleEncrypt => "LikelyDoubleEncrypt",
+            SqepError::InvalidBase64 => "InvalidBase64",
+            SqepError::StreamTooLong => "StreamTooLong",
+            SqepError::UnrecognizedInput => "UnrecognizedInput",
+            SqepError::HeaderAuthenticated => "HeaderAuthenticated",
+            SqepError::BufferTooSmall { .. } => "BufferTooSmall",
+            SqepError::FileTooLarge { .. } => "FileTooLarge",
+            SqepError::AadTooLarge { .. } => "AadTooLarge",
+            SqepError::PlaintextTooShort { .. } => "PlaintextTooShort",
+            SqepError::FingerprintCollision { .. } => "FingerprintCollision",
+            SqepError::Cancelled => "Cancelled",
+            SqepError::NotAKey => "NotAKey",
+            SqepError::InvalidKeyLength { .. } => "InvalidKeyLength",
+            SqepError::RngUnavailable => "RngUnavailable",
+            SqepError::UnsupportedVersion { .. } => "UnsupportedVersion",
+        }
+    }
+}
+
+impl fmt::Display for SqepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqepError::CiphertextTooShort => f.write_str("Ciphertext too short"),
+            SqepError::InvalidHeader => f.write_str("Invalid header"),
+            SqepError::AeadInit => f.write_str("AEAD key setup failed"),
+            SqepError::SealFailed => f.write_str("Encryption failed"),
+            SqepError::UnsupportedAlgorithm(id) => {
+                write!(f, "Unsupported algorithm id 0x{:02x}", id)
+            }
+            SqepError::AlgorithmMismatch { expected, found } => {
+                write!(f, "Frame uses {:?}, but the cipher is configured for {:?}", found, expected)
+            }
+            SqepError::InvalidNonce => f.write_str("Nonce error"),
+            SqepError::DecryptionFailed => f.write_str("Decryption failed"),
+            SqepError::NoKeyMatched => f.write_str("No candidate key matched"),
+            SqepError::UnknownKeyId { key_id } => {
+                write!(f, "No key with id {} in the keyring", key_id)
+            }
+            SqepError::Expired { expires_at } => write!(f, "Frame expired at {}", expires_at),
+            SqepError::InvalidUtf8 => f.write_str("UTF-8 error"),
+            SqepError::TrailingBytes { extra } => {
+                write!(f, "{} unexpected trailing bytes after frame", extra)
+            }
+            SqepError::OutputTooLarge { max } => {
+                write!(f, "Plaintext would exceed the {} byte limit", max)
+            }
+            SqepError::FrameTooLarge => f.write_str("Frame too large for length prefix"),
+            SqepError::Io(msg) => write!(f, "I/O error: {}", msg),
+            SqepError::InvalidTrailerLength => f.write_str("Invalid trailer length"),
+            SqepError::NoFragments => f.write_str("No fragments supplied"),
+            SqepError::FragmentInvalid { index } => {
+                write!(f, "Fragment {} is missing, reordered or invalid", index)
+            }
+            SqepError::Serialization(msg) => write!(f, "Serialization error: {}", msg),
+            SqepError::RecordTooLarge { max } => {
+                write!(f, "Plaintext exceeds the {} byte record size", max)
+            }
+            SqepError::RecordSizeMismatch { expected, found } => {
+                write!(f, "Record is {} bytes, expected {}", found, expected)
+            }
+            SqepError::LikelyDoubleEncrypt => f.write_str("Input already looks like an SQEP frame"),
+            SqepError::InvalidBase64 => f.write_str("Invalid base64"),
+            SqepError::StreamTooLong => f.write_str("Stream exceeds the chunk counter"),
+            SqepError::HeaderAuthenticated => {
+                f.write_str("Frame header is authenticated; use rekey instead")
+            }
+            SqepError::BufferTooSmall { needed } => {
+                write!(f, "Output buffer too small, {} bytes needed", needed)
+            }
+            SqepError::FileTooLarge { size, max } => {
+                write!(f, "File is {} bytes, over the {} byte limit", size, max)
+            }
+            SqepError::UnrecognizedInput => {
+                f.write_str("Input is neither a frame nor a base64-encoded frame")
+            }
+            SqepError::AadTooLarge { len, max } => {
+                write!(f, "AAD is {} bytes, over the {} byte limit", len, max)
+            }
+            SqepError::PlaintextTooShort { len, min } => {
+                write!(f, "Plaintext is {} bytes, under the {} byte minimum", len, min)
+            }
+            SqepError::FingerprintCollision { key_id, existing_id } => write!(
+                f,
+                "Key id {} collides with key {} (same id or fingerprint)",
+                key_id, existing_id
+            ),
+            SqepError::Cancelled => f.write_str("Operation cancelled"),
+            SqepError::NotAKey => {
+                f.write_str("Input is an encrypted frame, not a key; check the key file path")
+            }
+            SqepError::InvalidKeyLength { len } => {
+                write!(f, "Key is {} bytes, expected 32", len)
+            }
+            SqepError::RngUnavailable => f.write_str("System random number generator unavailable"),
+            SqepError::UnsupportedVersion { found: (major, minor), max_supported } => {
+                let (max_major, max_minor) = max_supported.number();
+                write!(
+                    f,
+                    "Frame format {}.{} is newer than this build reads (up to {}.{}); \
+                     upgrade sqep-lite",
+                    major, minor, max_major, max_minor
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SqepError {}
+
+impl From<std::io::Error> for SqepError {
+    fn from(e: std::io::Error) -> Self {
+        SqepError::Io(e.to_string())
+    }
+}
@@ -0,0 +1,117 @@
+//! Fragmentation of one message into several standalone frames.
+//!
+//! Each fragment is a regular `SQEP4.0-LITE` frame whose AAD binds a
+//! message id (the nonce of fragment 0), the fragment index and the total
+//! fragment count. Missing, reordered or foreign fragments fail to open.
+
+use crate::error::SqepError;
+use crate::frame::{write_len, Frame, NONCE_LEN};
+use crate::lite::{random_nonce, ZeroshieldCipher};
+use crate::tlv::compact_frame;
+
+const FRAG_DOMAIN: &[u8] = b"SQEP:LITE:FRAG:v1";
+
+impl ZeroshieldCipher {
+    /// Split `plaintext` into standalone frames of at most `max_frame` bytes.
+    ///
+    /// Always returns at least one fragment (empty plaintext yields one
+    /// empty fragment). Feed the frames, in order, to `reassemble`.
+    /// `BufferTooSmall` if `max_frame <= self.frame_overhead()`, since no
+    /// payload would fit, and `FrameTooLarge` past `u32::MAX` fragments.
+    pub fn encrypt_fragmented(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        max_frame: usize,
+    ) -> Result<Vec<Vec<u8>>, SqepError> {
+        let plaintext = plaintext.as_ref();
+        let overhead = self.frame_overhead();
+        if max_frame <= overhead {
+            return Err(SqepError::BufferTooSmall { needed: overhead + 1 });
+        }
+        let payload = max_frame - overhead;
+
+        let chunks: Vec<&[u8]> = if plaintext.is_empty() {
+            vec![plaintext]
+        } else {
+            plaintext.chunks(payload).collect()
+        };
+        let total = chunks.len();
+
+        // Fragment 0's nonce doubles as the message id for all fragments
+        let message_id = random_nonce();
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let nonce = if index == 0 { message_id } else { random_nonce() };
+                let aad = fragment_aad(&message_id, index, total)?;
+                self.seal_frame_with_nonce(nonce, chunk, &aad)
+            })
+            .collect()
+    }
+
+    /// Verify and join frames produced by `encrypt_fragmented`.
+    ///
+    /// Frames must be complete and in their original order.
+    pub fn reassemble(&self, frames: &[Vec<u8>]) -> Result<Vec<u8>, SqepError> {
+        let first = frames.first().ok_or(SqepError::NoFragments)?;
+        let mut message_id = [0u8; NONCE_LEN];
+        message_id.copy_from_slice(Frame::decode(&compact_frame(first)?)?.nonce());
+
+        let mut out = Vec::new();
+        for (index, frame) in frames.iter().enumerate() {
+            let aad = fragment_aad(&message_id, index, frames.len())?;
+            let part = self.open_frame(frame, &aad).map_err(|e| match e {
+                SqepError::DecryptionFailed => SqepError::FragmentInvalid { index },
+                other => other,
+            })?;
+            out.extend_from_slice(&part);
+        }
+        Ok(out)
+    }
+}
+
+/// AAD = DOMAIN || MESSAGE_ID || INDEX (u32 BE) || TOTAL (u32 BE)
+fn fragment_aad(
+    message_id: &[u8; NONCE_LEN],
+    index: usize,
+    total: usize,
+) -> Result<Vec<u8>, SqepError> {
+    let (index, total) = (write_len(index)?, write_len(total)?);
+    Ok([FRAG_DOMAIN, message_id, &index, &total].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragments_roundtrip() {
+        let cipher = ZeroshieldCipher::new();
+        let msg: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+
+        let frames = cipher.encrypt_fragmented(&msg, 100).expect("encrypt");
+        assert_eq!(frames.len(), 17);
+        assert!(frames.iter().all(|f| f.len() <= 100));
+        assert_eq!(cipher.reassemble(&frames).expect("reassemble"), msg);
+
+        let overhead = cipher.frame_overhead();
+        let too_small = cipher.encrypt_fragmented(&msg, overhead);
+        assert_eq!(too_small, Err(SqepError::BufferTooSmall { needed: overhead + 1 }));
+    }
+
+    #[test]
+    fn missing_or_reordered_fragments_fail() {
+        let cipher = ZeroshieldCipher::new();
+        let frames = cipher.encrypt_fragmented([7u8; 300], 140).expect("encrypt");
+        assert_eq!(frames.len(), 3);
+
+        let missing = vec![frames[0].clone(), frames[1].clone()];
+        assert_eq!(cipher.reassemble(&missing), Err(SqepError::FragmentInvalid { index: 0 }));
+
+        let reordered = vec![frames[0].clone(), frames[2].clone(), frames[1].clone()];
+        assert_eq!(cipher.reassemble(&reordered), Err(SqepError::FragmentInvalid { index: 1 }));
+
+        assert_eq!(cipher.reassemble(&[]), Err(SqepError::NoFragments));
+    }
+}
@@ -0,0 +1,659 @@
+//! Wire format of a single SQEP Lite frame
+//!
+//! ```text
+//! SQEP4.0-LITE (12) || NONCE (12) || CIPHERTEXT+TAG (>= 16)
+//! SQEP4.0-LCAS (12) || NONCE (12) || CIPHERTEXT+TAG (>= 16)
+//! SQEP4.1-LITE (12) || ALG (1)    || NONCE (12) || CIPHERTEXT+TAG (>= 16)
+//! SQEP4.2-LITE (12) || ALG (1)    || KDF (1)    || NONCE (12) || CIPHERTEXT+TAG (>= 16)
+//! SQEP4.3-LITE (12) || ALG || KDF || MASK (1)   || NONCE (12) || CIPHERTEXT+TAG (>= 16)
+//! SQEP4.4-LITE (12) || ALG || KDF || MASK || TAG_POS (1) || NONCE (12) || BODY (>= 16)
+//! SQEP3.9      (7)  || NONCE (12) || CIPHERTEXT+TAG (>= 16)   legacy, read-only
+//! ```
+//!
+//! Every length or count serialized by the crate (length-prefixed frames,
+//! trailers, fragment indices, record lengths, context encodings) is a
+//! fixed-width 4-byte big-endian (network order) `u32`, written and read
+//! with [`write_len`] / [`read_len`].
+//!
+//! Everything here is pure byte handling; sealing and opening live in
+//! [`crate::lite`].
+
+use crate::algorithm::Algorithm;
+use crate::error::SqepError;
+use std::borrow::Cow;
+
+use crate::kdf::{KdfHash, MaskParams, MaskStrength};
+
+/// Length of every frame magic
+pub const MAGIC_LEN: usize = 12;
+/// Length of the AEAD nonce following the header
+pub const NONCE_LEN: usize = 12;
+/// Length of the AEAD tag closing the body
+pub const TAG_LEN: usize = 16;
+/// Length of the algorithm id byte of extended frames
+pub const ALG_LEN: usize = 1;
+/// Length of the mask KDF hash id byte of `SQEP4.2-LITE` frames
+pub const KDF_ID_LEN: usize = 1;
+/// Length of the mask strength id byte of `SQEP4.3-LITE` frames
+pub const MASK_ID_LEN: usize = 1;
+/// Length of the tag position id byte of `SQEP4.4-LITE` frames
+pub const TAG_POS_LEN: usize = 1;
+/// Width of every serialized length or count
+pub const LEN_SIZE: usize = 4;
+
+/// Magic of classic frames (ChaCha20-Poly1305, random nonce)
+pub const HEADER_MAGIC: &[u8] = b"SQEP4.0-LITE";
+/// Magic of extended frames, followed by the algorithm id byte
+pub const EXT_MAGIC: &[u8] = b"SQEP4.1-LITE";
+/// Magic of frames recording the mask KDF hash, after the algorithm id byte
+pub const KDF_EXT_MAGIC: &[u8] = b"SQEP4.2-LITE";
+/// Magic of frames recording the mask strength, after the KDF hash id byte
+pub const MASK_EXT_MAGIC: &[u8] = b"SQEP4.3-LITE";
+/// Magic of frames recording the tag position, after the mask strength byte
+pub const TAG_EXT_MAGIC: &[u8] = b"SQEP4.4-LITE";
+/// Magic of content-addressed frames (deterministic nonce)
+pub const CAS_MAGIC: &[u8] = b"SQEP4.0-LCAS";
+/// Magic of frames carrying an authenticated expiry
+pub const EXPIRY_MAGIC: &[u8] = b"SQEP4.0-LEXP";
+/// Magic of frames naming their key's fingerprint
+pub const FINGERPRINT_MAGIC: &[u8] = b"SQEP4.0-LKFP";
+/// Length of the key fingerprint in `SQEP4.0-LKFP` frames (as `fingerprint()`)
+pub const FINGERPRINT_LEN: usize = 6;
+/// Magic of frames carrying an authenticated deduplication tag
+pub const DEDUP_MAGIC: &[u8] = b"SQEP4.0-LDUP";
+/// Length of a deduplication tag (see `ZeroshieldCipher::dedup_tag`)
+pub const DEDUP_TAG_LEN: usize = 32;
+/// Magic of file frames carrying the source's authenticated mtime
+pub const FILE_META_MAGIC: &[u8] = b"SQEP4.0-LFMT";
+/// Magic of legacy frames (`SQEP3.9 || NONCE || CIPHERTEXT+TAG`, no XOR mask)
+pub const LEGACY_MAGIC: &[u8] = b"SQEP3.9";
+/// Magic of key-id envelopes (see [`crate::keyring`])
+pub const KEY_ID_MAGIC: &[u8] = b"SQEP4.0-LKID";
+/// Magic of chunked streams (see [`crate::stream`])
+pub const STREAM_MAGIC: &[u8] = b"SQEP4.0-STRM";
+/// Magic of a TLV-encoded frame (see [`crate::tlv`])
+pub const TLV_MAGIC: &[u8] = b"SQEP4.0-LTLV";
+
+/// Longest header `Frame::decode` accepts (`SQEP4.0-LKFP` and its fingerprint)
+pub(crate) const MAX_HEADER_LEN: usize = MAGIC_LEN + FINGERPRINT_LEN;
+const _: () =
+    assert!(MAX_HEADER_LEN >= MAGIC_LEN + ALG_LEN + KDF_ID_LEN + MASK_ID_LEN + TAG_POS_LEN);
+
+/// AAD prefix binding the content-addressed mode
+pub(crate) const CAS_DOMAIN: &[u8] = b"SQEP:LITE:CAS:v1";
+
+/// Generation of the frame format, as told by its magic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FormatVersion {
+    /// `SQEP3.9`: ChaCha20-Poly1305 only, no XOR mask. Read-only.
+    V3_9,
+    /// `SQEP4.0-*`: keyed XOR mask under ChaCha20-Poly1305
+    V4_0,
+    /// `SQEP4.1-LITE`: 4.0 plus an authenticated algorithm byte
+    V4_1,
+    /// `SQEP4.2-LITE`: 4.1 plus an authenticated mask KDF hash byte
+    V4_2,
+    /// `SQEP4.3-LITE`: 4.2 plus an authenticated mask strength byte
+    V4_3,
+    /// `SQEP4.4-LITE`: 4.3 plus an authenticated tag position byte
+    V4_4,
+}
+
+/// Every format version this build reads, oldest first; the last one is
+/// the newest it writes
+pub const SUPPORTED_VERSIONS: &[FormatVersion] = &[
+    FormatVersion::V3_9,
+    FormatVersion::V4_0,
+    FormatVersion::V4_1,
+    FormatVersion::V4_2,
+    FormatVersion::V4_3,
+    FormatVersion::V4_4,
+];
+
+impl FormatVersion {
+    /// `(major, minor)` as written in the magic, e.g. `(4, 4)` for `SQEP4.4`
+    pub fn number(self) -> (u8, u8) {
+        match self {
+            FormatVersion::V3_9 => (3, 9),
+            FormatVersion::V4_0 => (4, 0),
+            FormatVersion::V4_1 => (4, 1),
+            FormatVersion::V4_2 => (4, 2),
+            FormatVersion::V4_3 => (4, 3),
+            FormatVersion::V4_4 => (4, 4),
+        }
+    }
+
+    /// Newest version this build reads
+    pub fn latest() -> Self {
+        *SUPPORTED_VERSIONS.last().expect("at least one supported version")
+    }
+}
+
+/// Version `(major, minor)` of a `SQEP<major>.<minor>-` magic newer than
+/// [`FormatVersion::latest`], i.e. a frame from a later crate release
+fn newer_version(bytes: &[u8]) -> Option<(u8, u8)> {
+    let magic = bytes.get(..MAGIC_LEN)?.strip_prefix(b"SQEP")?;
+    let dash = magic.iter().position(|&b| b == b'-')?;
+    let (major, minor) = std::str::from_utf8(&magic[..dash]).ok()?.split_once('.')?;
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(major) || !digits(minor) {
+        return None;
+    }
+    let found = (major.parse().ok()?, minor.parse().ok()?);
+    (found > FormatVersion::latest().number()).then_some(found)
+}
+
+/// Where the AEAD tag sits in a frame's body.
+///
+/// `Append` (`CIPHERTEXT || TAG`) is the layout of every other frame and
+/// stream chunk. `Prepend` (`TAG || CIPHERTEXT`) matches formats that put
+/// the tag first; it is recorded in a `SQEP4.4-LITE` header, so `decrypt`
+/// follows the frame. The bytes sealed are the same either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TagPosition {
+    /// Tag after the ciphertext (default)
+    #[default]
+    Append,
+    /// Tag before the ciphertext
+    Prepend,
+}
+
+impl TagPosition {
+    /// Id byte recorded in `SQEP4.4-LITE` frames
+    pub fn id(self) -> u8 {
+        match self {
+            TagPosition::Append => 0x01,
+            TagPosition::Prepend => 0x02,
+        }
+    }
+
+    /// Look up a position by frame id; unknown ids return `InvalidHeader`
+    pub fn from_id(id: u8) -> Result<Self, SqepError> {
+        match id {
+            0x01 => Ok(TagPosition::Append),
+            0x02 => Ok(TagPosition::Prepend),
+            _ => Err(SqepError::InvalidHeader),
+        }
+    }
+
+    /// Reorder a `CIPHERTEXT || TAG` body into this layout
+    pub(crate) fn place(self, body: &mut [u8]) {
+        if self == TagPosition::Prepend {
+            body.rotate_right(TAG_LEN);
+        }
+    }
+}
+
+/// Public attributes of a frame for indexing, readable without the key.
+///
+/// Everything here is already visible in the frame's bytes. No frame format
+/// records when it was sealed; that timestamp travels out of band in
+/// `SealMeta`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexAttributes {
+    pub version: FormatVersion,
+    pub algorithm: Algorithm,
+    pub kdf_hash: KdfHash,
+    /// Sealed by `encrypt_content_addressed` (`SQEP4.0-LCAS`)
+    pub content_addressed: bool,
+    /// Bit length of the plaintext length: 0 for empty plaintext, else `b`
+    /// for `2^(b-1) <= len < 2^b`
+    pub size_bucket: u8,
+}
+
+/// Borrowed view of a parsed frame
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    version: FormatVersion,
+    algorithm: Algorithm,
+    kdf_hash: KdfHash,
+    mask_strength: MaskStrength,
+    tag_position: TagPosition,
+    aad_prefix: &'a [u8],
+    header: &'a [u8],
+    nonce: &'a [u8],
+    body: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    /// Assemble `HEADER || NONCE || BODY`
+    pub fn encode(header: &[u8], nonce: &[u8; NONCE_LEN], body: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(header.len() + NONCE_LEN + body.len());
+        frame.extend_from_slice(header);
+        frame.extend_from_slice(nonce);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    /// Split a `SQEP4.0-LITE`, `SQEP4.0-LCAS`, `SQEP4.0-LKFP`,
+    /// `SQEP4.1-LITE` to `SQEP4.4-LITE` or legacy `SQEP3.9` frame into parts.
+    ///
+    /// A magic naming a version past [`FormatVersion::latest`] fails with
+    /// `UnsupportedVersion`, any other unknown magic with `InvalidHeader`.
+    pub fn decode(bytes: &'a [u8]) -> Result<Self, SqepError> {
+        let chacha = Algorithm::ChaCha20Poly1305;
+        let mut kdf_hash = KdfHash::Sha256;
+        let mut mask_strength = MaskStrength::ChaCha20;
+        let mut tag_position = TagPosition::Append;
+        let (version, header_len, algorithm, aad_prefix): (_, usize, _, &[u8]) =
+            if bytes.starts_with(LEGACY_MAGIC) {
+                (FormatVersion::V3_9, LEGACY_MAGIC.len(), chacha, &[])
+            } else if bytes.len() < MAGIC_LEN {
+                return Err(SqepError::CiphertextTooShort);
+            } else if bytes.starts_with(HEADER_MAGIC) {
+                (FormatVersion::V4_0, MAGIC_LEN, chacha, &[])
+            } else if bytes.starts_with(CAS_MAGIC) {
+                // Content-addressed frames bind their mode via AAD
+                (FormatVersion::V4_0, MAGIC_LEN, chacha, CAS_DOMAIN)
+            } else if bytes.starts_with(FINGERPRINT_MAGIC) {
+                // The fingerprint is authenticated with the magic
+                let header_len = MAGIC_LEN + FINGERPRINT_LEN;
+                let header = bytes.get(..header_len).ok_or(SqepError::CiphertextTooShort)?;
+                (FormatVersion::V4_0, header_len, chacha, header)
+            } else if bytes.starts_with(EXT_MAGIC) {
+                // Extended frames authenticate the whole header
+                let header_len = MAGIC_LEN + ALG_LEN;
+                let id = *bytes.get(MAGIC_LEN).ok_or(SqepError::CiphertextTooShort)?;
+                (FormatVersion::V4_1, header_len, Algorithm::from_id(id)?, &bytes[..header_len])
+            } else if bytes.starts_with(KDF_EXT_MAGIC) {
+                let header_len = MAGIC_LEN + ALG_LEN + KDF_ID_LEN;
+                let ids = bytes.get(MAGIC_LEN..header_len).ok_or(SqepError::CiphertextTooShort)?;
+                kdf_hash = KdfHash::from_id(ids[1])?;
+                (FormatVersion::V4_2, header_len, Algorithm::from_id(ids[0])?, &bytes[..header_len])
+            } else if bytes.starts_with(MASK_EXT_MAGIC) {
+                let header_len = MAGIC_LEN + ALG_LEN + KDF_ID_LEN + MASK_ID_LEN;
+                let ids = bytes.get(MAGIC_LEN..header_len).ok_or(SqepError::CiphertextTooShort)?;
+                kdf_hash = KdfHash::from_id(ids[1])?;
+                mask_strength = MaskStrength::from_id(ids[2])?;
+                (FormatVersion::V4_3, header_len, Algorithm::from_id(ids[0])?, &bytes[..header_len])
+            } else if bytes.starts_with(TAG_EXT_MAGIC) {
+                let header_len = MAGIC_LEN + ALG_LEN + KDF_ID_LEN + MASK_ID_LEN + TAG_POS_LEN;
+                let ids = bytes.get(MAGIC_LEN..header_len).ok_or(SqepError::CiphertextTooShort)?;
+                kdf_hash = KdfHash::from_id(ids[1])?;
+                mask_strength = MaskStrength::from_id(ids[2])?;
+                tag_position = TagPosition::from_id(ids[3])?;
+                (FormatVersion::V4_4, header_len, Algorithm::from_id(ids[0])?, &bytes[..header_len])
+            } else if let Some(found) = newer_version(bytes) {
+                let max_supported = FormatVersion::latest();
+                return Err(SqepError::UnsupportedVersion { found, max_supported });
+            } else {
+                return Err(SqepError::InvalidHeader);
+            };
+
+        // The body must at least hold the AEAD tag (empty plaintext)
+        if bytes.len() < header_len + NONCE_LEN + TAG_LEN {
+            return Err(SqepError::CiphertextTooShort);
+        }
+
+        let (header, rest) = bytes.split_at(header_len);
+        let (nonce, body) = rest.split_at(NONCE_LEN);
+        Ok(Self {
+            version,
+            algorithm,
+            kdf_hash,
+            mask_strength,
+            tag_position,
+            aad_prefix,
+            header,
+            nonce,
+            body,
+        })
+    }
+
+    /// Format generation of this frame
+    pub fn version(&self) -> FormatVersion {
+        self.version
+    }
+
+    /// AEAD the body was sealed with
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// HKDF hash that derived the XOR mask seed
+    pub fn kdf_hash(&self) -> KdfHash {
+        self.kdf_hash
+    }
+
+    /// Keystream generator of the XOR mask
+    pub fn mask_strength(&self) -> MaskStrength {
+        self.mask_strength
+    }
+
+    /// Where the AEAD tag sits in the body
+    pub fn tag_position(&self) -> TagPosition {
+        self.tag_position
+    }
+
+    /// Seed hash and generator of the XOR mask
+    pub(crate) fn mask(&self) -> MaskParams {
+        MaskParams { hash: self.kdf_hash, strength: self.mask_strength }
+    }
+
+    /// Magic, plus the id bytes for extended frames
+    pub fn header(&self) -> &'a [u8] {
+        self.header
+    }
+
+    /// Key fingerprint named by a `SQEP4.0-LKFP` frame, `None` for others
+    pub fn fingerprint(&self) -> Option<&'a [u8]> {
+        self.header.strip_prefix(FINGERPRINT_MAGIC)
+    }
+
+    /// The frame's nonce
+    pub fn nonce(&self) -> &'a [u8] {
+        self.nonce
+    }
+
+    /// `CIPHERTEXT+TAG`, or `TAG+CIPHERTEXT` for `TagPosition::Prepend`
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// The AEAD tag, the last (or, prepended, the first) `TAG_LEN` bytes
+    /// of the body, without decrypting (e.g. to store or audit it
+    /// separately).
+    ///
+    /// `decode` already refuses frames too short to hold a tag, so this
+    /// only fails with `CiphertextTooShort` for a body that lost its tag.
+    pub fn tag(&self) -> Result<[u8; TAG_LEN], SqepError> {
+        if self.body.len() < TAG_LEN {
+            return Err(SqepError::CiphertextTooShort);
+        }
+        let mut tag = [0u8; TAG_LEN];
+        tag.copy_from_slice(self.split_tag().1);
+        Ok(tag)
+    }
+
+    /// `(CIPHERTEXT, TAG)` of a body holding at least a tag
+    pub(crate) fn split_tag(&self) -> (&'a [u8], &'a [u8]) {
+        match self.tag_position {
+            TagPosition::Append => self.body.split_at(self.body.len() - TAG_LEN),
+            TagPosition::Prepend => {
+                let (tag, ciphertext) = self.body.split_at(TAG_LEN);
+                (ciphertext, tag)
+            }
+        }
+    }
+
+    /// The body as `CIPHERTEXT+TAG`, the layout the AEAD opens
+    pub(crate) fn sealed_body(&self) -> Cow<'a, [u8]> {
+        match self.tag_position {
+            TagPosition::Append => Cow::Borrowed(self.body),
+            TagPosition::Prepend => {
+                let (ciphertext, tag) = self.split_tag();
+                Cow::Owned([ciphertext, tag].concat())
+            }
+        }
+    }
+
+    /// Total encoded length
+    pub fn len(&self) -> usize {
+        self.header.len() + NONCE_LEN + self.body.len()
+    }
+
+    /// Plaintext length, which the frame length gives away anyway: the
+    /// body minus the tag, and minus the 16 padding bytes of legacy frames
+    pub fn plaintext_len(&self) -> usize {
+        let padding = if self.version == FormatVersion::V3_9 { TAG_LEN } else { 0 };
+        self.body.len().saturating_sub(TAG_LEN + padding)
+    }
+
+    /// Attributes to index this frame by, taken from the header and length
+    pub fn index_attributes(&self) -> IndexAttributes {
+        IndexAttributes {
+            version: self.version,
+            algorithm: self.algorithm,
+            kdf_hash: self.kdf_hash,
+            content_addressed: self.header == CAS_MAGIC,
+            size_bucket: (usize::BITS - self.plaintext_len().leading_zeros()) as u8,
+        }
+    }
+
+    /// Never true: a decoded frame always holds a header, nonce and tag
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Copy of this frame under `new_magic`, with nonce and body untouched.
+    ///
+    /// Only `SQEP4.0-LITE` and legacy `SQEP3.9` frames qualify: their AAD
+    /// doesn't cover the magic, so it can change without breaking the tag.
+    /// Every other frame binds its header and returns `HeaderAuthenticated`;
+    /// move those with `ZeroshieldCipher::rekey` instead, which needs the key.
+    ///
+    /// The body is not re-sealed, so `new_magic` must name the same
+    /// construction to whatever reads the result (e.g. a store-specific
+    /// label). It must be as long as the current magic and may not be a
+    /// different SQEP magic, which would have the body read under another
+    /// construction; both return `InvalidHeader`.
+    pub fn rewrap_magic(&self, new_magic: &[u8]) -> Result<Vec<u8>, SqepError> {
+        let unbound = matches!(self.version, FormatVersion::V3_9 | FormatVersion::V4_0)
+            && self.aad_prefix.is_empty();
+        if !unbound {
+            return Err(SqepError::HeaderAuthenticated);
+        }
+        let other_sqep = looks_like_frame(new_magic) && new_magic != self.header;
+        if new_magic.len() != self.header.len() || other_sqep {
+            return Err(SqepError::InvalidHeader);
+        }
+        Ok([new_magic, self.nonce, self.body].concat())
+    }
+
+    /// Header-derived AAD that precedes any caller AAD
+    pub(crate) fn aad_prefix(&self) -> &'a [u8] {
+        self.aad_prefix
+    }
+}
+
+/// True if `bytes` starts with one of the frame magics above.
+///
+/// A cheap prefix check meant to catch accidental double encryption; it
+/// says nothing about whether the frame is valid or authentic.
+pub fn looks_like_frame(bytes: &[u8]) -> bool {
+    [
+        HEADER_MAGIC,
+        EXT_MAGIC,
+        KDF_EXT_MAGIC,
+        MASK_EXT_MAGIC,
+        TAG_EXT_MAGIC,
+        CAS_MAGIC,
+        FINGERPRINT_MAGIC,
+        EXPIRY_MAGIC,
+        DEDUP_MAGIC,
+        FILE_META_MAGIC,
+        STREAM_MAGIC,
+        KEY_ID_MAGIC,
+        TLV_MAGIC,
+        LEGACY_MAGIC,
+    ]
+    .iter()
+    .any(|magic| bytes.starts_with(magic))
+}
+
+/// Deduplication tag of a `SQEP4.0-LDUP` frame, readable without the key.
+///
+/// `None` for any other frame. The tag is only checked by
+/// `decrypt_dedup_tagged`; an index built from tags of untrusted frames
+/// should treat them as hints until the frame has been opened.
+pub fn dedup_tag_of(bytes: &[u8]) -> Option<[u8; DEDUP_TAG_LEN]> {
+    let tag = bytes.strip_prefix(DEDUP_MAGIC)?.get(..DEDUP_TAG_LEN)?;
+    Some(tag.try_into().expect("slice of DEDUP_TAG_LEN"))
+}
+
+/// Encode `len` as a big-endian `u32`; `FrameTooLarge` above `u32::MAX`
+pub fn write_len(len: usize) -> Result<[u8; LEN_SIZE], SqepError> {
+    let len = u32::try_from(len).map_err(|_| SqepError::FrameTooLarge)?;
+    Ok(len.to_be_bytes())
+}
+
+/// Decode the big-endian `u32` length at the start of `bytes`
+pub fn read_len(bytes: &[u8]) -> Result<usize, SqepError> {
+    let mut len_bytes = [0u8; LEN_SIZE];
+    len_bytes.copy_from_slice(bytes.get(..LEN_SIZE).ok_or(SqepError::CiphertextTooShort)?);
+    Ok(u32::from_be_bytes(len_bytes) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let nonce = [7u8; NONCE_LEN];
+        let body = [0xAB; TAG_LEN + 5];
+        let bytes = Frame::encode(HEADER_MAGIC, &nonce, &body);
+
+        let frame = Frame::decode(&bytes).expect("decode");
+        assert_eq!(frame.header(), HEADER_MAGIC);
+        assert_eq!(frame.nonce(), &nonce);
+        assert_eq!(frame.body(), &body);
+        assert_eq!(frame.len(), bytes.len());
+        assert_eq!(frame.algorithm(), Algorithm::ChaCha20Poly1305);
+        assert!(frame.aad_prefix().is_empty());
+
+        assert_eq!(frame.version(), FormatVersion::V4_0);
+
+        let cas = Frame::encode(CAS_MAGIC, &nonce, &body);
+        assert_eq!(Frame::decode(&cas).unwrap().aad_prefix(), CAS_DOMAIN);
+
+        let legacy = Frame::encode(LEGACY_MAGIC, &nonce, &body);
+        let frame = Frame::decode(&legacy).expect("decode legacy");
+        assert_eq!(frame.version(), FormatVersion::V3_9);
+        assert_eq!(frame.nonce(), &nonce);
+        assert_eq!(frame.body(), &body);
+    }
+
+    #[test]
+    fn tag_is_the_end_of_the_body() {
+        let mut body = vec![0xAB; 5];
+        body.extend_from_slice(&[0xCD; TAG_LEN]);
+        let bytes = Frame::encode(HEADER_MAGIC, &[7u8; NONCE_LEN], &body);
+        assert_eq!(Frame::decode(&bytes).unwrap().tag().unwrap(), [0xCD; TAG_LEN]);
+
+        // A sealed frame ends in its tag; flipping a tag bit breaks it
+        let cipher = crate::lite::ZeroshieldCipher::new();
+        let (mut ct, _m) = cipher.encrypt_with_meta(b"detached").expect("encrypt");
+        let tag = Frame::decode(&ct).unwrap().tag().unwrap();
+        assert_eq!(tag, ct[ct.len() - TAG_LEN..]);
+        let last = ct.len() - 1;
+        ct[last] ^= 1;
+        assert_eq!(cipher.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn extended_header_carries_the_algorithm() {
+        let header = [EXT_MAGIC, &[Algorithm::ChaCha20Poly1305.id()]].concat();
+        let bytes = Frame::encode(&header, &[1u8; NONCE_LEN], &[0u8; TAG_LEN]);
+
+        let frame = Frame::decode(&bytes).expect("decode");
+        assert_eq!(frame.header(), &header[..]);
+        assert_eq!(frame.aad_prefix(), &header[..]);
+
+        let unknown = Frame::encode(&[EXT_MAGIC, &[0xEE]].concat(), &[1u8; NONCE_LEN], &[0u8; 16]);
+        assert_eq!(Frame::decode(&unknown).unwrap_err(), SqepError::UnsupportedAlgorithm(0xEE));
+
+        let header = [KDF_EXT_MAGIC, &[0x01, KdfHash::Sha512.id()]].concat();
+        let bytes = Frame::encode(&header, &[1u8; NONCE_LEN], &[0u8; TAG_LEN]);
+        let frame = Frame::decode(&bytes).expect("decode");
+        assert_eq!(frame.version(), FormatVersion::V4_2);
+        assert_eq!(frame.kdf_hash(), KdfHash::Sha512);
+        assert_eq!(frame.aad_prefix(), &header[..]);
+    }
+
+    #[test]
+    fn index_attributes_of_a_fresh_frame() {
+        use crate::lite::ZeroshieldCipher;
+
+        let cipher = ZeroshieldCipher::new().with_kdf_hash(KdfHash::Sha384);
+        let (ct, _m) = cipher.encrypt_with_meta([0u8; 100]).expect("encrypt");
+        assert_eq!(
+            Frame::decode(&ct).unwrap().index_attributes(),
+            IndexAttributes {
+                version: FormatVersion::V4_2,
+                algorithm: Algorithm::ChaCha20Poly1305,
+                kdf_hash: KdfHash::Sha384,
+                content_addressed: false,
+                size_bucket: 7,
+            }
+        );
+
+        let (cas, _m) = ZeroshieldCipher::new().encrypt_content_addressed(b"").expect("encrypt");
+        let attrs = Frame::decode(&cas).unwrap().index_attributes();
+        assert!(attrs.content_addressed);
+        assert_eq!((attrs.version, attrs.size_bucket), (FormatVersion::V4_0, 0));
+    }
+
+    #[test]
+    fn looks_like_frame_checks_the_magic() {
+        assert!(!looks_like_frame(b"hello world, not a frame"));
+        assert!(!looks_like_frame(b"SQEP"));
+        assert!(looks_like_frame(&Frame::encode(HEADER_MAGIC, &[0u8; NONCE_LEN], &[])));
+        assert!(looks_like_frame(EXPIRY_MAGIC));
+    }
+
+    #[test]
+    fn lengths_are_big_endian_u32() {
+        assert_eq!(write_len(0x0102_0304).unwrap(), [0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(write_len(300).unwrap(), [0x00, 0x00, 0x01, 0x2C]);
+        assert_eq!(read_len(&[0x00, 0x00, 0x01, 0x2C, 0xFF]).unwrap(), 300);
+        assert_eq!(read_len(&[0x01, 0x02]).unwrap_err(), SqepError::CiphertextTooShort);
+
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(write_len(1 << 32).unwrap_err(), SqepError::FrameTooLarge);
+    }
+
+    #[test]
+    fn rewrap_magic_only_touches_unauthenticated_headers() {
+        let body = [0x42u8; TAG_LEN + 3];
+        let bytes = Frame::encode(HEADER_MAGIC, &[5u8; NONCE_LEN], &body);
+        let relabelled = Frame::decode(&bytes).unwrap().rewrap_magic(b"ACME4.0-LITE").unwrap();
+        assert_eq!(&relabelled[..MAGIC_LEN], b"ACME4.0-LITE");
+        assert_eq!(relabelled[MAGIC_LEN..], bytes[MAGIC_LEN..]);
+
+        let frame = Frame::decode(&bytes).unwrap();
+        assert_eq!(frame.rewrap_magic(b"SHORT").unwrap_err(), SqepError::InvalidHeader);
+        assert_eq!(frame.rewrap_magic(CAS_MAGIC).unwrap_err(), SqepError::InvalidHeader);
+
+        let header = [EXT_MAGIC, &[Algorithm::ChaCha20Poly1305.id()]].concat();
+        let extended = Frame::encode(&header, &[5u8; NONCE_LEN], &body);
+        let err = Frame::decode(&extended).unwrap().rewrap_magic(b"ACME4.1-LITE\x01");
+        assert_eq!(err.unwrap_err(), SqepError::HeaderAuthenticated);
+        let cas = Frame::encode(CAS_MAGIC, &[5u8; NONCE_LEN], &body);
+        let err = Frame::decode(&cas).unwrap().rewrap_magic(b"ACME4.0-LCAS");
+        assert_eq!(err.unwrap_err(), SqepError::HeaderAuthenticated);
+    }
+
+    #[test]
+    fn decode_rejects_bad_input() {
+        assert_eq!(Frame::decode(b"SQEP").unwrap_err(), SqepError::CiphertextTooShort);
+        assert_eq!(Frame::decode(&[0u8; 64]).unwrap_err(), SqepError::InvalidHeader);
+
+        let expiry = Frame::encode(EXPIRY_MAGIC, &[0u8; NONCE_LEN], &[0u8; TAG_LEN]);
+        assert_eq!(Frame::decode(&expiry).unwrap_err(), SqepError::InvalidHeader);
+
+        let no_tag = Frame::encode(HEADER_MAGIC, &[0u8; NONCE_LEN], &[0u8; TAG_LEN - 1]);
+        assert_eq!(Frame::decode(&no_tag).unwrap_err(), SqepError::CiphertextTooShort);
+    }
+
+    #[test]
+    fn frames_from_a_newer_release_ask_for_an_upgrade() {
+        use crate::lite::ZeroshieldCipher;
+
+        let cipher = ZeroshieldCipher::new();
+        let (frame, _m) = cipher.encrypt_with_meta(b"from the future").expect("encrypt");
+        assert_eq!(FormatVersion::latest().number(), (4, 4));
+
+        for (magic, found) in [(b"SQEP4.5-LITE", (4, 5)), (b"SQEP5.0-LITE", (5, 0))] {
+            let future = [&magic[..], &frame[MAGIC_LEN..]].concat();
+            let err = cipher.decrypt(&future).unwrap_err();
+            let max_supported = FormatVersion::V4_4;
+            assert_eq!(err, SqepError::UnsupportedVersion { found, max_supported });
+            assert!(err.to_string().contains("upgrade sqep-lite"));
+        }
+
+        // Unknown magics of a known version are still just bad headers
+        let unknown = [&b"SQEP4.4-LXYZ"[..], &frame[MAGIC_LEN..]].concat();
+        assert_eq!(cipher.decrypt(&unknown), Err(SqepError::InvalidHeader));
+    }
+}
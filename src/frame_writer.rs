@@ -0,0 +1,144 @@
+//! Step-by-step frame assembly in memory.
+//!
+//! `FrameWriter` builds the same `HEADER || NONCE || CIPHERTEXT+TAG` frame
+//! as `encrypt_with_meta`, one part at a time. The parts must be pushed in
+//! wire order; doing otherwise is a programming error and panics. With
+//! `FrameEncoding::Tlv` the frame is re-encoded once `append_sealed` has
+//! completed it.
+
+use crate::error::SqepError;
+use crate::frame::NONCE_LEN;
+use crate::lite::{header_aad, random_nonce, SealMeta, ZeroshieldCipher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Empty,
+    Header,
+    Nonce,
+    Sealed,
+}
+
+/// Incremental builder for a single frame
+pub struct FrameWriter<'a> {
+    cipher: &'a ZeroshieldCipher,
+    frame: Vec<u8>,
+    header: Vec<u8>,
+    nonce: [u8; NONCE_LEN],
+    meta: Option<SealMeta>,
+    stage: Stage,
+}
+
+impl<'a> FrameWriter<'a> {
+    /// Start an empty frame for `cipher`
+    pub fn new(cipher: &'a ZeroshieldCipher) -> Self {
+        Self {
+            cipher,
+            frame: Vec::new(),
+            header: Vec::new(),
+            nonce: [0u8; NONCE_LEN],
+            meta: None,
+            stage: Stage::Empty,
+        }
+    }
+
+    /// Write the magic (plus algorithm byte for extended frames).
+    ///
+    /// # Panics
+    /// Panics unless this is the first call on the writer.
+    pub fn push_header(&mut self) -> &mut Self {
+        self.advance(Stage::Empty, Stage::Header, "push_header");
+        self.header = self.cipher.frame_header();
+        self.frame.extend_from_slice(&self.header);
+        self
+    }
+
+    /// Write a fresh random nonce.
+    ///
+    /// # Panics
+    /// Panics unless `push_header` was the previous call.
+    pub fn push_nonce(&mut self) -> &mut Self {
+        self.advance(Stage::Header, Stage::Nonce, "push_nonce");
+        self.nonce = random_nonce();
+        self.frame.extend_from_slice(&self.nonce);
+        self
+    }
+
+    /// Encrypt `plaintext` under the pushed header and nonce and append
+    /// `CIPHERTEXT+TAG`. On error the writer is left as it was, so `finish`
+    /// still panics rather than return a frame without a body.
+    ///
+    /// # Panics
+    /// Panics unless `push_nonce` was the previous call.
+    pub fn append_sealed(&mut self, plaintext: impl AsRef<[u8]>) -> Result<&mut Self, SqepError> {
+        let plaintext = plaintext.as_ref();
+        self.expect_stage(Stage::Nonce, "append_sealed");
+        let aad = header_aad(&self.header, &[]);
+        let key = self.cipher.aead_key()?;
+        let mask = self.cipher.config().mask();
+        let mut body = self.cipher.seal_body_using(&key, mask, &self.nonce, plaintext, &aad)?;
+        self.cipher.config().tag_position.place(&mut body);
+        let frame = [&self.frame[..], &body].concat();
+        let frame = self.cipher.config().frame_encoding.encode(frame)?;
+        let meta = self.cipher.frame_meta(plaintext.len(), &frame, self.cipher.clock())?;
+
+        // Only a fully sealed frame moves the writer on
+        self.frame = frame;
+        self.meta = Some(meta);
+        self.stage = Stage::Sealed;
+        Ok(self)
+    }
+
+    /// Finish into the frame bytes and their metadata.
+    ///
+    /// # Panics
+    /// Panics unless `append_sealed` succeeded.
+    pub fn finish(self) -> (Vec<u8>, SealMeta) {
+        assert_eq!(self.stage, Stage::Sealed, "FrameWriter::finish called before append_sealed");
+        let meta = self.meta.expect("append_sealed builds the metadata");
+        (self.frame, meta)
+    }
+
+    fn advance(&mut self, expected: Stage, next: Stage, step: &str) {
+        self.expect_stage(expected, step);
+        self.stage = next;
+    }
+
+    fn expect_stage(&self, expected: Stage, step: &str) {
+        assert_eq!(self.stage, expected, "FrameWriter::{} called out of order", step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn writer_frame_decrypts_like_encrypt_with_meta() {
+        let cipher = ZeroshieldCipher::new();
+        let mut writer = FrameWriter::new(&cipher);
+        writer.push_header().push_nonce().append_sealed(b"piecemeal").expect("seal");
+        let (frame, meta) = writer.finish();
+
+        let (reference, _m) = cipher.encrypt_with_meta(b"piecemeal").expect("encrypt");
+        assert_eq!(frame.len(), reference.len());
+        assert_eq!(cipher.decrypt(&frame).unwrap(), cipher.decrypt(&reference).unwrap());
+        assert_eq!(meta.hash, format!("{:x}", Sha256::digest(&frame)));
+    }
+
+    #[test]
+    #[should_panic(expected = "finish called before append_sealed")]
+    fn failed_seal_does_not_finish_a_frame() {
+        let cipher = ZeroshieldCipher::new().with_min_plaintext_len(1);
+        let mut writer = FrameWriter::new(&cipher);
+        assert!(writer.push_header().push_nonce().append_sealed(b"").is_err());
+        let _ = writer.finish();
+    }
+
+    #[test]
+    #[should_panic(expected = "push_nonce called out of order")]
+    fn nonce_before_header_panics() {
+        let cipher = ZeroshieldCipher::new();
+        FrameWriter::new(&cipher).push_nonce();
+    }
+}
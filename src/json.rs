@@ -0,0 +1,63 @@
+//! Typed JSON convenience: encrypt a `Serialize` value, decrypt into a
+//! `DeserializeOwned` one.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::SqepError;
+use crate::lite::{SealMeta, ZeroshieldCipher};
+
+impl ZeroshieldCipher {
+    /// Serialize `value` to JSON and encrypt it
+    pub fn encrypt_json<T: Serialize>(&self, value: &T) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let json = serde_json::to_vec(value).map_err(|e| SqepError::Serialization(e.to_string()))?;
+        self.encrypt_with_meta(&json)
+    }
+
+    /// Decrypt a frame and deserialize its JSON plaintext into `T`
+    pub fn decrypt_json<T: DeserializeOwned>(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+    ) -> Result<T, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let json = self.decrypt(ciphertext)?;
+        serde_json::from_slice(&json).map_err(|e| SqepError::Serialization(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        endpoint: String,
+        retries: u32,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let cipher = ZeroshieldCipher::new();
+        let config = Config {
+            endpoint: "https://example.invalid".into(),
+            retries: 3,
+            tags: vec!["prod".into()],
+        };
+
+        let (ct, _m) = cipher.encrypt_json(&config).expect("encrypt");
+        let back: Config = cipher.decrypt_json(&ct).expect("decrypt");
+        assert_eq!(back, config);
+    }
+
+    #[test]
+    fn wrong_shape_is_a_serialization_error() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, _m) = cipher.encrypt_with_meta(b"not json").expect("encrypt");
+        assert!(matches!(
+            cipher.decrypt_json::<Config>(&ct),
+            Err(SqepError::Serialization(_))
+        ));
+    }
+}
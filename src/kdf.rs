@@ -0,0 +1,298 @@
+//! Key derivation helpers built on HKDF-SHA256.
+//!
+//! Subkeys are derived as `HKDF-SHA256(salt = "SQEP:LITE:SUBKEY:v1",
+//! ikm = key, info = label)`, so different labels give independent keys
+//! and no subkey reveals the parent key. Ciphers configured with
+//! `KdfHash::Sha3_256` (feature `sha3`) use HKDF-SHA3-256 here too.
+//!
+//! The XOR mask of a frame is the `ChaCha20Rng` keystream seeded with
+//! `HKDF(salt = NONCE, ikm = key, info = "SQEP:LITE:QT:v1")`, expanded to
+//! 32 bytes with the frame's `KdfHash` (SHA-256 by default); see
+//! [`derive_xor_seed`]. Frames written with `MaskStrength::ChaCha8` use
+//! `ChaCha8Rng` on the same seed instead.
+
+use ring::hkdf;
+
+use crate::error::SqepError;
+use crate::lite::{OkmLen, ZeroshieldCipher, KEY_LEN};
+
+const SUBKEY_DOMAIN: &[u8] = b"SQEP:LITE:SUBKEY:v1";
+const CLIENT_TO_SERVER: &[u8] = b"SQEP:LITE:DUPLEX:c2s";
+const SERVER_TO_CLIENT: &[u8] = b"SQEP:LITE:DUPLEX:s2c";
+/// HKDF info of the XOR mask seed. Fixed for every cipher and frame format,
+/// so frames carry no mask domain id to check.
+const QT_DOMAIN: &[u8] = b"SQEP:LITE:QT:v1";
+
+/// HKDF hash deriving the XOR mask seed of new frames.
+///
+/// Anything but the default SHA-256 is recorded in a `SQEP4.2-LITE`
+/// header, so `decrypt` follows the frame. The seed fed to `ChaCha20Rng`
+/// is 32 bytes whichever hash derives it.
+///
+/// Non-exhaustive, like `Algorithm`: the `sha3` feature adds a variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum KdfHash {
+    /// HKDF-SHA256 (default, the only hash of 4.0/4.1 frames)
+    #[default]
+    Sha256,
+    /// HKDF-SHA384
+    Sha384,
+    /// HKDF-SHA512
+    Sha512,
+    /// HKDF-SHA3-256 via the RustCrypto `hkdf` and `sha3` crates (`ring`
+    /// has no SHA-3). Also used by `derive_subkey`, unlike the SHA-2
+    /// choices, so a cipher configured with it derives nothing with SHA-2.
+    #[cfg(feature = "sha3")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha3")))]
+    Sha3_256,
+}
+
+impl KdfHash {
+    /// Id byte recorded in `SQEP4.2-LITE` frames
+    pub fn id(self) -> u8 {
+        match self {
+            KdfHash::Sha256 => 0x01,
+            KdfHash::Sha384 => 0x02,
+            KdfHash::Sha512 => 0x03,
+            #[cfg(feature = "sha3")]
+            KdfHash::Sha3_256 => 0x04,
+        }
+    }
+
+    /// Look up a hash by frame id; unknown ids, and ids of hashes not
+    /// compiled into this build, return `UnsupportedAlgorithm`
+    pub fn from_id(id: u8) -> Result<Self, SqepError> {
+        match id {
+            0x01 => Ok(KdfHash::Sha256),
+            0x02 => Ok(KdfHash::Sha384),
+            0x03 => Ok(KdfHash::Sha512),
+            #[cfg(feature = "sha3")]
+            0x04 => Ok(KdfHash::Sha3_256),
+            other => Err(SqepError::UnsupportedAlgorithm(other)),
+        }
+    }
+
+    /// Fill `out` with `HKDF(salt, ikm, info)` under this hash
+    pub(crate) fn hkdf_fill(self, salt: &[u8], ikm: &[u8], info: &[u8], out: &mut [u8]) {
+        let algorithm = match self {
+            KdfHash::Sha256 => hkdf::HKDF_SHA256,
+            KdfHash::Sha384 => hkdf::HKDF_SHA384,
+            KdfHash::Sha512 => hkdf::HKDF_SHA512,
+            #[cfg(feature = "sha3")]
+            KdfHash::Sha3_256 => {
+                let hkdf = ::hkdf::Hkdf::<sha3::Sha3_256>::new(Some(salt), ikm);
+                hkdf.expand(info, out).expect("HKDF-SHA3 expand");
+                return;
+            }
+        };
+        let prk = hkdf::Salt::new(algorithm, salt).extract(ikm);
+        let info = [info];
+        let okm = prk.expand(&info, OkmLen(out.len())).expect("HKDF expand");
+        okm.fill(out).expect("HKDF fill");
+    }
+
+    /// Hash of `derive_subkey`: SHA-256, which existing subkeys depend on,
+    /// except for SHA-3 ciphers
+    fn subkey_hash(self) -> KdfHash {
+        match self {
+            KdfHash::Sha256 | KdfHash::Sha384 | KdfHash::Sha512 => KdfHash::Sha256,
+            #[cfg(feature = "sha3")]
+            KdfHash::Sha3_256 => KdfHash::Sha3_256,
+        }
+    }
+}
+
+/// Keystream generator of the XOR mask of new frames.
+///
+/// The default is deliberately the 20-round `ChaCha20Rng`, the same ChaCha
+/// round count as the ChaCha20-Poly1305 AEAD underneath: the mask is meant
+/// to be as strong as the cipher it wraps, and a test pins the generator so
+/// it can't quietly move to fewer rounds. `ChaCha8` trades margin for
+/// speed; anything but the default is recorded in a `SQEP4.3-LITE` header,
+/// so `decrypt` follows the frame. Confidentiality and integrity rest on
+/// the AEAD either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MaskStrength {
+    /// `ChaCha20Rng`, 20 rounds (default, the only mask of 4.0–4.2 frames)
+    #[default]
+    ChaCha20,
+    /// `ChaCha8Rng`, 8 rounds
+    ChaCha8,
+}
+
+impl MaskStrength {
+    /// Id byte recorded in `SQEP4.3-LITE` frames
+    pub fn id(self) -> u8 {
+        match self {
+            MaskStrength::ChaCha20 => 0x01,
+            MaskStrength::ChaCha8 => 0x02,
+        }
+    }
+
+    /// Look up a strength by frame id; unknown ids return `UnsupportedAlgorithm`
+    pub fn from_id(id: u8) -> Result<Self, SqepError> {
+        match id {
+            0x01 => Ok(MaskStrength::ChaCha20),
+            0x02 => Ok(MaskStrength::ChaCha8),
+            other => Err(SqepError::UnsupportedAlgorithm(other)),
+        }
+    }
+}
+
+/// How a body's XOR mask is generated: the seed hash and the generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct MaskParams {
+    pub(crate) hash: KdfHash,
+    pub(crate) strength: MaskStrength,
+}
+
+impl MaskParams {
+    /// HKDF-SHA256 and `ChaCha20Rng`, fixed for every special-purpose
+    /// frame format and stream chunk
+    pub(crate) const DEFAULT: Self =
+        Self { hash: KdfHash::Sha256, strength: MaskStrength::ChaCha20 };
+}
+
+/// The 32-byte seed of a frame's XOR mask, before it is fed to
+/// `ChaCha20Rng::from_seed`, for default (HKDF-SHA256) frames.
+///
+/// Step by step: `PRK = HMAC-SHA256(key = nonce, msg = key)`, then
+/// `seed = HMAC-SHA256(key = PRK, msg = "SQEP:LITE:QT:v1" || 0x01)`, i.e.
+/// RFC 5869 HKDF with salt = the frame's 12-byte nonce, ikm = the cipher
+/// key, info = `"SQEP:LITE:QT:v1"` and L = 32. The mask XORed into the
+/// plaintext is the generator's `next_u32` output, little-endian, in order.
+pub fn derive_xor_seed(key: &[u8; KEY_LEN], nonce: &[u8]) -> [u8; 32] {
+    xor_seed_using(key, nonce, KdfHash::Sha256)
+}
+
+/// `derive_xor_seed` under any `KdfHash`
+pub(crate) fn xor_seed_using(key: &[u8; KEY_LEN], nonce: &[u8], hash: KdfHash) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    hash.hkdf_fill(nonce, key, QT_DOMAIN, &mut seed);
+    seed
+}
+
+/// Side of a duplex channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+impl ZeroshieldCipher {
+    /// Derive an independent cipher for `label` (same algorithm settings)
+    pub fn derive_subkey(&self, label: &[u8]) -> ZeroshieldCipher {
+        let hash = self.config().kdf_hash.subkey_hash();
+        let mut key = [0u8; KEY_LEN];
+        hash.hkdf_fill(SUBKEY_DOMAIN, self.key_bytes(), label, &mut key);
+        self.with_key(key)
+    }
+
+    /// Derive `(send, recv)` ciphers for one side of a duplex channel.
+    ///
+    /// The client's send key equals the server's receive key and vice
+    /// versa, so a message can't be reflected back to its sender.
+    pub fn split_duplex(&self, role: Role) -> (ZeroshieldCipher, ZeroshieldCipher) {
+        let c2s = self.derive_subkey(CLIENT_TO_SERVER);
+        let s2c = self.derive_subkey(SERVER_TO_CLIENT);
+        match role {
+            Role::Client => (c2s, s2c),
+            Role::Server => (s2c, c2s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplex_keys_pair_up() {
+        let shared = ZeroshieldCipher::new();
+        let (client_send, client_recv) = shared.split_duplex(Role::Client);
+        let (server_send, server_recv) = shared.split_duplex(Role::Server);
+
+        let (ct, _m) = client_send.encrypt_with_meta(b"hello server").expect("encrypt");
+        assert_eq!(server_recv.decrypt(&ct).expect("decrypt"), b"hello server");
+        assert_eq!(server_send.decrypt(&ct), Err(SqepError::DecryptionFailed));
+        // Reflecting the client's own message back at it fails too
+        assert_eq!(client_recv.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn xor_seed_known_answer() {
+        let key: [u8; KEY_LEN] = std::array::from_fn(|i| i as u8);
+        let nonce: [u8; 12] = std::array::from_fn(|i| 0xa0 + i as u8);
+        let seed = derive_xor_seed(&key, &nonce);
+        let seed: String = seed.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(seed, "fd9a57089415db889d0f9181ac726ddfa92eff041de2ef4191ee139764143dd4");
+        assert_ne!(xor_seed_using(&key, &nonce, KdfHash::Sha512), derive_xor_seed(&key, &nonce));
+    }
+
+    #[test]
+    fn subkeys_differ_per_label() {
+        let parent = ZeroshieldCipher::from_key([1u8; KEY_LEN]);
+        let a = parent.derive_subkey(b"a");
+        assert_eq!(a.fingerprint(), parent.derive_subkey(b"a").fingerprint());
+        assert_ne!(a.fingerprint(), parent.derive_subkey(b"b").fingerprint());
+        assert_ne!(a.fingerprint(), parent.fingerprint());
+
+        // SHA-2 mask hashes keep the SHA-256 subkeys
+        let sha512 = parent.clone().with_kdf_hash(KdfHash::Sha512);
+        assert_eq!(sha512.derive_subkey(b"a").fingerprint(), a.fingerprint());
+    }
+
+    /// HMAC-SHA3-256 written out from FIPS 198-1 (block size 136), so the
+    /// test doesn't check `hkdf` against itself
+    #[cfg(feature = "sha3")]
+    fn hmac_sha3_256(key: &[u8], message: &[u8]) -> [u8; 32] {
+        use sha3::{Digest, Sha3_256};
+        let mut block = [0u8; 136];
+        if key.len() > block.len() {
+            block[..32].copy_from_slice(&Sha3_256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let pad = |byte: u8| block.map(|b| b ^ byte);
+        let inner = Sha3_256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+        Sha3_256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().into()
+    }
+
+    #[cfg(feature = "sha3")]
+    #[test]
+    fn sha3_subkeys_and_seeds_are_hkdf_sha3_256() {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        // NIST HMAC_SHA3-256 samples #1 (short key) and #3 (hashed long key)
+        let key: Vec<u8> = (0..=0xA7u8).collect();
+        assert_eq!(
+            hex(&hmac_sha3_256(&key[..32], b"Sample message for keylen<blocklen")),
+            "4fe8e202c4f058e8dddc23d8c34e467343e23555e24fc2f025d598f558f67205"
+        );
+        assert_eq!(
+            hex(&hmac_sha3_256(&key, b"Sample message for keylen>blocklen")),
+            "9bcf2c238e235c3ce88404e813bd2f3a97185ac6f238c63d6229a00b07974258"
+        );
+
+        // RFC 5869 on top: PRK = HMAC(salt, IKM), OKM = HMAC(PRK, info || 0x01)
+        let key = [1u8; KEY_LEN];
+        let nonce = [2u8; 12];
+        let expected = |salt: &[u8], info: &[u8]| {
+            let prk = hmac_sha3_256(salt, &key);
+            hmac_sha3_256(&prk, &[info, &[1]].concat())
+        };
+        let seed = xor_seed_using(&key, &nonce, KdfHash::Sha3_256);
+        assert_eq!(seed, expected(&nonce, QT_DOMAIN));
+        assert_eq!(hex(&seed), "fd7d660abcec42e0b46f5962643e16230e5b04e090b94307e27eb89c76afb192");
+
+        let sha3 = ZeroshieldCipher::from_key(key).with_kdf_hash(KdfHash::Sha3_256);
+        let subkey = sha3.derive_subkey(b"a");
+        assert_eq!(subkey.key_bytes(), &expected(SUBKEY_DOMAIN, b"a"));
+        assert_eq!(
+            hex(subkey.key_bytes()),
+            "5664351dd6743bc1b0b89cda0e3bc4cc4813d15c9fe0c4c54534828751cac44b"
+        );
+        let sha2 = ZeroshieldCipher::from_key(key).derive_subkey(b"a");
+        assert_ne!(subkey.key_bytes(), sha2.key_bytes());
+    }
+}
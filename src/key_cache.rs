@@ -0,0 +1,209 @@
+//! Multi-key decryption that tries recently matching keys first.
+//!
+//! Most frames don't say which key sealed them, so `decrypt_try_keys`
+//! tries every key in order. A `KeyCache` remembers which keys opened
+//! recent frames and tries those first: a service whose traffic repeats
+//! on a few of many keys usually opens a frame on the first attempt.
+//!
+//! `SQEP4.0-LKFP` frames (`encrypt_with_fingerprint`) name their sender's
+//! key fingerprint, and the cache is keyed by it: the key that opened a
+//! fingerprint's last frame is the only one tried first for the next.
+//! Other frames carry nothing to key on, so for them the cache falls back
+//! to one list of recently matching keys, tried in order.
+//!
+//! Each of the two holds at most `capacity` entries (default
+//! `DEFAULT_CACHE_CAPACITY`), most recently matched first. An entry moves
+//! to the front whenever its key opens a frame, and the least recently
+//! matched one is evicted once that side is full. Only fingerprints and
+//! indices into the key list are stored, never plaintext or key material.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::error::SqepError;
+use crate::frame::{Frame, FINGERPRINT_LEN};
+use crate::lite::ZeroshieldCipher;
+
+/// Keys remembered by a `KeyCache` unless configured otherwise
+pub const DEFAULT_CACHE_CAPACITY: usize = 16;
+
+/// A key list for trial decryption, with recent matches tried first
+pub struct KeyCache {
+    keys: Vec<ZeroshieldCipher>,
+    capacity: usize,
+    recent: Mutex<Recent>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Recent matches, most recent first
+#[derive(Default)]
+struct Recent {
+    /// Fingerprint of `SQEP4.0-LKFP` frames and the key that opened them
+    senders: VecDeque<([u8; FINGERPRINT_LEN], usize)>,
+    /// Indices into `keys` that opened frames naming no key
+    keys: VecDeque<usize>,
+}
+
+impl KeyCache {
+    /// Cache over `keys`, remembering `DEFAULT_CACHE_CAPACITY` of them
+    pub fn new(keys: Vec<ZeroshieldCipher>) -> Self {
+        Self {
+            keys,
+            capacity: DEFAULT_CACHE_CAPACITY,
+            recent: Mutex::new(Recent::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Remember at most `capacity` recently matched keys (call before use).
+    ///
+    /// # Panics
+    /// Panics if `capacity` is 0.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1");
+        self.capacity = capacity;
+        self
+    }
+
+    /// Decrypt `frame` with whichever key opens it, returning that key's
+    /// index and the plaintext like `decrypt_try_keys`, or `NoKeyMatched`
+    pub fn decrypt(&self, frame: impl AsRef<[u8]>) -> Result<(usize, Vec<u8>), SqepError> {
+        let frame = frame.as_ref();
+        let sender = Frame::decode(frame)
+            .ok()
+            .and_then(|f| f.fingerprint())
+            .and_then(|f| <[u8; FINGERPRINT_LEN]>::try_from(f).ok());
+        let recent: Vec<usize> = match sender {
+            Some(fingerprint) => {
+                let senders = &self.recent().senders;
+                senders.iter().filter(|(f, _)| *f == fingerprint).map(|&(_, i)| i).collect()
+            }
+            None => self.recent().keys.iter().copied().collect(),
+        };
+        if let Some(found) = self.first_match(frame, sender, recent.iter().copied())? {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(found);
+        }
+
+        let rest = (0..self.keys.len()).filter(|i| !recent.contains(i));
+        let found = self.first_match(frame, sender, rest)?.ok_or(SqepError::NoKeyMatched)?;
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        Ok(found)
+    }
+
+    /// Decrypts opened by a remembered key
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Decrypts that needed a key outside the cache
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Try the keys at `indices` in order, moving a match to the front
+    fn first_match(
+        &self,
+        frame: &[u8],
+        sender: Option<[u8; FINGERPRINT_LEN]>,
+        indices: impl Iterator<Item = usize>,
+    ) -> Result<Option<(usize, Vec<u8>)>, SqepError> {
+        for index in indices.filter(|&i| self.keys[i].key_matches_frame(frame)) {
+            match self.keys[index].decrypt(frame) {
+                Ok(plaintext) => {
+                    self.remember(sender, index);
+                    return Ok(Some((index, plaintext)));
+                }
+                // Structural errors are the same for every key
+                Err(
+                    e @ (SqepError::CiphertextTooShort
+                    | SqepError::InvalidHeader
+                    | SqepError::UnsupportedVersion { .. }),
+                ) => return Err(e),
+                Err(_) => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn remember(&self, sender: Option<[u8; FINGERPRINT_LEN]>, index: usize) {
+        let mut recent = self.recent();
+        match sender {
+            Some(fingerprint) => {
+                recent.senders.retain(|&(f, _)| f != fingerprint);
+                recent.senders.push_front((fingerprint, index));
+                recent.senders.truncate(self.capacity);
+            }
+            None => {
+                recent.keys.retain(|&i| i != index);
+                recent.keys.push_front(index);
+                recent.keys.truncate(self.capacity);
+            }
+        }
+    }
+
+    fn recent(&self) -> std::sync::MutexGuard<'_, Recent> {
+        self.recent.lock().expect("key cache poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_frame_from_the_same_key_hits_the_cache() {
+        let keys: Vec<_> = (0..5).map(|_| ZeroshieldCipher::new()).collect();
+        let cache = KeyCache::new(keys.clone());
+
+        let (first, _m) = keys[3].encrypt_with_meta(b"first").expect("encrypt");
+        let (second, _m) = keys[3].encrypt_with_meta(b"second").expect("encrypt");
+        assert_eq!(cache.decrypt(&first).unwrap(), (3, b"first".to_vec()));
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+        assert_eq!(cache.decrypt(&second).unwrap(), (3, b"second".to_vec()));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        let stranger = ZeroshieldCipher::new().encrypt_with_meta(b"?").expect("encrypt").0;
+        assert_eq!(cache.decrypt(&stranger), Err(SqepError::NoKeyMatched));
+        assert_eq!(cache.decrypt(b"SQEP"), Err(SqepError::CiphertextTooShort));
+    }
+
+    #[test]
+    fn fingerprint_frames_are_cached_by_sender() {
+        let keys: Vec<_> = (0..4).map(|_| ZeroshieldCipher::new()).collect();
+        let cache = KeyCache::new(keys.clone());
+        let frame = |i: usize| keys[i].encrypt_with_fingerprint(b"x").expect("encrypt").0;
+
+        assert_eq!(cache.decrypt(frame(2)).unwrap(), (2, b"x".to_vec()));
+        assert_eq!(cache.recent().senders[0].1, 2);
+        assert!(cache.recent().keys.is_empty());
+        assert_eq!(cache.decrypt(frame(2)).unwrap().0, 2);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        // Another sender's frame doesn't try the key cached for the first
+        assert_eq!(cache.decrypt(frame(1)).unwrap().0, 1);
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        assert_eq!(cache.recent().senders.len(), 2);
+    }
+
+    #[test]
+    fn least_recently_matched_key_is_evicted() {
+        let keys: Vec<_> = (0..3).map(|_| ZeroshieldCipher::new()).collect();
+        let cache = KeyCache::new(keys.clone()).with_capacity(2);
+        let frame = |i: usize| keys[i].encrypt_with_meta(b"x").expect("encrypt").0;
+
+        for i in [0, 1, 2] {
+            cache.decrypt(frame(i)).expect("decrypt");
+        }
+        assert_eq!(cache.recent().keys, [2, 1]);
+
+        cache.decrypt(frame(0)).expect("decrypt");
+        assert_eq!((cache.hits(), cache.misses()), (0, 4));
+        cache.decrypt(frame(2)).expect("decrypt");
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.recent().keys, [2, 0]);
+    }
+}
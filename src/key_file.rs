@@ -0,0 +1,115 @@
+//! Writing freshly generated keys to disk with restrictive permissions.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+
+#[cfg(feature = "encoding")]
+use base64::{engine::general_purpose::STANDARD, Engine};
+use zeroize::Zeroizing;
+
+use crate::error::SqepError;
+use crate::lite::{ZeroshieldCipher, KEY_LEN};
+
+/// Text encoding of a key file (the key followed by a newline)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyFormat {
+    /// Standard base64, as `export_key_base64` returns it
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+    Base64,
+    /// Lowercase hex, 64 characters
+    Hex,
+}
+
+impl KeyFormat {
+    fn encode(self, key: &[u8; KEY_LEN]) -> String {
+        match self {
+            #[cfg(feature = "encoding")]
+            KeyFormat::Base64 => STANDARD.encode(key),
+            KeyFormat::Hex => key.iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+impl ZeroshieldCipher {
+    /// Generate a new random key, write it to `path` in `format`, and
+    /// return its cipher.
+    ///
+    /// The file is created with mode 0600 on Unix (platform defaults
+    /// elsewhere). An existing file is never overwritten (`Io` error)
+    /// unless `force` is set, in which case it is truncated and its
+    /// permissions tightened to 0600 as well.
+    pub fn generate_to_file(path: &str, format: KeyFormat, force: bool) -> Result<Self, SqepError> {
+        let cipher = Self::new();
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if force {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        let encoded = Zeroizing::new(format.encode(cipher.key_bytes()));
+        file.write_all(encoded.as_bytes())?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        Ok(cipher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_key_path() -> String {
+        let path = std::env::temp_dir().join(format!("sqep-lite-{}.key", nanoid::nanoid!()));
+        path.to_str().expect("utf-8 temp path").to_owned()
+    }
+
+    #[test]
+    fn key_file_holds_the_key_and_is_not_overwritten() {
+        let path = temp_key_path();
+        let cipher = ZeroshieldCipher::generate_to_file(&path, KeyFormat::Hex, false).expect("gen");
+        let text = fs::read_to_string(&path).expect("read key file");
+        assert_eq!(text, format!("{}\n", KeyFormat::Hex.encode(cipher.key_bytes())));
+        assert_eq!(text.len(), 2 * KEY_LEN + 1);
+
+        let refused = ZeroshieldCipher::generate_to_file(&path, KeyFormat::Hex, false);
+        assert!(matches!(refused, Err(SqepError::Io(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+        let replaced = ZeroshieldCipher::generate_to_file(&path, KeyFormat::Hex, true).expect("gen");
+        assert_ne!(replaced.fingerprint(), cipher.fingerprint());
+        assert_ne!(fs::read_to_string(&path).unwrap(), text);
+        fs::remove_file(&path).expect("cleanup");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_owner_only_on_unix() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_key_path();
+        // A pre-existing world-readable file is tightened when forced
+        fs::write(&path, b"old").expect("write");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).expect("chmod");
+        ZeroshieldCipher::generate_to_file(&path, KeyFormat::Hex, true).expect("gen");
+        let mode = fs::metadata(&path).expect("metadata").permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(&path).expect("cleanup");
+
+        ZeroshieldCipher::generate_to_file(&path, KeyFormat::Hex, false).expect("gen");
+        let mode = fs::metadata(&path).expect("metadata").permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(&path).expect("cleanup");
+    }
+}
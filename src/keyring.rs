@@ -0,0 +1,170 @@
+//! Several keys addressed by an id carried in the frame.
+//!
+//! ```text
+//! SQEP4.0-LKID (12) || KEY_ID (u32 BE) || FRAME
+//! ```
+//!
+//! `FRAME` is a regular frame of the selected key's cipher, sealed with
+//! AAD `"SQEP:LITE:KEYID:v1" || KEY_ID`. The id is readable in the clear so
+//! `Keyring::decrypt` can pick the key without trying each one, but it is
+//! authenticated: pointing it at another key of the ring fails to decrypt.
+
+use std::collections::HashMap;
+
+use crate::error::SqepError;
+use crate::frame::{KEY_ID_MAGIC, LEN_SIZE, MAGIC_LEN};
+use crate::lite::{SealMeta, ZeroshieldCipher};
+
+const KEY_ID_DOMAIN: &[u8] = b"SQEP:LITE:KEYID:v1";
+
+/// Ciphers indexed by key id
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: HashMap<u32, ZeroshieldCipher>,
+}
+
+impl Keyring {
+    /// Empty keyring
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add (or replace) the cipher for `key_id`
+    pub fn insert(&mut self, key_id: u32, cipher: ZeroshieldCipher) {
+        self.keys.insert(key_id, cipher);
+    }
+
+    /// Add the cipher for `key_id`, or fail with `FingerprintCollision` if
+    /// the id is taken or a key with the same `fingerprint()` is already in
+    /// the ring (which would make fingerprint-based lookups ambiguous)
+    pub fn add_key(&mut self, key_id: u32, cipher: ZeroshieldCipher) -> Result<(), SqepError> {
+        let fingerprint = cipher.fingerprint();
+        let clash = self
+            .keys
+            .iter()
+            .find(|(&id, existing)| id == key_id || existing.fingerprint() == fingerprint);
+        if let Some((&existing_id, _)) = clash {
+            return Err(SqepError::FingerprintCollision { key_id, existing_id });
+        }
+        self.keys.insert(key_id, cipher);
+        Ok(())
+    }
+
+    /// `add_key` without the collision checks, replacing any cipher already
+    /// under `key_id` (same as `insert`)
+    pub fn add_key_force(&mut self, key_id: u32, cipher: ZeroshieldCipher) {
+        self.insert(key_id, cipher);
+    }
+
+    /// Cipher registered for `key_id`, if any
+    pub fn get(&self, key_id: u32) -> Option<&ZeroshieldCipher> {
+        self.keys.get(&key_id)
+    }
+
+    /// Encrypt under the key `key_id`, recording the id in the frame
+    pub fn encrypt(
+        &self,
+        key_id: u32,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let cipher = self.cipher(key_id)?;
+        let id = key_id.to_be_bytes();
+        let overhead = MAGIC_LEN + LEN_SIZE + cipher.frame_overhead();
+        let mut sink = cipher.frame_sink(plaintext.len(), overhead)?;
+        let frame = cipher.seal_frame(plaintext, &key_id_aad(&id))?;
+
+        sink.push(KEY_ID_MAGIC);
+        sink.push(&id);
+        sink.push(&frame);
+        Ok(sink.finish(cipher.clock()))
+    }
+
+    /// Decrypt with the key named by the frame's key id, or `UnknownKeyId`
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let key_id = key_id_of(ciphertext)?;
+        let frame = &ciphertext[MAGIC_LEN + LEN_SIZE..];
+        self.cipher(key_id)?.open_frame(frame, &key_id_aad(&key_id.to_be_bytes()))
+    }
+
+    fn cipher(&self, key_id: u32) -> Result<&ZeroshieldCipher, SqepError> {
+        self.get(key_id).ok_or(SqepError::UnknownKeyId { key_id })
+    }
+}
+
+/// Key id recorded in a frame from `Keyring::encrypt` (not yet authenticated)
+pub fn key_id_of(ciphertext: &[u8]) -> Result<u32, SqepError> {
+    if ciphertext.len() < MAGIC_LEN + LEN_SIZE {
+        return Err(SqepError::CiphertextTooShort);
+    }
+    if !ciphertext.starts_with(KEY_ID_MAGIC) {
+        return Err(SqepError::InvalidHeader);
+    }
+    let mut id = [0u8; LEN_SIZE];
+    id.copy_from_slice(&ciphertext[MAGIC_LEN..MAGIC_LEN + LEN_SIZE]);
+    Ok(u32::from_be_bytes(id))
+}
+
+fn key_id_aad(id: &[u8; LEN_SIZE]) -> Vec<u8> {
+    [KEY_ID_DOMAIN, id].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring_of_three() -> Keyring {
+        let mut ring = Keyring::new();
+        for id in [10, 20, 30] {
+            ring.insert(id, ZeroshieldCipher::new());
+        }
+        ring
+    }
+
+    #[test]
+    fn ring_picks_the_key_from_the_frame() {
+        let ring = ring_of_three();
+        let (ct, _m) = ring.encrypt(20, b"sealed under key 20").expect("encrypt");
+        assert_eq!(key_id_of(&ct).unwrap(), 20);
+        assert_eq!(ring.decrypt(&ct).unwrap(), b"sealed under key 20");
+
+        // The inner frame alone is bound to the key id
+        let key = ring.get(20).unwrap();
+        assert_eq!(key.decrypt(&ct[MAGIC_LEN + LEN_SIZE..]), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn forged_or_unknown_key_ids_fail() {
+        let mut ring = ring_of_three();
+        let (mut ct, _m) = ring.encrypt(10, b"for key 10").expect("encrypt");
+        assert_eq!(ring.encrypt(99, b"x").unwrap_err(), SqepError::UnknownKeyId { key_id: 99 });
+
+        // Same key behind two ids: relabelling still fails authentication
+        ring.insert(11, ring.get(10).unwrap().clone());
+        ct[MAGIC_LEN..MAGIC_LEN + LEN_SIZE].copy_from_slice(&11u32.to_be_bytes());
+        assert_eq!(ring.decrypt(&ct), Err(SqepError::DecryptionFailed));
+
+        ct[MAGIC_LEN..MAGIC_LEN + LEN_SIZE].copy_from_slice(&42u32.to_be_bytes());
+        assert_eq!(ring.decrypt(&ct), Err(SqepError::UnknownKeyId { key_id: 42 }));
+    }
+
+    #[test]
+    fn add_key_refuses_colliding_ids_and_fingerprints() {
+        let mut ring = ring_of_three();
+        let original = ring.get(20).unwrap().fingerprint();
+
+        let taken = SqepError::FingerprintCollision { key_id: 20, existing_id: 20 };
+        assert_eq!(ring.add_key(20, ZeroshieldCipher::new()), Err(taken));
+        // The same key (hence the same fingerprint) under a fresh id
+        let twin = ring.get(30).unwrap().clone();
+        let clash = SqepError::FingerprintCollision { key_id: 31, existing_id: 30 };
+        assert_eq!(ring.add_key(31, twin.clone()), Err(clash));
+        assert!(ring.get(31).is_none());
+        assert_eq!(ring.get(20).unwrap().fingerprint(), original);
+
+        ring.add_key(40, ZeroshieldCipher::new()).expect("fresh key");
+        ring.add_key_force(31, twin);
+        assert_eq!(ring.get(31).unwrap().fingerprint(), ring.get(30).unwrap().fingerprint());
+    }
+}
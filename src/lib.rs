@@ -11,7 +11,7 @@
 //! let msg = b"hello quantum world!";
 //!
 //! // Encrypt and get metadata (timestamp + hash)
-//! let (ct, meta) = cipher.encrypt_with_meta(msg);
+//! let (ct, meta) = cipher.encrypt_with_meta(msg).unwrap();
 //! assert!(meta.timestamp > 0);
 //!
 //! // Decrypt back
@@ -22,15 +22,96 @@
 //! You can also use `decrypt_utf8` and the file helpers
 //! `encrypt_file` / `decrypt_file` for filesystem use.
 
-#![forbid(unsafe_code)]
+// Mapping a file is inherently unsafe, so the `mmap` feature allows the
+// one block in `mmap` that does it
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
 #![deny(rust_2018_idioms)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+/// Enter a debug-level `tracing` span until the end of the enclosing block.
+/// Expands to nothing without the `tracing` feature, so fields are never
+/// evaluated. Only record lengths and fingerprints here, never secrets.
+macro_rules! trace_span {
+    ($name:literal $(, $($field:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($name $(, $($field)*)?).entered();
+    };
+}
+
+pub mod algorithm;
+pub mod archive;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub mod async_stream;
+pub mod capabilities;
+pub mod clock;
+pub mod components;
+pub mod concat;
+pub mod config;
+#[cfg(feature = "serde_json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+pub mod context;
+pub mod error;
+pub mod fragment;
+pub mod frame;
+pub mod frame_writer;
+#[cfg(feature = "serde_json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde_json")))]
+pub mod json;
+pub mod kdf;
+pub mod key_cache;
+pub mod key_file;
+pub mod keyring;
 pub mod lite;
+#[cfg(feature = "mmap")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mmap")))]
+pub mod mmap;
+pub mod mux;
+pub mod nonce;
+pub mod prepared;
+pub mod ratchet;
+pub mod record;
+pub mod resume;
+pub mod scratch;
+pub mod stream;
+mod telemetry;
+pub mod tlv;
+#[cfg(feature = "encoding")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+pub mod token;
+pub mod vault;
 
 // Public re-exports for users of the crate.
+pub use algorithm::Algorithm;
+pub use capabilities::{negotiate, Capabilities};
+pub use clock::{Clock, FixedClock, SystemClock};
+#[cfg(feature = "tokio")]
+pub use async_stream::{AsyncDecryptReader, AsyncEncryptWriter};
+pub use components::EncryptedComponents;
+pub use config::Config;
+pub use error::SqepError;
+pub use frame::{dedup_tag_of, looks_like_frame, FormatVersion, Frame, IndexAttributes, TagPosition};
+pub use frame_writer::FrameWriter;
+pub use kdf::{KdfHash, MaskStrength, Role};
+pub use key_cache::KeyCache;
+pub use key_file::KeyFormat;
+pub use keyring::Keyring;
 pub use lite::{
     ZeroshieldCipher,
     SealMeta,
+    DryRunReport,
 };
+#[cfg(feature = "encoding")]
+pub use lite::looks_like_key_base64;
+pub use mux::MuxCipher;
+pub use nonce::{NonceCounter, UniqueNonce};
+pub use prepared::PreparedCipher;
+pub use ratchet::RatchetCipher;
+pub use record::FixedRecordCipher;
+pub use resume::{ResumableDecryptor, ResumeState};
+pub use scratch::ScratchBuffers;
+pub use stream::{AuthPolicy, DecryptReader, EncryptWriter};
+pub use tlv::{decode_tlv, encode_tlv, FrameEncoding};
+pub use vault::Vault;
 
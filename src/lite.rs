@@ -4,198 +4,2874 @@
 
 #![allow(dead_code)] // suppresses "unused" warnings across the whole file
 
+use std::borrow::Cow;
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+#[cfg(feature = "encoding")]
 use base64::{engine::general_purpose::STANDARD, Engine};
-use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use filetime::FileTime;
 use ring::hkdf;
 use ring::rand::{SecureRandom, SystemRandom};
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::algorithm::{AeadKey, Algorithm};
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::error::SqepError;
+use crate::frame::{
+    looks_like_frame, FormatVersion, read_len, write_len, Frame, CAS_DOMAIN, CAS_MAGIC,
+    EXPIRY_MAGIC, DEDUP_MAGIC, DEDUP_TAG_LEN, FILE_META_MAGIC, FINGERPRINT_LEN, FINGERPRINT_MAGIC,
+    HEADER_MAGIC, LEN_SIZE, MAGIC_LEN, MAX_HEADER_LEN, NONCE_LEN, STREAM_MAGIC, TAG_LEN,
+    TagPosition, TLV_MAGIC,
+};
+use crate::kdf::{self, KdfHash, MaskParams, MaskStrength};
+use crate::nonce::UniqueNonce;
+use crate::stream::DecryptReader;
+use crate::telemetry;
+use crate::tlv::{compact_frame, decode_tlv, encode_tlv, FrameEncoding};
 
 // Stream keystream expander for the keyed XOR mask
 use rand::{RngCore, SeedableRng};
-use rand_chacha::ChaCha20Rng;
+use rand_chacha::{ChaCha20Rng, ChaCha8Rng};
 
-const NONCE_LEN: usize = 12;
-const KEY_LEN: usize = 32;
-const HEADER_MAGIC: &[u8] = b"SQEP4.0-LITE";
+pub(crate) const KEY_LEN: usize = 32;
+/// Largest input `encrypt_file` reads into memory (1 GiB); use
+/// `encrypt_file_limited` for another cap, or the streaming API
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1 << 30;
+const EXPIRY_DOMAIN: &[u8] = b"SQEP:LITE:EXPIRY:v1";
+const EXPIRY_LEN: usize = 8;
+const DEDUP_DOMAIN: &[u8] = b"SQEP:LITE:DEDUP:v1";
+const FILE_META_DOMAIN: &[u8] = b"SQEP:LITE:FILEMETA:v1";
+/// `MTIME_SECS (u64 BE) || MTIME_NANOS (u32 BE)`
+const MTIME_LEN: usize = 12;
+#[cfg(feature = "testing")]
+const TEST_SEED_DOMAIN: &[u8] = b"SQEP:LITE:TESTSEED:v1";
+const KEY_SHARES_DOMAIN: &[u8] = b"SQEP:LITE:SHARES:v1";
+const TRAILER_DOMAIN: &[u8] = b"SQEP:LITE:TRAILER:v1";
+/// Trailing bytes `decrypt_strict` looks past when a frame fails its tag
+const STRICT_TRAILING_SEARCH: usize = 64;
+/// Header, nonce and tag of the longest frame `Frame::decode` accepts
+const MAX_FRAME_PREFIX: usize = MAX_HEADER_LEN + NONCE_LEN + TAG_LEN;
 
-/// Metadata sealed into encrypted payload
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Metadata sealed into encrypted payload (more fields may be added, so
+/// it can't be built with a struct literal outside the crate)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[non_exhaustive]
 pub struct SealMeta {
     pub timestamp: u64,
     pub hash: String,
 }
 
+/// Sizes `encrypt_with_meta` would produce, from `encrypt_dry_run`.
+///
+/// There is no hash: it depends on the random nonce and the ciphertext.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DryRunReport {
+    /// Length of the whole frame
+    pub ciphertext_len: usize,
+    /// Length of the AEAD tag inside it
+    pub tag_len: usize,
+    /// Bytes added around the plaintext (header, nonce and tag)
+    pub overhead: usize,
+}
+
 /// Primary cipher struct: ZeroshieldCipher
 #[derive(Clone)]
 pub struct ZeroshieldCipher {
     key: [u8; KEY_LEN],
+    config: Config,
+    clock: Arc<dyn Clock>,
 }
 
 impl ZeroshieldCipher {
-    /// Generate a new random encryption key
+    /// Generate a new random encryption key.
+    ///
+    /// # Panics
+    /// Panics if the system RNG fails; see `try_new`.
     pub fn new() -> Self {
-        let rng = SystemRandom::new();
+        Self::try_new().expect("Secure key generation failed")
+    }
+
+    /// Generate a new random encryption key, returning `RngUnavailable`
+    /// instead of panicking if the system RNG fails (e.g. in sandboxes
+    /// that block `getrandom`)
+    pub fn try_new() -> Result<Self, SqepError> {
+        Self::generate_with(|key| SystemRandom::new().fill(key))
+    }
+
+    /// Cipher for a key filled in by `fill`
+    fn generate_with(
+        fill: impl FnOnce(&mut [u8]) -> Result<(), ring::error::Unspecified>,
+    ) -> Result<Self, SqepError> {
         let mut key = [0u8; KEY_LEN];
-        rng.fill(&mut key).expect("Secure key generation failed");
-        Self { key }
+        fill(&mut key).map_err(|_| SqepError::RngUnavailable)?;
+        Ok(Self::from_key(key))
     }
 
     /// Initialize cipher from provided key
     pub fn from_key(key: [u8; KEY_LEN]) -> Self {
-        Self { key }
+        Self {
+            key,
+            config: Config::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Rebuild a split-knowledge key from two custodians' shares.
+    ///
+    /// The key is `HKDF-SHA256(salt = "SQEP:LITE:SHARES:v1",
+    /// ikm = share_a || share_b)`, not their XOR, so no share (or relation
+    /// between shares) maps linearly onto the key. To set it up, draw two
+    /// independent random 32-byte shares and hand one to each custodian;
+    /// both must later supply theirs, in the same A/B order, to rebuild the
+    /// same key. Either share alone says nothing about the key.
+    pub fn from_shares(share_a: &[u8; KEY_LEN], share_b: &[u8; KEY_LEN]) -> Self {
+        let mut ikm = Zeroizing::new([0u8; 2 * KEY_LEN]);
+        ikm[..KEY_LEN].copy_from_slice(share_a);
+        ikm[KEY_LEN..].copy_from_slice(share_b);
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, KEY_SHARES_DOMAIN).extract(&ikm[..]);
+        let okm = prk.expand(&[], OkmLen(KEY_LEN)).expect("HKDF expand (key shares)");
+
+        let mut key = [0u8; KEY_LEN];
+        okm.fill(&mut key).expect("HKDF fill (key shares)");
+        Self::from_key(key)
+    }
+
+    /// Deterministic cipher for tests: the key is HKDF-SHA256 of `seed`.
+    ///
+    /// **NOT FOR PRODUCTION.** A `u64` seed has at most 64 bits of entropy
+    /// and anyone who knows (or guesses) the seed has the key. Use it only
+    /// for reproducible tests and golden files.
+    #[cfg(feature = "testing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+    pub fn from_seed(seed: u64) -> Self {
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, TEST_SEED_DOMAIN).extract(&seed.to_be_bytes());
+        let okm = prk.expand(&[], OkmLen(KEY_LEN)).expect("HKDF expand (test key)");
+
+        let mut key = [0u8; KEY_LEN];
+        okm.fill(&mut key).expect("HKDF fill (test key)");
+        Self::from_key(key)
+    }
+
+    /// Select the AEAD used for new frames. `decrypt` then requires
+    /// `SQEP4.1`–`4.4` frames to name the same one (`AlgorithmMismatch`).
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.config.algorithm = algorithm;
+        self
+    }
+
+    /// Select the HKDF hash deriving the XOR mask seed of new frames
+    /// (decryption follows the frame)
+    pub fn with_kdf_hash(mut self, hash: KdfHash) -> Self {
+        self.config.kdf_hash = hash;
+        self
+    }
+
+    /// Select the XOR mask keystream generator for new frames (default
+    /// `MaskStrength::ChaCha20`; decryption follows the frame). Frames of
+    /// the special-purpose formats (content-addressed, expiring, ...) always
+    /// use the default.
+    pub fn with_mask_strength(mut self, strength: MaskStrength) -> Self {
+        self.config.mask_strength = strength;
+        self
+    }
+
+    /// Put the AEAD tag of new frames before the ciphertext
+    /// (`TagPosition::Prepend`) instead of after it, for interop with
+    /// formats laid out that way; recorded in a `SQEP4.4-LITE` header, so
+    /// decryption follows the frame. Frames of the special-purpose formats
+    /// and stream chunks always append the tag.
+    pub fn with_tag_position(mut self, position: TagPosition) -> Self {
+        self.config.tag_position = position;
+        self
+    }
+
+    /// Write every `SQEP4.x-LITE` frame this cipher seals (`encrypt_with_meta`
+    /// and its variants, `FrameWriter`, records, envelopes, ...) as TLV
+    /// records (`FrameEncoding::Tlv`, see `encode_tlv`) instead of the
+    /// compact layout. Frames with their own magic (content-addressed,
+    /// fingerprint, expiry, dedup, file mtime), `encrypt_components` and
+    /// streams keep their layouts. Decryption follows the frame: `decrypt`
+    /// and its variants open both.
+    pub fn with_frame_encoding(mut self, encoding: FrameEncoding) -> Self {
+        self.config.frame_encoding = encoding;
+        self
+    }
+
+    /// Cap the AAD of `encrypt_with_context` / `decrypt_with_context` and
+    /// the authenticated trailer of `encrypt_with_trailer` /
+    /// `decrypt_with_trailer` (default `DEFAULT_MAX_AAD_LEN`); longer ones
+    /// fail with `AadTooLarge`
+    pub fn with_max_aad_len(mut self, max: usize) -> Self {
+        self.config.max_aad_len = max;
+        self
+    }
+
+    /// Refuse to encrypt plaintext shorter than `min` bytes with
+    /// `PlaintextTooShort` (default 0), e.g. `1` for protocols where an
+    /// empty message is always a bug. Applies to every method returning
+    /// `SealMeta` (including `Keyring::encrypt` and `FrameWriter`), not to
+    /// streams or raw frame helpers; decryption is unaffected.
+    pub fn with_min_plaintext_len(mut self, min: usize) -> Self {
+        self.config.min_plaintext_len = min;
+        self
+    }
+
+    /// Compute `SealMeta.hash`, SHA-256 over the whole frame (default on).
+    ///
+    /// Disabled, every `SealMeta` this cipher returns has an empty `hash`
+    /// and the hash pass is skipped, saving one SHA-256 over each frame
+    /// (noticeable on large ones). The timestamp is still set. Only turn it
+    /// off if nothing downstream indexes or checks frames by that hash.
+    pub fn with_meta_hash(mut self, enabled: bool) -> Self {
+        self.config.hash_meta = enabled;
+        self
+    }
+
+    /// Bind each plaintext's length into its AAD (default off).
+    ///
+    /// On, every body this cipher seals or opens (frames of all kinds and
+    /// stream chunks; not legacy `SQEP3.9` frames) authenticates
+    /// `AAD || PLAINTEXT_LEN (u64 BE)`, the length on opening taken from
+    /// the ciphertext size. The AEAD already rejects truncated or extended
+    /// ciphertext; this is defense in depth for protocols composing frames
+    /// with other length fields, so that a body re-sealed at another length
+    /// under the same AAD can never be mistaken for the original.
+    ///
+    /// Nothing in the frame records the setting: both sides must agree on
+    /// it, and frames sealed one way fail with `DecryptionFailed` the other.
+    pub fn with_bind_length(mut self, enabled: bool) -> Self {
+        self.config.bind_length = enabled;
+        self
+    }
+
+    /// Read the time from `clock` for `SealMeta` timestamps, expiry and
+    /// token checks (default `SystemClock`)
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Replace all settings at once
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// AEAD used for new frames
+    pub fn algorithm(&self) -> Algorithm {
+        self.config.algorithm
+    }
+
+    /// Current settings
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Bytes a frame adds around the plaintext (header, nonce and tag, plus
+    /// the record headers of TLV frames)
+    pub fn frame_overhead(&self) -> usize {
+        self.config.header_len() + NONCE_LEN + TAG_LEN + self.config.frame_encoding.overhead()
+    }
+
+    /// Frame sizes for a `plaintext_len`-byte message, without encrypting
+    pub fn encrypt_dry_run(&self, plaintext_len: usize) -> DryRunReport {
+        let overhead = self.frame_overhead();
+        DryRunReport {
+            ciphertext_len: plaintext_len.saturating_add(overhead),
+            tag_len: TAG_LEN,
+            overhead,
+        }
+    }
+
+    /// Approximate peak heap bytes `encrypt_with_meta` allocates for a
+    /// `plaintext_len`-byte input under `config`.
+    ///
+    /// Compact frames are sealed in place in one buffer of the final frame
+    /// size, so that is about 1x the plaintext (plus a copy of the header as
+    /// AAD). TLV frames are re-encoded from that compact frame, about 2x.
+    /// Pure arithmetic, no allocation.
+    pub fn encrypt_memory_estimate(plaintext_len: usize, config: &Config) -> usize {
+        let header = config.header_len();
+        let frame = plaintext_len.saturating_add(header + NONCE_LEN + TAG_LEN);
+        match config.frame_encoding {
+            FrameEncoding::Compact => frame.saturating_add(header),
+            FrameEncoding::Tlv => {
+                let tlv = frame.saturating_add(FrameEncoding::Tlv.overhead());
+                frame.saturating_add(header).saturating_add(tlv)
+            }
+        }
     }
 
     /// Generate short fingerprint (first 6 bytes of SHA256)
     pub fn fingerprint(&self) -> String {
+        self.fingerprint_n(6)
+    }
+
+    /// `fingerprint()` as raw bytes, as named by `SQEP4.0-LKFP` frames
+    fn fingerprint_bytes(&self) -> [u8; FINGERPRINT_LEN] {
+        let digest = Sha256::digest(self.key);
+        let mut fingerprint = [0u8; FINGERPRINT_LEN];
+        fingerprint.copy_from_slice(&digest[..FINGERPRINT_LEN]);
+        fingerprint
+    }
+
+    /// Fingerprint of the first `bytes` bytes of SHA256, as hex (clamped to 32)
+    pub fn fingerprint_n(&self, bytes: usize) -> String {
         let mut hasher = Sha256::new();
-        hasher.update(&self.key);
-        hex::encode(&hasher.finalize()[..6])
+        hasher.update(self.key);
+        let digest = hasher.finalize();
+        digest[..bytes.min(32)].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Wipe the key and consume the cipher, e.g. on logout, instead of
+    /// leaving the bytes in memory until it is dropped (dropping does not
+    /// zeroize).
+    ///
+    /// Only this value is wiped: clones (e.g. those held by a `Keyring` or
+    /// `KeyCache`) keep their own copy of the key. A cipher shared through
+    /// an `Arc` can only be cleared once every other handle has been
+    /// dropped, via `Arc::try_unwrap(shared).map(ZeroshieldCipher::clear_key)`.
+    pub fn clear_key(mut self) {
+        self.wipe_key();
+    }
+
+    fn wipe_key(&mut self) {
+        self.key.zeroize();
     }
 
     /// Export key as base64 string
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
     pub fn export_key_base64(&self) -> String {
         STANDARD.encode(self.key)
     }
 
+    /// Cipher for a key exported by `export_key_base64` (surrounding
+    /// whitespace, such as a key file's newline, is ignored).
+    ///
+    /// Returns `NotAKey` if the input is a frame, raw or base64-encoded,
+    /// rather than a key, `InvalidBase64` if it doesn't decode, and
+    /// `InvalidKeyLength` unless it decodes to exactly `KEY_LEN` bytes.
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+    pub fn import_key_base64(encoded: &str) -> Result<Self, SqepError> {
+        decode_key_base64(encoded).map(|key| Self::from_key(*key))
+    }
+
     /// Encrypt plaintext and attach metadata
-    pub fn encrypt_with_meta(&self, plaintext: &[u8]) -> (Vec<u8>, SealMeta) {
-        // 1) Nonce
-        let rng = SystemRandom::new();
-        let mut nonce_bytes = [0u8; NONCE_LEN];
-        rng.fill(&mut nonce_bytes).expect("Nonce generation failed");
+    ///
+    /// Like `decrypt` and the other single-buffer methods, accepts anything
+    /// byte-like (`&[u8]`, `Vec<u8>`, arrays, `bytes::Bytes`, ...).
+    pub fn encrypt_with_meta(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let (frame, meta, _nonce) = self.encrypt_with_meta_detailed(plaintext)?;
+        Ok((frame, meta))
+    }
 
-        // 2) KEYED and self-inverse xor transform (no data-dependent randomness)
-        let mut in_out = qt_xor_keyed(plaintext, &self.key, &nonce_bytes);
+    /// `encrypt_with_meta`, also returning the random nonce embedded in the
+    /// frame (for nonce-reuse audits or correlating frames with logs)
+    pub fn encrypt_with_meta_detailed(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta, [u8; NONCE_LEN]), SqepError> {
+        let plaintext = plaintext.as_ref();
+        trace_span!(
+            "sqep.encrypt",
+            plaintext_len = plaintext.len(),
+            fingerprint = %self.fingerprint()
+        );
+        let nonce_bytes = random_nonce();
+        let (frame, meta) = self.seal_frame_with_meta_using(
+            &self.aead_key()?,
+            nonce_bytes,
+            plaintext,
+            &[],
+            &*self.clock,
+        )?;
+        Ok((frame, meta, nonce_bytes))
+    }
 
-        // 3) AEAD (ChaCha20-Poly1305)
-        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
-        let key = LessSafeKey::new(UnboundKey::new(&aead::CHACHA20_POLY1305, &self.key).unwrap());
-        key.seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out).unwrap();
+    /// `encrypt_with_meta`, timestamping `SealMeta` with `clock` instead of
+    /// the cipher's own
+    pub fn encrypt_with_clock(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        clock: &dyn Clock,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let (key, plaintext) = (self.aead_key()?, plaintext.as_ref());
+        self.seal_frame_with_meta_using(&key, random_nonce(), plaintext, &[], clock)
+    }
+
+    /// Byte-identical frames for golden-file tests: `nonce` is used as
+    /// given and `SealMeta` is timestamped `timestamp`. Pair it with
+    /// `from_seed` to fix the key as well.
+    ///
+    /// **NOT FOR PRODUCTION.** Sealing two plaintexts under the same key
+    /// and nonce reveals their XOR and lets an attacker forge frames.
+    #[cfg(feature = "testing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+    pub fn encrypt_deterministic_test(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        nonce: [u8; NONCE_LEN],
+        timestamp: u64,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let clock = crate::clock::FixedClock(timestamp);
+        self.seal_frame_with_meta_using(&self.aead_key()?, nonce, plaintext, &[], &clock)
+    }
 
-        // 4) Frame: HEADER || NONCE || CIPHERTEXT+TAG
-        let full = [HEADER_MAGIC, &nonce_bytes, &in_out].concat();
+    /// `encrypt_with_meta` under a caller-supplied nonce, consumed so it
+    /// can't seal a second frame (see `UniqueNonce` / `NonceCounter`)
+    pub fn encrypt_with_unique_nonce(
+        &self,
+        nonce: UniqueNonce,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let key = self.aead_key()?;
+        self.seal_frame_with_meta_using(&key, nonce.into_bytes(), plaintext, &[], &*self.clock)
+    }
 
-        // 5) Meta
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        let hash = Sha256::digest(&full);
+    /// Same as `encrypt_with_meta`, but refuses input that already looks
+    /// like a frame (`SqepError::LikelyDoubleEncrypt`)
+    pub fn encrypt_checked(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        if looks_like_frame(plaintext) {
+            return Err(SqepError::LikelyDoubleEncrypt);
+        }
+        self.encrypt_with_meta(plaintext)
+    }
 
-        (
-            full,
-            SealMeta {
-                timestamp,
-                hash: format!("{:x}", hash),
-            },
-        )
+    /// Panicking variant of `encrypt_with_meta` (pre-0.5 signature)
+    pub fn encrypt_with_meta_unchecked(&self, plaintext: impl AsRef<[u8]>) -> (Vec<u8>, SealMeta) {
+        self.encrypt_with_meta(plaintext).expect("Encryption failed")
     }
 
-    /// Decrypt ciphertext and verify integrity
-    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, &'static str> {
-        if ciphertext.len() < HEADER_MAGIC.len() + NONCE_LEN {
-            return Err("Ciphertext too short");
+    /// Encrypt plaintext into a `bytes::Bytes` frame for zero-copy sharing
+    #[cfg(feature = "bytes")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "bytes")))]
+    pub fn encrypt_bytes(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(bytes::Bytes, SealMeta), SqepError> {
+        let (full, meta) = self.encrypt_with_meta(plaintext)?;
+        Ok((bytes::Bytes::from(full), meta))
+    }
+
+    /// Encrypt deterministically for content-addressed storage.
+    ///
+    /// The nonce is derived as `HKDF(key, SHA256(plaintext))`, so the same
+    /// `(plaintext, key)` pair always yields a byte-identical frame (and the
+    /// same `SealMeta.hash`), which lets a store deduplicate by content.
+    /// Frames use the `SQEP4.0-LCAS` magic, bound via AAD, and always use
+    /// ChaCha20-Poly1305; `decrypt` opens them like any other frame.
+    ///
+    /// Tradeoff: anyone who sees two frames learns whether they hold the
+    /// same plaintext (linkability), and can confirm a guessed plaintext is
+    /// stored if they can get it encrypted under your key. Use the random
+    /// nonce `encrypt_with_meta` unless you need dedup.
+    pub fn encrypt_content_addressed(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let nonce_bytes = self.content_nonce(plaintext);
+        let mut sink = self.frame_sink(plaintext.len(), CAS_MAGIC.len() + NONCE_LEN)?;
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let body = self.seal_body_using(
+            &key,
+            MaskParams::DEFAULT,
+            &nonce_bytes,
+            plaintext,
+            CAS_DOMAIN,
+        )?;
+
+        sink.push(CAS_MAGIC);
+        sink.push(&nonce_bytes);
+        sink.push(&body);
+        Ok(sink.finish(&*self.clock))
+    }
+
+    /// Encrypt into a frame naming this key's fingerprint.
+    ///
+    /// Layout: `SQEP4.0-LKFP || FINGERPRINT || NONCE || CIPHERTEXT+TAG`, the
+    /// first 6 bytes of `SHA256(key)` (as `fingerprint()`) authenticated
+    /// with the magic. Multi-key readers can skip keys that can't match via
+    /// `key_matches_frame`. The fingerprint links every frame of a key to
+    /// the others. These frames open with `decrypt` and always use
+    /// ChaCha20-Poly1305.
+    pub fn encrypt_with_fingerprint(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let header = [FINGERPRINT_MAGIC, &self.fingerprint_bytes()].concat();
+        let mut sink = self.frame_sink(plaintext.len(), header.len() + NONCE_LEN)?;
+        let nonce_bytes = random_nonce();
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let mask = MaskParams::DEFAULT;
+        let body = self.seal_body_using(&key, mask, &nonce_bytes, plaintext, &header)?;
+
+        sink.push(&header);
+        sink.push(&nonce_bytes);
+        sink.push(&body);
+        Ok(sink.finish(&*self.clock))
+    }
+
+    /// Cheap hint whether this key could open `frame`, without touching
+    /// the ciphertext.
+    ///
+    /// Compares (in constant time) this key's fingerprint with the one a
+    /// `SQEP4.0-LKFP` frame names, so `false` means the frame was sealed
+    /// under another key. `true` is only a hint: 48-bit fingerprints can
+    /// collide, the header may be forged, and frames that name no key (or
+    /// don't parse) always return `true`. Only opening the frame proves
+    /// the key right.
+    pub fn key_matches_frame(&self, frame: impl AsRef<[u8]>) -> bool {
+        let frame = frame.as_ref();
+        let named = match Frame::decode(frame).ok().and_then(|f| f.fingerprint()) {
+            Some(named) => named,
+            None => return true,
+        };
+        constant_time_eq(named, &self.fingerprint_bytes())
+    }
+
+    /// Encrypt with a hard expiry (UNIX seconds) enforced at decryption.
+    ///
+    /// Layout: `SQEP4.0-LEXP || EXPIRES_AT (u64 BE) || NONCE || CIPHERTEXT+TAG`.
+    /// `EXPIRES_AT` is readable in the clear but bound via AAD, so it can't
+    /// be extended without the key. Open with `decrypt_check_expiry`; plain
+    /// `decrypt` rejects these frames so the expiry can't be bypassed.
+    /// Expiring frames always use ChaCha20-Poly1305.
+    pub fn encrypt_with_expiry(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        expires_at: u64,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let expires = expires_at.to_be_bytes();
+        let aad = [EXPIRY_DOMAIN, &expires].concat();
+        let overhead = EXPIRY_MAGIC.len() + EXPIRY_LEN + NONCE_LEN;
+        let mut sink = self.frame_sink(plaintext.len(), overhead)?;
+
+        let nonce_bytes = random_nonce();
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let body = self.seal_body_using(&key, MaskParams::DEFAULT, &nonce_bytes, plaintext, &aad)?;
+
+        sink.push(EXPIRY_MAGIC);
+        sink.push(&expires);
+        sink.push(&nonce_bytes);
+        sink.push(&body);
+        Ok(sink.finish(&*self.clock))
+    }
+
+    /// Decrypt a frame from `encrypt_with_expiry`, returning `Expired` once
+    /// the current time is past its `expires_at`.
+    pub fn decrypt_check_expiry(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let header_len = EXPIRY_MAGIC.len() + EXPIRY_LEN;
+        if ciphertext.len() < header_len + NONCE_LEN + TAG_LEN {
+            return Err(SqepError::CiphertextTooShort);
+        }
+        let (header, rest) = ciphertext.split_at(header_len);
+        if &header[..EXPIRY_MAGIC.len()] != EXPIRY_MAGIC {
+            return Err(SqepError::InvalidHeader);
+        }
+        let expires = &header[EXPIRY_MAGIC.len()..];
+        let aad = [EXPIRY_DOMAIN, expires].concat();
+
+        // Authenticate first, so a forged expiry never reaches the time check
+        let (nonce_bytes, encrypted_data) = rest.split_at(NONCE_LEN);
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let plaintext = self.open_body_using(
+            &key,
+            MaskParams::DEFAULT,
+            nonce_bytes,
+            encrypted_data,
+            &aad,
+        )?;
+
+        let mut expires_arr = [0u8; EXPIRY_LEN];
+        expires_arr.copy_from_slice(expires);
+        let expires_at = u64::from_be_bytes(expires_arr);
+        if self.clock.now_unix_secs() > expires_at {
+            return Err(SqepError::Expired { expires_at });
         }
+        Ok(plaintext)
+    }
+
+    /// Deterministic tag of `plaintext` under this key, for deduplication.
+    ///
+    /// `HKDF-SHA256(salt = "SQEP:LITE:DEDUP:v1", ikm = key, info =
+    /// SHA256(plaintext))`. Equal plaintexts under the same key get equal
+    /// tags; without the key a tag reveals nothing about the plaintext and
+    /// can't be computed for a guess.
+    ///
+    /// Linkability: anyone who sees two tags learns whether they belong to
+    /// the same plaintext, which is exactly what deduplication needs and
+    /// exactly what random-nonce frames hide. A key holder can also confirm
+    /// a guessed plaintext against a tag. Only publish tags where equality
+    /// of contents may be known (see also `encrypt_content_addressed`).
+    pub fn dedup_tag(&self, plaintext: impl AsRef<[u8]>) -> [u8; DEDUP_TAG_LEN] {
+        let plaintext = plaintext.as_ref();
+        let digest = Sha256::digest(plaintext);
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, DEDUP_DOMAIN).extract(&self.key);
+        let info = [&digest[..]];
+        let okm = prk.expand(&info, OkmLen(DEDUP_TAG_LEN)).expect("HKDF expand (dedup)");
+
+        let mut tag = [0u8; DEDUP_TAG_LEN];
+        okm.fill(&mut tag).expect("HKDF fill (dedup)");
+        tag
+    }
+
+    /// Encrypt with the plaintext's `dedup_tag` stored in the header.
+    ///
+    /// Layout: `SQEP4.0-LDUP || TAG || NONCE || CIPHERTEXT+TAG`. The tag is
+    /// readable in the clear (`dedup_tag_of`) but bound via AAD, and the
+    /// nonce is still random. Open with `decrypt_dedup_tagged`; plain
+    /// `decrypt` rejects these frames. Tagged frames always use
+    /// ChaCha20-Poly1305.
+    pub fn encrypt_with_dedup_tag(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let tag = self.dedup_tag(plaintext);
+        let aad = [DEDUP_DOMAIN, &tag].concat();
+        let overhead = DEDUP_MAGIC.len() + DEDUP_TAG_LEN + NONCE_LEN;
+        let mut sink = self.frame_sink(plaintext.len(), overhead)?;
 
-        // 1) Parse header
-        let (header, rest) = ciphertext.split_at(HEADER_MAGIC.len());
-        if header != HEADER_MAGIC {
-            return Err("Invalid header");
+        let nonce_bytes = random_nonce();
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let body = self.seal_body_using(&key, MaskParams::DEFAULT, &nonce_bytes, plaintext, &aad)?;
+
+        sink.push(DEDUP_MAGIC);
+        sink.push(&tag);
+        sink.push(&nonce_bytes);
+        sink.push(&body);
+        Ok(sink.finish(&*self.clock))
+    }
+
+    /// Decrypt a frame from `encrypt_with_dedup_tag`.
+    ///
+    /// Fails with `DecryptionFailed` unless the stored tag is the
+    /// plaintext's `dedup_tag` under this key.
+    pub fn decrypt_dedup_tagged(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let header_len = DEDUP_MAGIC.len() + DEDUP_TAG_LEN;
+        if ciphertext.len() < header_len + NONCE_LEN + TAG_LEN {
+            return Err(SqepError::CiphertextTooShort);
+        }
+        let (header, rest) = ciphertext.split_at(header_len);
+        if &header[..DEDUP_MAGIC.len()] != DEDUP_MAGIC {
+            return Err(SqepError::InvalidHeader);
         }
+        let tag = &header[DEDUP_MAGIC.len()..];
+        let aad = [DEDUP_DOMAIN, tag].concat();
 
-        // 2) Split nonce and data
         let (nonce_bytes, encrypted_data) = rest.split_at(NONCE_LEN);
-        let nonce = Nonce::try_assume_unique_for_key(nonce_bytes).map_err(|_| "Nonce error")?;
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let mut plaintext =
+            self.open_body_using(&key, MaskParams::DEFAULT, nonce_bytes, encrypted_data, &aad)?;
 
-        // 3) AEAD open
-        let key = LessSafeKey::new(UnboundKey::new(&aead::CHACHA20_POLY1305, &self.key).unwrap());
-        let mut in_out = encrypted_data.to_vec();
-        let decrypted = key
-            .open_in_place(nonce, Aad::empty(), &mut in_out)
-            .map_err(|_| "Decryption failed")?;
+        // Only a key holder could have mis-tagged it, but keep the index honest
+        if self.dedup_tag(&plaintext) != tag {
+            plaintext.zeroize();
+            return Err(SqepError::DecryptionFailed);
+        }
+        Ok(plaintext)
+    }
 
-        // 4) Reverse the KEYED xor transform (self-inverse) and return owned Vec<u8>
-        Ok(qt_xor_keyed(decrypted, &self.key, nonce_bytes))
+    /// Whether two frames hold the same plaintext, for frames without a
+    /// dedup tag. Needs the key, unlike comparing `dedup_tag_of` values.
+    ///
+    /// Both frames must open under this key (else `decrypt`'s error). The
+    /// plaintexts are compared in constant time, never returned, and
+    /// zeroized before this returns; only their lengths, which the frame
+    /// lengths give away anyway, can short-cut the comparison.
+    pub fn same_content(
+        &self,
+        frame_a: impl AsRef<[u8]>,
+        frame_b: impl AsRef<[u8]>,
+    ) -> Result<bool, SqepError> {
+        let frame_a = frame_a.as_ref();
+        let frame_b = frame_b.as_ref();
+        let a = Zeroizing::new(self.decrypt(frame_a)?);
+        let b = Zeroizing::new(self.decrypt(frame_b)?);
+        Ok(constant_time_eq(&a, &b))
+    }
+
+    /// Encrypt a batch of messages, building the AEAD key only once.
+    ///
+    /// Every message still gets its own fresh random nonce.
+    pub fn encrypt_many(&self, msgs: &[&[u8]]) -> Result<Vec<(Vec<u8>, SealMeta)>, SqepError> {
+        let key = self.aead_key()?;
+        msgs.iter()
+            .map(|msg| {
+                self.seal_frame_with_meta_using(&key, random_nonce(), msg, &[], &*self.clock)
+            })
+            .collect()
+    }
+
+    /// Decrypt a batch of frames, building the AEAD key only once.
+    ///
+    /// Each frame is verified independently; one bad frame doesn't stop the rest.
+    pub fn decrypt_many(
+        &self,
+        frames: &[&[u8]],
+    ) -> Result<Vec<Result<Vec<u8>, SqepError>>, SqepError> {
+        let key = self.aead_key()?;
+        Ok(frames
+            .iter()
+            .map(|frame| self.open_frame_using(&key, frame, &[]))
+            .collect())
+    }
+
+    /// Decrypt ciphertext and verify integrity
+    ///
+    /// Accepts anything byte-like (`&[u8]`, `Vec<u8>`, `bytes::Bytes`, ...).
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        trace_span!(
+            "sqep.decrypt",
+            ciphertext_len = ciphertext.len(),
+            fingerprint = %self.fingerprint()
+        );
+        self.open_frame(ciphertext, &[])
+    }
+
+    /// Decrypt into `out` without allocating, returning the plaintext length.
+    ///
+    /// `out` must hold the frame's body minus the tag (the plaintext, plus
+    /// the 16 padding bytes of legacy frames), else `BufferTooSmall`. The
+    /// AEAD opens in place inside `out`, and that region is zeroed again if
+    /// authentication fails. TLV frames are reassembled first, which is the
+    /// one case that allocates.
+    pub fn decrypt_into_slice(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        out: &mut [u8],
+    ) -> Result<usize, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let ciphertext = compact_frame(ciphertext)?;
+        let frame = Frame::decode(&ciphertext)?;
+        self.check_algorithm(&frame)?;
+        let (body, tag) = frame.split_tag();
+        let needed = body.len();
+        let buf = out.get_mut(..needed).ok_or(SqepError::BufferTooSmall { needed })?;
+        buf.copy_from_slice(body);
+        self.open_in_place(&frame, tag, buf)
+    }
+
+    /// Decrypt a frame the caller owns inside its own allocation.
+    ///
+    /// Opens the body in place, then shifts the plaintext to the front and
+    /// truncates, so unlike `decrypt` no second buffer is allocated. The
+    /// returned `Vec` keeps the frame's capacity. TLV frames are
+    /// reassembled into a new buffer first.
+    pub fn decrypt_owned(&self, mut frame: Vec<u8>) -> Result<Vec<u8>, SqepError> {
+        if frame.starts_with(TLV_MAGIC) {
+            frame = decode_tlv(&frame)?.0;
+        }
+        let parsed = Frame::decode(&frame)?;
+        self.check_algorithm(&parsed)?;
+        let body_start = parsed.len() - parsed.body().len();
+        let (start, tag_start) = match parsed.tag_position() {
+            TagPosition::Append => (body_start, frame.len() - TAG_LEN),
+            TagPosition::Prepend => (body_start + TAG_LEN, body_start),
+        };
+        let end = start + parsed.body().len() - TAG_LEN;
+
+        // Header, nonce and tag move to the stack, so the body can be
+        // mutated while they stay readable
+        let mut parts = [0u8; MAX_FRAME_PREFIX];
+        parts[..body_start].copy_from_slice(&frame[..body_start]);
+        parts[body_start..body_start + TAG_LEN]
+            .copy_from_slice(&frame[tag_start..tag_start + TAG_LEN]);
+        let parts = Frame::decode(&parts[..body_start + TAG_LEN]).expect("header parsed above");
+
+        let len = self.open_in_place(&parts, parts.body(), &mut frame[start..end])?;
+        frame.copy_within(start..start + len, 0);
+        frame.truncate(len);
+        Ok(frame)
+    }
+
+    /// Decrypt a raw frame or a standard-base64 encoding of one (surrounding
+    /// whitespace is ignored), for stores holding both.
+    ///
+    /// Returns `UnrecognizedInput` if neither form starts with a frame magic.
+    #[cfg(feature = "encoding")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+    pub fn decrypt_auto(&self, input: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let input = input.as_ref();
+        if looks_like_frame(input) {
+            return self.decrypt(input);
+        }
+        let start = input.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(input.len());
+        let end = input.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(start, |i| i + 1);
+        match STANDARD.decode(&input[start..end]) {
+            Ok(frame) if looks_like_frame(&frame) => self.decrypt(&frame),
+            _ => Err(SqepError::UnrecognizedInput),
+        }
+    }
+
+    /// Decrypt, requiring the input to be exactly one frame, and report
+    /// bytes after it as `TrailingBytes` rather than a generic
+    /// authentication failure.
+    ///
+    /// A compact frame's body runs to the end of its input, so trailing
+    /// bytes only show as a failed tag: when the whole input fails, the
+    /// up to 64 next shorter prefixes are tried, one AEAD pass each, and
+    /// `TrailingBytes` names the first that opens. A
+    /// TLV frame must hold no records beyond its known ones. Legacy 3.9
+    /// frames, padding included, are accepted as they are.
+    pub fn decrypt_strict(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        if ciphertext.starts_with(TLV_MAGIC) {
+            let (compact, meta) = decode_tlv(ciphertext)?;
+            let canonical = encode_tlv(&compact, meta.as_deref())?.len();
+            if ciphertext.len() > canonical {
+                return Err(SqepError::TrailingBytes {
+                    extra: ciphertext.len() - canonical,
+                });
+            }
+            return self.open_frame(&compact, &[]);
+        }
+        match self.open_frame(ciphertext, &[]) {
+            Err(SqepError::DecryptionFailed) => {}
+            opened => return opened,
+        }
+
+        // Look for the real end of the frame; these probes aren't metered
+        let key = self.aead_key()?;
+        let search = STRICT_TRAILING_SEARCH.min(ciphertext.len());
+        for extra in 1..=search {
+            let prefix = &ciphertext[..ciphertext.len() - extra];
+            if Frame::decode(prefix).is_err() {
+                break;
+            }
+            if let Ok(plaintext) = self.open_frame_unmetered(&key, prefix, &[]) {
+                drop(Zeroizing::new(plaintext));
+                return Err(SqepError::TrailingBytes { extra });
+            }
+        }
+        Err(SqepError::DecryptionFailed)
     }
 
-    /// Convenience: decrypt and ensure the output is valid UTF-8
-    pub fn decrypt_utf8(&self, ciphertext: &[u8]) -> Result<String, &'static str> {
+    /// Decrypt, refusing frames whose plaintext would exceed `max_plaintext`
+    /// bytes. The check runs before any buffer is allocated, so an
+    /// attacker-supplied blob can't force a large allocation.
+    pub fn decrypt_bounded(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+        max_plaintext: usize,
+    ) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let plaintext_len = Frame::decode(&compact_frame(ciphertext)?)?.plaintext_len();
+        if plaintext_len > max_plaintext {
+            return Err(SqepError::OutputTooLarge { max: max_plaintext });
+        }
+        self.open_frame(ciphertext, &[])
+    }
+
+    /// Encrypt plaintext and append an authenticated, unencrypted trailer.
+    ///
+    /// Layout: `HEADER || NONCE || CIPHERTEXT+TAG || TRAILER || TRAILER_LEN`,
+    /// where `TRAILER_LEN` is a 4-byte big-endian length so readers can
+    /// locate the trailer from the end of the frame. The trailer is bound
+    /// to the ciphertext via AAD; changing it makes decryption fail. A
+    /// trailer over the configured `max_aad_len` fails with `AadTooLarge`,
+    /// one longer than `u32::MAX` bytes with `FrameTooLarge`.
+    pub fn encrypt_with_trailer(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        trailer: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let trailer = trailer.as_ref();
+        self.check_aad_len(trailer)?;
+        let trailer_len = write_len(trailer.len())?;
+        let aad = [TRAILER_DOMAIN, trailer].concat();
+
+        let mut frame = self.seal_frame_using(&self.aead_key()?, random_nonce(), plaintext, &aad)?;
+        frame.reserve(trailer.len() + LEN_SIZE);
+        frame.extend_from_slice(trailer);
+        frame.extend_from_slice(&trailer_len);
+        let meta = self.frame_meta(plaintext.len(), &frame, &*self.clock)?;
+        Ok((frame, meta))
+    }
+
+    /// Decrypt a frame produced by `encrypt_with_trailer`.
+    ///
+    /// Returns `(plaintext, trailer)`; the trailer is only returned once
+    /// the AEAD tag has verified it.
+    pub fn decrypt_with_trailer(
+        &self,
+        ciphertext: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, Vec<u8>), SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        if ciphertext.len() < LEN_SIZE {
+            return Err(SqepError::CiphertextTooShort);
+        }
+
+        // 1) Trailer length is the last 4 bytes (big-endian)
+        let (rest, len_bytes) = ciphertext.split_at(ciphertext.len() - LEN_SIZE);
+        let trailer_len = read_len(len_bytes)?;
+        if trailer_len > rest.len() {
+            return Err(SqepError::InvalidTrailerLength);
+        }
+
+        // 2) Split frame and trailer, then open with the trailer as AAD
+        let (frame, trailer) = rest.split_at(rest.len() - trailer_len);
+        self.check_aad_len(trailer)?;
+        let aad = [TRAILER_DOMAIN, trailer].concat();
+        let plaintext = self.open_frame(frame, &aad)?;
+
+        Ok((plaintext, trailer.to_vec()))
+    }
+
+    /// Try each of `keys` in turn; return the index and plaintext of the
+    /// first key that authenticates the frame, or `NoKeyMatched`.
+    pub fn decrypt_try_keys(
+        ciphertext: impl AsRef<[u8]>,
+        keys: &[ZeroshieldCipher],
+    ) -> Result<(usize, Vec<u8>), SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        Self::decrypt_try_keys_with(ciphertext, keys, false)
+    }
+
+    /// Like `decrypt_try_keys`; with `try_all` set, every key is attempted
+    /// even after a match, so the number of attempts doesn't reveal which
+    /// key matched. The first matching key still wins.
+    pub fn decrypt_try_keys_with(
+        ciphertext: impl AsRef<[u8]>,
+        keys: &[ZeroshieldCipher],
+        try_all: bool,
+    ) -> Result<(usize, Vec<u8>), SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let mut found = None;
+        for (index, key) in keys.iter().enumerate() {
+            // The fingerprint is public, so skipping reveals nothing new
+            if !key.key_matches_frame(ciphertext) {
+                continue;
+            }
+            match key.decrypt(ciphertext) {
+                Ok(pt) if found.is_none() => found = Some((index, pt)),
+                // Structural errors are the same for every key
+                Err(
+                    e @ (SqepError::CiphertextTooShort
+                    | SqepError::InvalidHeader
+                    | SqepError::UnsupportedVersion { .. }),
+                ) => return Err(e),
+                _ => {}
+            }
+            if found.is_some() && !try_all {
+                break;
+            }
+        }
+        found.ok_or(SqepError::NoKeyMatched)
+    }
+
+    /// Decrypt only a frame written by the pre-4.0 `SQEP3.9` code.
+    ///
+    /// `decrypt` already opens these transparently; this variant rejects
+    /// every other format with `InvalidHeader`, e.g. for migration tools.
+    pub fn decrypt_legacy(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        if Frame::decode(ciphertext)?.version() != FormatVersion::V3_9 {
+            return Err(SqepError::InvalidHeader);
+        }
+        self.open_frame(ciphertext, &[])
+    }
+
+    /// Re-encrypt a `SQEP3.9` frame as a current frame of this cipher.
+    ///
+    /// Opens `legacy_frame` like `decrypt_legacy` (so other formats return
+    /// `InvalidHeader`) and seals the plaintext again with the XOR mask
+    /// in this cipher's format (`SQEP4.0-LITE` with the default settings).
+    /// The plaintext only lives in a buffer that is zeroized on return.
+    pub fn migrate_frame(&self, legacy_frame: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let legacy_frame = legacy_frame.as_ref();
+        let plaintext = Zeroizing::new(self.decrypt_legacy(legacy_frame)?);
+        self.seal_frame(&plaintext, &[])
+    }
+
+    /// Re-encrypt a frame this cipher opens as a frame of `new_key`.
+    ///
+    /// Opens `frame` like `decrypt` and seals the plaintext again with a
+    /// fresh nonce under `new_key` and its settings, so passing a cipher
+    /// with the same key but other settings only migrates the format. This
+    /// is the way to relabel frames whose header is authenticated, which
+    /// `Frame::rewrap_magic` refuses. The plaintext only lives in a buffer
+    /// that is zeroized on return.
+    pub fn rekey(
+        &self,
+        frame: impl AsRef<[u8]>,
+        new_key: &ZeroshieldCipher,
+    ) -> Result<Vec<u8>, SqepError> {
+        let plaintext = Zeroizing::new(self.decrypt(frame)?);
+        new_key.seal_frame(&plaintext, &[])
+    }
+
+    /// Convenience: decrypt and ensure the output is valid UTF-8.
+    /// Strict: invalid UTF-8 fails with `InvalidUtf8` (see
+    /// `decrypt_utf8_lossy` for best-effort text).
+    pub fn decrypt_utf8(&self, ciphertext: impl AsRef<[u8]>) -> Result<String, SqepError> {
         let bytes = self.decrypt(ciphertext)?;
-        let s = std::str::from_utf8(&bytes).map_err(|_| "UTF-8 error")?;
+        let s = std::str::from_utf8(&bytes).map_err(|_| SqepError::InvalidUtf8)?;
         Ok(s.to_owned())
     }
 
-    /// Encrypt file to another file path
+    /// Decrypt and convert to text, replacing invalid UTF-8 with U+FFFD
+    /// instead of failing, for logging and debugging tools.
+    ///
+    /// Only the text conversion is lenient: the frame must still
+    /// authenticate. Use `decrypt_utf8` wherever the text is processed
+    /// further, since the replacement loses the original bytes.
+    pub fn decrypt_utf8_lossy(&self, ciphertext: impl AsRef<[u8]>) -> Result<String, SqepError> {
+        let bytes = self.decrypt(ciphertext)?;
+        Ok(String::from_utf8(bytes)
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()))
+    }
+
+    /// Encrypt file to another file path.
+    ///
+    /// The file is read into memory, so inputs over `DEFAULT_MAX_FILE_SIZE`
+    /// are refused with `FileTooLarge` (as an `InvalidInput` I/O error).
     pub fn encrypt_file(&self, input_path: &str, output_path: &str) -> std::io::Result<SealMeta> {
-        let data = fs::read(input_path)?;
-        let (encrypted, meta) = self.encrypt_with_meta(&data);
+        self.encrypt_file_limited(input_path, output_path, DEFAULT_MAX_FILE_SIZE)
+    }
+
+    /// `encrypt_file` with a caller-chosen cap on the input size, checked
+    /// from the file's metadata before anything is read
+    pub fn encrypt_file_limited(
+        &self,
+        input_path: &str,
+        output_path: &str,
+        max_bytes: u64,
+    ) -> std::io::Result<SealMeta> {
+        trace_span!("sqep.encrypt_file", fingerprint = %self.fingerprint());
+        let data = read_capped(input_path, max_bytes)?;
+        self.write_frame_file(&data, output_path)
+    }
+
+    /// Encrypt a file like `encrypt_file`, also sealing its modification time.
+    ///
+    /// Layout: `SQEP4.0-LFMT || MTIME (12) || NONCE || CIPHERTEXT+TAG`, with
+    /// `MTIME` bound via AAD so it can't be altered without the key.
+    /// `decrypt_file` restores it on the output file. If the source has no
+    /// usable mtime, a plain frame is written instead.
+    pub fn encrypt_file_preserving_metadata(
+        &self,
+        input_path: &str,
+        output_path: &str,
+    ) -> std::io::Result<SealMeta> {
+        trace_span!("sqep.encrypt_file", fingerprint = %self.fingerprint());
+        let data = read_capped(input_path, DEFAULT_MAX_FILE_SIZE)?;
+        let mtime = fs::metadata(input_path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok());
+        let sealed = match mtime {
+            Some(mtime) => self.seal_with_mtime(&data, mtime),
+            None => self.encrypt_with_meta(&data),
+        };
+        let (encrypted, meta) =
+            sealed.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         fs::write(output_path, encrypted)?;
         Ok(meta)
     }
 
     /// Decrypt file to another file path
+    ///
+    /// Files from `encrypt_file_preserving_metadata` get their original
+    /// mtime back, where the filesystem allows it.
     pub fn decrypt_file(&self, input_path: &str, output_path: &str) -> std::io::Result<()> {
+        trace_span!("sqep.decrypt_file", fingerprint = %self.fingerprint());
         let data = fs::read(input_path)?;
-        let decrypted = self
-            .decrypt(&data)
+        let (decrypted, mtime) = self
+            .open_file_frame(&data)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        fs::write(output_path, decrypted)
+        fs::write(output_path, decrypted)?;
+        if let Some(mtime) = mtime {
+            // Best effort: not every filesystem can set an mtime
+            let _ = filetime::set_file_mtime(output_path, FileTime::from_system_time(mtime));
+        }
+        Ok(())
     }
-}
 
-// ---------------------------------------------------------------------
-// Keyed, self-inverse XOR transform (Lite)
-// ---------------------------------------------------------------------
+    /// Encrypt everything read from `reader` into the file at `output_path`
+    ///
+    /// The single-frame format authenticates the whole message at once, so
+    /// the input is buffered in memory before sealing.
+    pub fn encrypt_from_reader(
+        &self,
+        reader: &mut dyn Read,
+        output_path: &str,
+    ) -> std::io::Result<SealMeta> {
+        trace_span!("sqep.encrypt_file", fingerprint = %self.fingerprint());
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.write_frame_file(&data, output_path)
+    }
 
-const QT_DOMAIN: &[u8] = b"SQEP:LITE:QT:v1";
+    /// Seal `plaintext` like `encrypt_with_meta` into a new file at
+    /// `output_path`. The frame is hashed for `SealMeta.hash` as it is
+    /// written, not read back.
+    fn write_frame_file(&self, plaintext: &[u8], output_path: &str) -> std::io::Result<SealMeta> {
+        let frame = self
+            .check_plaintext_len(plaintext.len())
+            .and_then(|()| self.aead_key())
+            .and_then(|key| self.seal_frame_using(&key, random_nonce(), plaintext, &[]))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
 
-fn qt_xor_keyed(data: &[u8], key32: &[u8; KEY_LEN], nonce12: &[u8]) -> Vec<u8> {
-    // HKDF(PRK) from (salt=nonce, ikm=key), then 32B seed -> ChaCha20Rng stream
-    let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, nonce12);
-    let prk = salt.extract(key32);
+        let mut out = HashingWriter::new(fs::File::create(output_path)?, self.config.hash_meta);
+        out.write_all(&frame)?;
+        let (mut file, meta) = out.finish(&*self.clock);
+        file.flush()?;
+        Ok(meta)
+    }
 
-    // Bind context to domain; expand exactly 32 bytes of seed
-    let info_arr = [QT_DOMAIN];
-    let okm = prk.expand(&info_arr, hkdf::HKDF_SHA256).expect("HKDF expand (seed)");
+    /// Decrypt the file at `input_path` into any `writer` (stdout, socket, ...)
+    ///
+    /// Nothing is written unless the whole frame authenticates.
+    pub fn decrypt_to_writer(
+        &self,
+        input_path: &str,
+        writer: &mut dyn Write,
+    ) -> std::io::Result<()> {
+        trace_span!("sqep.decrypt_file", fingerprint = %self.fingerprint());
+        let data = fs::read(input_path)?;
+        let (decrypted, _mtime) = self
+            .open_file_frame(&data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        writer.write_all(&decrypted)?;
+        writer.flush()
+    }
 
-    // 32-byte seed for ChaCha20Rng
-    let mut seed = [0u8; 32];
-    okm.fill(&mut seed).expect("HKDF fill (seed)");
+    /// Check that the file at `path` authenticates under this key without
+    /// writing any plaintext, e.g. for periodic integrity scans of backups.
+    ///
+    /// Chunked stream files (`SQEP4.0-STRM`) are verified chunk by chunk,
+    /// final MAC included, in constant memory. Any other file holds a
+    /// single frame whose one tag covers the whole body, so it is read into
+    /// memory whole, as `decrypt_file` does. Bit-rot or tampering fails
+    /// with `DecryptionFailed`.
+    pub fn verify_file(&self, path: &str) -> Result<(), SqepError> {
+        trace_span!("sqep.verify_file", fingerprint = %self.fingerprint());
+        let mut file = fs::File::open(path)?;
+        let mut data = Vec::new();
+        (&mut file).take(MAGIC_LEN as u64).read_to_end(&mut data)?;
+        if data.starts_with(STREAM_MAGIC) {
+            return DecryptReader::new(self, data.chain(file)).verify_to_end();
+        }
+        file.read_to_end(&mut data)?;
+        self.open_file_frame(&data).map(|(plaintext, _mtime)| drop(Zeroizing::new(plaintext)))
+    }
 
-    // Expand to keystream of data.len()
-    let mut ks = vec![0u8; data.len()];
-    let mut rng = ChaCha20Rng::from_seed(seed);
-    let mut i = 0usize;
-    while i < ks.len() {
-        let word = rng.next_u32().to_le_bytes();
-        let take = core::cmp::min(4, ks.len() - i);
-        ks[i..i + take].copy_from_slice(&word[..take]);
-        i += take;
+    /// Time source for timestamps and expiry checks (crate-internal)
+    pub(crate) fn clock(&self) -> &dyn Clock {
+        &*self.clock
     }
 
-    data.iter().zip(ks.iter()).map(|(a, b)| a ^ b).collect()
-}
+    /// Raw key bytes (crate-internal)
+    pub(crate) fn key_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.key
+    }
 
-// ---------------------------------------------------------------------
-// Backward-compat shims (deprecated): previously exported helpers
-// Now they are identity transforms to avoid data-dependent XOR pitfalls.
-// ---------------------------------------------------------------------
+    /// Same settings, different key (crate-internal)
+    pub(crate) fn with_key(&self, key: [u8; KEY_LEN]) -> Self {
+        Self {
+            key,
+            ..self.clone()
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Frame header for this cipher's settings: `SQEP4.0-LITE` for the
+    /// defaults, `SQEP4.1-LITE || ALG` for another algorithm,
+    /// `SQEP4.2-LITE || ALG || KDF` for another mask KDF hash, then
+    /// `SQEP4.3` adding the mask strength and `SQEP4.4` the tag position
+    pub(crate) fn frame_header(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        self.write_frame_header(&mut header);
+        header
+    }
 
-    #[test]
-    fn roundtrip_exact_len() {
-        let cipher = ZeroshieldCipher::new();
-        let msg = b"hello quantum world!";
-        let (ct, _m) = cipher.encrypt_with_meta(msg);
-        let pt = cipher.decrypt(&ct).expect("decrypt");
-        assert_eq!(pt, msg, "roundtrip mismatch");
-        // Also check UTF-8 path
-        let s = cipher.decrypt_utf8(&ct).expect("utf8");
-        assert_eq!(s.as_bytes(), msg);
+    /// Append `frame_header()` to `out`
+    pub(crate) fn write_frame_header(&self, out: &mut Vec<u8>) {
+        self.config.write_header(out);
+    }
+
+    /// Frame buffer hashing for `SealMeta` unless disabled in the config,
+    /// sized for `plaintext_len` bytes sealed after `overhead` bytes.
+    ///
+    /// Every in-memory `SealMeta` is built here or in `frame_meta`, both of
+    /// which enforce the configured minimum plaintext length, so create the
+    /// sink before sealing to fail fast.
+    pub(crate) fn frame_sink(
+        &self,
+        plaintext_len: usize,
+        overhead: usize,
+    ) -> Result<FrameSink, SqepError> {
+        self.check_plaintext_len(plaintext_len)?;
+        let capacity = plaintext_len.saturating_add(overhead + TAG_LEN);
+        Ok(FrameSink::with_capacity(capacity, self.config.hash_meta))
+    }
+
+    /// Seal `plaintext` into a frame in the configured `FrameEncoding`,
+    /// binding `aad`
+    pub(crate) fn seal_frame(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, SqepError> {
+        self.seal_frame_with_nonce(random_nonce(), plaintext, aad)
+    }
+
+    /// Same as `seal_frame`, also building the `SealMeta` for the frame
+    pub(crate) fn seal_frame_with_meta(
+        &self,
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let key = self.aead_key()?;
+        self.seal_frame_with_meta_using(&key, random_nonce(), plaintext, aad, &*self.clock)
+    }
+
+    /// `seal_frame_with_meta` with an already constructed AEAD key and nonce
+    pub(crate) fn seal_frame_with_meta_using(
+        &self,
+        key: &AeadKey,
+        nonce_bytes: [u8; NONCE_LEN],
+        plaintext: &[u8],
+        aad: &[u8],
+        clock: &dyn Clock,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let frame = self.seal_frame_using(key, nonce_bytes, plaintext, aad)?;
+        let meta = self.frame_meta(plaintext.len(), &frame, clock)?;
+        Ok((frame, meta))
+    }
+
+    /// Seal `plaintext` into a frame in the configured `FrameEncoding`.
+    /// Every frame of the `SQEP4.x-LITE` family this cipher writes is
+    /// sealed here, so the encoding applies to all of them.
+    pub(crate) fn seal_frame_using(
+        &self,
+        key: &AeadKey,
+        nonce_bytes: [u8; NONCE_LEN],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        let frame = self.seal_compact_using(key, nonce_bytes, plaintext, aad)?;
+        self.config.frame_encoding.encode(frame)
+    }
+
+    /// Seal `plaintext` into a compact `HEADER || NONCE || CIPHERTEXT+TAG`
+    /// frame, in place inside one buffer of the final size
+    pub(crate) fn seal_compact_using(
+        &self,
+        key: &AeadKey,
+        nonce_bytes: [u8; NONCE_LEN],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        let overhead = self.config.header_len() + NONCE_LEN + TAG_LEN;
+        let mut frame = Vec::with_capacity(plaintext.len().saturating_add(overhead));
+        self.write_frame_header(&mut frame);
+        let aad = header_aad(&frame, aad);
+        frame.extend_from_slice(&nonce_bytes);
+        let body_start = frame.len();
+        frame.extend_from_slice(plaintext);
+        let mask = self.config.mask();
+        let tag = self.seal_body_in_place(key, mask, &nonce_bytes, &mut frame[body_start..], &aad)?;
+        frame.extend_from_slice(&tag);
+        self.config.tag_position.place(&mut frame[body_start..]);
+        Ok(frame)
+    }
+
+    /// `SealMeta` of the finished `frame` sealing `plaintext_len` bytes,
+    /// timestamped by `clock`; `PlaintextTooShort` like `frame_sink`
+    pub(crate) fn frame_meta(
+        &self,
+        plaintext_len: usize,
+        frame: &[u8],
+        clock: &dyn Clock,
+    ) -> Result<SealMeta, SqepError> {
+        self.check_plaintext_len(plaintext_len)?;
+        let hasher = self.config.hash_meta.then(|| Sha256::new_with_prefix(frame));
+        Ok(seal_meta(hasher, clock))
+    }
+
+    /// `PlaintextTooShort` below the configured minimum plaintext length
+    pub(crate) fn check_plaintext_len(&self, len: usize) -> Result<(), SqepError> {
+        let min = self.config.min_plaintext_len;
+        if len < min {
+            return Err(SqepError::PlaintextTooShort { len, min });
+        }
+        Ok(())
+    }
+
+    /// Same as `seal_frame`, with a caller-chosen (unique!) nonce
+    pub(crate) fn seal_frame_with_nonce(
+        &self,
+        nonce_bytes: [u8; NONCE_LEN],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        self.seal_frame_using(&self.aead_key()?, nonce_bytes, plaintext, aad)
+    }
+
+    /// XOR-mask and AEAD-seal `plaintext`, returning `CIPHERTEXT+TAG`
+    pub(crate) fn seal_body_using(
+        &self,
+        key: &AeadKey,
+        mask: MaskParams,
+        nonce_bytes: &[u8; NONCE_LEN],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        let mut body = Vec::with_capacity(plaintext.len() + TAG_LEN);
+        self.seal_body_into(key, mask, nonce_bytes, plaintext, aad, &mut body)?;
+        Ok(body)
+    }
+
+    /// `seal_body_using` into `out`, replacing its contents but keeping its
+    /// allocation
+    pub(crate) fn seal_body_into(
+        &self,
+        key: &AeadKey,
+        mask: MaskParams,
+        nonce_bytes: &[u8; NONCE_LEN],
+        plaintext: &[u8],
+        aad: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), SqepError> {
+        out.clear();
+        out.reserve(plaintext.len() + TAG_LEN);
+        out.extend_from_slice(plaintext);
+        let tag = self.seal_body_in_place(key, mask, nonce_bytes, out, aad)?;
+        out.extend_from_slice(&tag);
+        Ok(())
+    }
+
+    /// XOR-mask and AEAD-seal the plaintext in `body` in place, returning
+    /// the tag to go after it
+    pub(crate) fn seal_body_in_place(
+        &self,
+        key: &AeadKey,
+        mask: MaskParams,
+        nonce_bytes: &[u8; NONCE_LEN],
+        body: &mut [u8],
+        aad: &[u8],
+    ) -> Result<[u8; TAG_LEN], SqepError> {
+        let started = telemetry::seal_started();
+        // 1) KEYED and self-inverse xor transform (no data-dependent randomness)
+        qt_xor_in_place(body, &self.key, nonce_bytes, mask);
+
+        // 2) AEAD (ChaCha20-Poly1305 or the configured algorithm)
+        let tag = key.seal_in_place_detached(nonce_bytes, &self.body_aad(aad, body.len()), body)?;
+        telemetry::encrypted(started);
+        Ok(tag)
+    }
+
+    /// Open a `HEADER || NONCE || CIPHERTEXT+TAG` frame sealed with `aad`
+    pub(crate) fn open_frame(&self, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, SqepError> {
+        self.open_frame_using(&self.aead_key()?, ciphertext, aad)
+    }
+
+    /// `open_frame` with an already constructed AEAD key
+    pub(crate) fn open_frame_using(
+        &self,
+        key: &AeadKey,
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        telemetry::opened(self.open_frame_unmetered(key, ciphertext, aad))
+    }
+
+    fn open_frame_unmetered(
+        &self,
+        key: &AeadKey,
+        ciphertext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        // 1) Parse header, nonce and data, reassembling TLV frames first
+        let ciphertext = compact_frame(ciphertext)?;
+        let frame = Frame::decode(&ciphertext)?;
+        let aad = [frame.aad_prefix(), aad].concat();
+
+        // 2) Frames recording an algorithm must match ours; the rest are
+        //    always ChaCha20-Poly1305, so rebuild the key if it differs
+        self.check_algorithm(&frame)?;
+        if frame.version() == FormatVersion::V3_9 {
+            return self.open_legacy_body(frame.nonce(), frame.body(), &aad);
+        }
+        let body = frame.sealed_body();
+        if frame.algorithm() == key.algorithm() {
+            self.open_body_using(key, frame.mask(), frame.nonce(), &body, &aad)
+        } else {
+            let key = self.aead_key_for(frame.algorithm())?;
+            self.open_body_using(&key, frame.mask(), frame.nonce(), &body, &aad)
+        }
+    }
+
+    /// Open `frame`'s ciphertext, already copied into `buf`, in place and
+    /// return the plaintext length; `buf` is zeroed again on failure
+    fn open_in_place(
+        &self,
+        frame: &Frame<'_>,
+        tag: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, SqepError> {
+        let key = self.aead_key_for(frame.algorithm())?;
+        let aad = if frame.version() == FormatVersion::V3_9 {
+            Cow::Borrowed(frame.aad_prefix())
+        } else {
+            self.body_aad(frame.aad_prefix(), buf.len())
+        };
+        let opened = key
+            .open_in_place_detached(frame.nonce(), &aad, buf, tag)
+            .and_then(|()| {
+                if frame.version() != FormatVersion::V3_9 {
+                    qt_xor_in_place(buf, &self.key, frame.nonce(), frame.mask());
+                    return Ok(buf.len());
+                }
+                // Legacy bodies end in 16 zero padding bytes
+                buf.len()
+                    .checked_sub(TAG_LEN)
+                    .filter(|&len| buf[len..].iter().all(|&b| b == 0))
+                    .ok_or(SqepError::DecryptionFailed)
+            });
+        if opened.is_err() {
+            buf.fill(0);
+        }
+        telemetry::opened(opened)
+    }
+
+    /// `AadTooLarge` if caller-supplied `aad` is over the configured cap
+    pub(crate) fn check_aad_len(&self, aad: &[u8]) -> Result<(), SqepError> {
+        let max = self.config.max_aad_len;
+        if aad.len() > max {
+            return Err(SqepError::AadTooLarge { len: aad.len(), max });
+        }
+        Ok(())
+    }
+
+    /// `AlgorithmMismatch` unless an extended frame names our algorithm
+    fn check_algorithm(&self, frame: &Frame<'_>) -> Result<(), SqepError> {
+        if frame.version() >= FormatVersion::V4_1 && frame.algorithm() != self.config.algorithm {
+            return Err(SqepError::AlgorithmMismatch {
+                expected: self.config.algorithm,
+                found: frame.algorithm(),
+            });
+        }
+        Ok(())
+    }
+
+    /// `aad`, followed by `plaintext_len` if the length is bound
+    pub(crate) fn body_aad<'a>(&self, aad: &'a [u8], plaintext_len: usize) -> Cow<'a, [u8]> {
+        if !self.config.bind_length {
+            return Cow::Borrowed(aad);
+        }
+        Cow::Owned([aad, &(plaintext_len as u64).to_be_bytes()].concat())
+    }
+
+    /// AEAD-open `CIPHERTEXT+TAG` and reverse the XOR mask
+    pub(crate) fn open_body_using(
+        &self,
+        key: &AeadKey,
+        mask: MaskParams,
+        nonce_bytes: &[u8],
+        encrypted_data: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        // Working copy, wiped on drop whether or not it authenticates
+        let mut in_out = Zeroizing::new(encrypted_data.to_vec());
+        self.open_body_in(key, mask, nonce_bytes, &mut in_out, aad)
+    }
+
+    /// Open `in_out`, a copy of `CIPHERTEXT+TAG`, in place and return the
+    /// unmasked plaintext; `in_out` is zeroed if authentication fails
+    fn open_body_in(
+        &self,
+        key: &AeadKey,
+        mask: MaskParams,
+        nonce_bytes: &[u8],
+        in_out: &mut Vec<u8>,
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        // 3) AEAD open
+        let aad = self.body_aad(aad, in_out.len().saturating_sub(TAG_LEN));
+        if let Err(e) = key.open_in_place(nonce_bytes, &aad, in_out) {
+            in_out.fill(0);
+            return Err(e);
+        }
+
+        // 4) Reverse the KEYED xor transform (self-inverse) and return owned Vec<u8>
+        Ok(qt_xor_keyed(in_out, &self.key, nonce_bytes, mask))
+    }
+
+    /// Seal a `SQEP4.0-LFMT` frame carrying `mtime` (since the UNIX epoch)
+    fn seal_with_mtime(
+        &self,
+        plaintext: &[u8],
+        mtime: Duration,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let mut mtime_bytes = [0u8; MTIME_LEN];
+        mtime_bytes[..8].copy_from_slice(&mtime.as_secs().to_be_bytes());
+        mtime_bytes[8..].copy_from_slice(&mtime.subsec_nanos().to_be_bytes());
+        let aad = [FILE_META_DOMAIN, &mtime_bytes].concat();
+        let overhead = FILE_META_MAGIC.len() + MTIME_LEN + NONCE_LEN;
+        let mut sink = self.frame_sink(plaintext.len(), overhead)?;
+
+        let nonce_bytes = random_nonce();
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let body = self.seal_body_using(&key, MaskParams::DEFAULT, &nonce_bytes, plaintext, &aad)?;
+
+        sink.push(FILE_META_MAGIC);
+        sink.push(&mtime_bytes);
+        sink.push(&nonce_bytes);
+        sink.push(&body);
+        Ok(sink.finish(&*self.clock))
+    }
+
+    /// Open a file's contents: a `SQEP4.0-LFMT` frame (returning its
+    /// authenticated mtime) or any frame `decrypt` accepts
+    fn open_file_frame(&self, data: &[u8]) -> Result<(Vec<u8>, Option<SystemTime>), SqepError> {
+        if !data.starts_with(FILE_META_MAGIC) {
+            return Ok((self.decrypt(data)?, None));
+        }
+        let header_len = FILE_META_MAGIC.len() + MTIME_LEN;
+        if data.len() < header_len + NONCE_LEN + TAG_LEN {
+            return Err(SqepError::CiphertextTooShort);
+        }
+        let (header, rest) = data.split_at(header_len);
+        let mtime_bytes = &header[FILE_META_MAGIC.len()..];
+        let aad = [FILE_META_DOMAIN, mtime_bytes].concat();
+
+        let (nonce_bytes, encrypted_data) = rest.split_at(NONCE_LEN);
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let plaintext = self.open_body_using(
+            &key,
+            MaskParams::DEFAULT,
+            nonce_bytes,
+            encrypted_data,
+            &aad,
+        )?;
+
+        let mut secs = [0u8; 8];
+        secs.copy_from_slice(&mtime_bytes[..8]);
+        let mut nanos = [0u8; 4];
+        nanos.copy_from_slice(&mtime_bytes[8..]);
+        let mtime = Duration::new(u64::from_be_bytes(secs), u32::from_be_bytes(nanos));
+        Ok((plaintext, UNIX_EPOCH.checked_add(mtime)))
+    }
+
+    /// AEAD-open a `SQEP3.9` body (no XOR mask) and strip its padding.
+    ///
+    /// The old encoder sealed 16 zero bytes after every plaintext (room it
+    /// reserved for the tag). Requiring them also stops a 4.0 frame, whose
+    /// magic isn't authenticated, from being relabelled as 3.9 to get its
+    /// still-masked bytes accepted.
+    fn open_legacy_body(
+        &self,
+        nonce_bytes: &[u8],
+        encrypted_data: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SqepError> {
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305)?;
+        let mut plaintext = encrypted_data.to_vec();
+        let opened = key.open_in_place(nonce_bytes, aad, &mut plaintext).and_then(|()| {
+            plaintext
+                .len()
+                .checked_sub(TAG_LEN)
+                .filter(|&len| plaintext[len..].iter().all(|&b| b == 0))
+                .ok_or(SqepError::DecryptionFailed)
+        });
+        match opened {
+            Ok(unpadded) => {
+                plaintext.truncate(unpadded);
+                Ok(plaintext)
+            }
+            // Bad padding means the body authenticated: wipe the plaintext
+            Err(e) => {
+                plaintext.zeroize();
+                Err(e)
+            }
+        }
+    }
+
+    /// Deterministic nonce for content-addressed frames
+    fn content_nonce(&self, plaintext: &[u8]) -> [u8; NONCE_LEN] {
+        let digest = Sha256::digest(plaintext);
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, CAS_DOMAIN).extract(&self.key);
+        let info = [&digest[..]];
+        let okm = prk.expand(&info, OkmLen(NONCE_LEN)).expect("HKDF expand (nonce)");
+
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        okm.fill(&mut nonce_bytes).expect("HKDF fill (nonce)");
+        nonce_bytes
+    }
+
+    /// AEAD key handle for this cipher's algorithm
+    pub(crate) fn aead_key(&self) -> Result<AeadKey, SqepError> {
+        self.aead_key_for(self.config.algorithm)
+    }
+
+    /// AEAD key handle for a specific algorithm
+    pub(crate) fn aead_key_for(&self, algorithm: Algorithm) -> Result<AeadKey, SqepError> {
+        AeadKey::new(algorithm, &self.key)
+    }
+}
+
+impl Default for ZeroshieldCipher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `a == b` in time independent of where they differ (not of their lengths)
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// AAD actually sealed: plain `aad` for 4.0 frames, `HEADER || aad` for
+/// extended frames (so the algorithm byte can't be swapped)
+pub(crate) fn header_aad(header: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(header.len() + aad.len());
+    header_aad_into(header, aad, &mut out);
+    out
+}
+
+/// `header_aad` into `out`, replacing its contents but keeping its
+/// allocation
+pub(crate) fn header_aad_into(header: &[u8], aad: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    if header != HEADER_MAGIC {
+        out.extend_from_slice(header);
+    }
+    out.extend_from_slice(aad);
+}
+
+/// Read a whole file, refusing it up front if it is over `max` bytes
+fn read_capped(path: &str, max: u64) -> std::io::Result<Vec<u8>> {
+    let size = fs::metadata(path)?.len();
+    if size > max {
+        let err = SqepError::FileTooLarge { size, max };
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, err));
+    }
+    fs::read(path)
+}
+
+/// Output length for `ring` HKDF expansions of arbitrary size
+pub(crate) struct OkmLen(pub(crate) usize);
+
+impl hkdf::KeyType for OkmLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// True if `encoded` is a key `import_key_base64` would accept
+#[cfg(feature = "encoding")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encoding")))]
+pub fn looks_like_key_base64(encoded: &str) -> bool {
+    decode_key_base64(encoded).is_ok()
+}
+
+#[cfg(feature = "encoding")]
+fn decode_key_base64(encoded: &str) -> Result<Zeroizing<[u8; KEY_LEN]>, SqepError> {
+    let encoded = encoded.trim();
+    if looks_like_frame(encoded.as_bytes()) {
+        return Err(SqepError::NotAKey);
+    }
+    let decoded = Zeroizing::new(STANDARD.decode(encoded).map_err(|_| SqepError::InvalidBase64)?);
+    if looks_like_frame(&decoded) {
+        return Err(SqepError::NotAKey);
+    }
+    if decoded.len() != KEY_LEN {
+        return Err(SqepError::InvalidKeyLength { len: decoded.len() });
+    }
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    key.copy_from_slice(&decoded);
+    Ok(key)
+}
+
+/// Fresh random nonce from the system CSPRNG.
+///
+/// `SystemRandom` is a zero-sized handle to the OS generator, so building
+/// one per call costs nothing; `benches/nonce_rng.rs` measures no
+/// difference against a shared one.
+pub(crate) fn random_nonce() -> [u8; NONCE_LEN] {
+    let rng = SystemRandom::new();
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce_bytes).expect("Nonce generation failed");
+    nonce_bytes
+}
+
+/// Frame buffer that feeds every appended part into SHA-256, so the
+/// `SealMeta.hash` is ready without a second pass over the frame.
+pub(crate) struct FrameSink {
+    frame: Vec<u8>,
+    /// `None` when `SealMeta` hashing is disabled
+    hasher: Option<Sha256>,
+}
+
+impl FrameSink {
+    pub(crate) fn with_capacity(capacity: usize, hash: bool) -> Self {
+        Self {
+            frame: Vec::with_capacity(capacity),
+            hasher: hash.then(Sha256::new),
+        }
+    }
+
+    /// Append the next part of the frame
+    pub(crate) fn push(&mut self, part: &[u8]) {
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(part);
+        }
+        self.frame.extend_from_slice(part);
+    }
+
+    /// Finish the frame and build its metadata, timestamped by `clock`
+    pub(crate) fn finish(self, clock: &dyn Clock) -> (Vec<u8>, SealMeta) {
+        (self.frame, seal_meta(self.hasher, clock))
+    }
+}
+
+/// `Write` adapter feeding everything written into SHA-256, so a frame
+/// streamed to disk gets its `SealMeta.hash` without being read back
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Option<Sha256>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W, hash: bool) -> Self {
+        Self {
+            inner,
+            hasher: hash.then(Sha256::new),
+        }
+    }
+
+    /// The inner writer and the metadata of what went through it
+    fn finish(self, clock: &dyn Clock) -> (W, SealMeta) {
+        (self.inner, seal_meta(self.hasher, clock))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn seal_meta(hasher: Option<Sha256>, clock: &dyn Clock) -> SealMeta {
+    SealMeta {
+        timestamp: clock.now_unix_secs(),
+        hash: hasher.map_or_else(String::new, |h| format!("{:x}", h.finalize())),
+    }
+}
+
+// ---------------------------------------------------------------------
+// Keyed, self-inverse XOR transform (Lite)
+// ---------------------------------------------------------------------
+
+fn qt_xor_keyed(data: &[u8], key32: &[u8; KEY_LEN], nonce12: &[u8], mask: MaskParams) -> Vec<u8> {
+    let mut out = data.to_vec();
+    qt_xor_in_place(&mut out, key32, nonce12, mask);
+    out
+}
+
+/// Same mask as `qt_xor_keyed`, applied in place without allocating
+pub(crate) fn qt_xor_in_place(
+    data: &mut [u8],
+    key32: &[u8; KEY_LEN],
+    nonce12: &[u8],
+    mask: MaskParams,
+) {
+    // HKDF(salt=nonce, ikm=key, info=QT domain) -> 32-byte seed, whatever the hash
+    let seed = kdf::xor_seed_using(key32, nonce12, mask.hash);
+    match mask.strength {
+        MaskStrength::ChaCha20 => xor_mask(data, &mut ChaCha20Rng::from_seed(seed)),
+        MaskStrength::ChaCha8 => xor_mask(data, &mut ChaCha8Rng::from_seed(seed)),
+    }
+}
+
+/// Keystream bytes generated per `fill_bytes` call
+const MASK_BLOCK: usize = 4096;
+
+/// XOR `data` with the RNG's keystream: its `u32` outputs in order, each as
+/// little-endian bytes (what `fill_bytes` produces). Whole `u64` words are
+/// XORed at a time so the loop vectorizes, with a bytewise tail.
+fn xor_mask(data: &mut [u8], rng: &mut impl RngCore) {
+    let mut keystream = [0u8; MASK_BLOCK];
+    for block in data.chunks_mut(MASK_BLOCK) {
+        // Only the last block can be short, so no keystream is skipped
+        let ks = &mut keystream[..block.len()];
+        rng.fill_bytes(ks);
+
+        let mut words = block.chunks_exact_mut(8);
+        let mut ks_words = ks.chunks_exact(8);
+        for (word, k) in (&mut words).zip(&mut ks_words) {
+            let masked = u64::from_ne_bytes((&*word).try_into().expect("8-byte word"))
+                ^ u64::from_ne_bytes(k.try_into().expect("8-byte word"));
+            word.copy_from_slice(&masked.to_ne_bytes());
+        }
+        let tail = words.into_remainder();
+        tail.iter_mut().zip(ks_words.remainder()).for_each(|(b, k)| *b ^= k);
+    }
+    keystream.zeroize();
+}
+
+// ---------------------------------------------------------------------
+// Backward-compat shims (deprecated): previously exported helpers
+// Now they are identity transforms to avoid data-dependent XOR pitfalls.
+// ---------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::frame::{KDF_EXT_MAGIC, LEGACY_MAGIC, MAGIC_LEN, MASK_EXT_MAGIC, TAG_EXT_MAGIC};
+
+    #[test]
+    fn word_wise_mask_matches_the_u32_keystream() {
+        let key = [3u8; KEY_LEN];
+        let nonce = [8u8; NONCE_LEN];
+        for len in [0, 1, 7, 8, 13, MASK_BLOCK - 1, MASK_BLOCK + 5, 3 * MASK_BLOCK] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let mut rng = ChaCha20Rng::from_seed(kdf::derive_xor_seed(&key, &nonce));
+            let mut word = [0u8; 4];
+            let expected: Vec<u8> = data
+                .iter()
+                .enumerate()
+                .map(|(i, b)| {
+                    if i % 4 == 0 {
+                        word = rng.next_u32().to_le_bytes();
+                    }
+                    b ^ word[i % 4]
+                })
+                .collect();
+            let masked = qt_xor_keyed(&data, &key, &nonce, MaskParams::DEFAULT);
+            assert_eq!(masked, expected, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn fingerprint_lengths() {
+        let cipher = ZeroshieldCipher::from_key([3u8; KEY_LEN]);
+        let full = cipher.fingerprint_n(32);
+
+        assert_eq!(full.len(), 64);
+        assert_eq!(cipher.fingerprint_n(4), full[..8]);
+        assert_eq!(cipher.fingerprint_n(6), full[..12]);
+        assert_eq!(cipher.fingerprint(), cipher.fingerprint_n(6));
+        assert_eq!(cipher.fingerprint_n(100), full);
+    }
+
+    #[test]
+    fn encrypt_checked_refuses_frames() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, _m) = cipher.encrypt_checked(b"plain input").expect("plain input is accepted");
+        assert_eq!(cipher.encrypt_checked(&ct).unwrap_err(), SqepError::LikelyDoubleEncrypt);
+        assert!(cipher.encrypt_with_meta(&ct).is_ok());
+    }
+
+    #[test]
+    fn byte_inputs_accept_common_containers() {
+        let cipher = ZeroshieldCipher::new();
+        let (from_array, _m) = cipher.encrypt_with_meta(*b"owned").expect("array");
+        let owned = b"owned".to_vec();
+        let (from_vec, _m) = cipher.encrypt_with_meta(owned).expect("vec");
+        let (from_ref, _m) = cipher.encrypt_checked(&b"owned"[..]).expect("slice");
+        let (from_zeroizing, _m) =
+            cipher.encrypt_with_meta(Zeroizing::new(b"owned".to_vec())).expect("zeroizing");
+
+        for frame in [from_array, from_vec.clone(), from_ref, from_zeroizing] {
+            assert_eq!(cipher.decrypt(&frame).unwrap(), b"owned");
+            assert_eq!(cipher.decrypt_utf8(frame.as_slice()).unwrap(), "owned");
+            assert_eq!(cipher.decrypt_utf8_lossy(frame).unwrap(), "owned");
+        }
+
+        // Paired APIs take the same containers on both sides
+        let tail = b"tail".to_vec();
+        let (trailed, _m) = cipher.encrypt_with_trailer(*b"body", tail).expect("trailer");
+        assert_eq!(cipher.decrypt_with_trailer(trailed.clone()).unwrap().1, b"tail");
+        assert!(cipher.same_content(from_vec.clone(), from_vec.as_slice()).unwrap());
+        assert_eq!(cipher.decrypt_strict(from_vec.clone()).unwrap(), b"owned");
+        assert_eq!(cipher.decrypt_bounded(Zeroizing::new(from_vec.clone()), 5).unwrap(), b"owned");
+        #[cfg(feature = "bytes")]
+        assert_eq!(cipher.decrypt(bytes::Bytes::from(from_vec)).unwrap(), b"owned");
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn seal_meta_serializes() {
+        let (_ct, meta) = ZeroshieldCipher::new().encrypt_with_meta(b"meta").expect("encrypt");
+        let json = serde_json::to_string(&meta).expect("serialize");
+        let back: SealMeta = serde_json::from_str(&json).expect("deserialize");
+        assert_eq!(back, meta);
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn decrypt_auto_accepts_raw_and_base64_frames() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, _m) = cipher.encrypt_with_meta(b"mixed store").expect("encrypt");
+        assert_eq!(cipher.decrypt_auto(&ct).unwrap(), b"mixed store");
+
+        let wrapped = format!("{}\n", STANDARD.encode(&ct));
+        assert_eq!(cipher.decrypt_auto(wrapped.as_bytes()).unwrap(), b"mixed store");
+
+        assert_eq!(cipher.decrypt_auto(b"not a frame!"), Err(SqepError::UnrecognizedInput));
+        let b64_garbage = STANDARD.encode(b"valid base64, no magic");
+        assert_eq!(cipher.decrypt_auto(b64_garbage.as_bytes()), Err(SqepError::UnrecognizedInput));
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn key_import_rejects_frames_and_wrong_lengths() {
+        let original = ZeroshieldCipher::new();
+        let exported = format!("{}\n", original.export_key_base64());
+        assert!(looks_like_key_base64(&exported));
+        let restored = ZeroshieldCipher::import_key_base64(&exported).expect("import");
+        assert_eq!(restored.key_bytes(), original.key_bytes());
+
+        let import_err = |encoded: &str| ZeroshieldCipher::import_key_base64(encoded).err();
+        let (frame, _m) = original.encrypt_with_meta(b"").expect("encrypt");
+        let frame_b64 = STANDARD.encode(&frame);
+        assert!(!looks_like_key_base64(&frame_b64));
+        assert_eq!(import_err(&frame_b64), Some(SqepError::NotAKey));
+        assert_eq!(import_err("SQEP4.0-LITE..."), Some(SqepError::NotAKey));
+
+        let mut random = [0u8; 48];
+        SystemRandom::new().fill(&mut random).expect("rng");
+        let wrong_len = Some(SqepError::InvalidKeyLength { len: 48 });
+        assert_eq!(import_err(&STANDARD.encode(random)), wrong_len);
+        assert_eq!(import_err("not base64!"), Some(SqepError::InvalidBase64));
+    }
+
+    #[test]
+    fn decrypt_into_slice_needs_room_for_the_plaintext() {
+        let cipher = ZeroshieldCipher::new().with_kdf_hash(KdfHash::Sha512);
+        let (ct, _m) = cipher.encrypt_with_meta(b"on the stack").expect("encrypt");
+
+        let mut exact = [0u8; 12];
+        assert_eq!(cipher.decrypt_into_slice(&ct, &mut exact).unwrap(), 12);
+        assert_eq!(&exact, b"on the stack");
+
+        let mut short = [0u8; 11];
+        let err = cipher.decrypt_into_slice(&ct, &mut short).unwrap_err();
+        assert_eq!(err, SqepError::BufferTooSmall { needed: 12 });
+
+        let mut tampered = ct.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let mut out = [0xAAu8; 32];
+        let err = cipher.decrypt_into_slice(&tampered, &mut out).unwrap_err();
+        assert_eq!(err, SqepError::DecryptionFailed);
+        assert!(out[..12].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn decrypt_owned_keeps_the_frame_buffer() {
+        let cipher = ZeroshieldCipher::new().with_kdf_hash(KdfHash::Sha384);
+        let (ct, _m) = cipher.encrypt_with_meta(b"opened in place").expect("encrypt");
+        let frame = ct.clone();
+        let capacity = frame.capacity();
+        let pt = cipher.decrypt_owned(frame).unwrap();
+        assert_eq!(pt, b"opened in place");
+        assert_eq!(pt.capacity(), capacity);
+
+        let mut tampered = ct;
+        tampered[MAGIC_LEN + 2] ^= 1;
+        assert_eq!(cipher.decrypt_owned(tampered), Err(SqepError::DecryptionFailed));
+        assert_eq!(cipher.decrypt_owned(b"SQEP".to_vec()), Err(SqepError::CiphertextTooShort));
+    }
+
+    #[test]
+    fn seal_meta_dedups_in_a_set() {
+        let meta = |timestamp, hash: &str| SealMeta {
+            timestamp,
+            hash: hash.to_owned(),
+        };
+        let set: std::collections::HashSet<_> =
+            [meta(1, "ab"), meta(1, "ab"), meta(1, "cd"), meta(2, "ab")].into_iter().collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn dry_run_matches_real_frames() {
+        let default = ZeroshieldCipher::new();
+        let extended = ZeroshieldCipher::new().with_kdf_hash(KdfHash::Sha384);
+        for cipher in [&default, &extended] {
+            for len in [0, 1, 15, 16, 1000, 65_536] {
+                let report = cipher.encrypt_dry_run(len);
+                let (ct, _m) = cipher.encrypt_with_meta(vec![0u8; len]).expect("encrypt");
+                assert_eq!(report.ciphertext_len, ct.len(), "len {}", len);
+                assert_eq!(report.overhead, ct.len() - len);
+                assert_eq!(report.tag_len, TAG_LEN);
+            }
+        }
+    }
+
+    #[test]
+    fn every_kdf_hash_roundtrips() {
+        let key = [4u8; KEY_LEN];
+        for hash in [KdfHash::Sha256, KdfHash::Sha384, KdfHash::Sha512] {
+            let cipher = ZeroshieldCipher::from_key(key).with_kdf_hash(hash);
+            let (ct, _m) = cipher.encrypt_with_meta(b"masked under").expect("encrypt");
+            assert_eq!(ct.len(), cipher.frame_overhead() + 12);
+            assert_eq!(Frame::decode(&ct).unwrap().kdf_hash(), hash);
+
+            // The frame names its hash, so a default cipher opens it too
+            assert_eq!(ZeroshieldCipher::from_key(key).decrypt(&ct).unwrap(), b"masked under");
+        }
+        let (ct, _m) = ZeroshieldCipher::from_key(key).encrypt_with_meta(b"x").unwrap();
+        assert!(ct.starts_with(HEADER_MAGIC));
+    }
+
+    #[test]
+    fn swapped_kdf_hash_fails() {
+        let cipher = ZeroshieldCipher::new().with_kdf_hash(KdfHash::Sha384);
+        let (mut ct, _m) = cipher.encrypt_with_meta(b"hash bound via AAD").expect("encrypt");
+        assert!(ct.starts_with(KDF_EXT_MAGIC));
+
+        ct[MAGIC_LEN + 1] = KdfHash::Sha512.id();
+        assert_eq!(cipher.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[cfg(feature = "sha3")]
+    #[test]
+    fn sha3_frames_roundtrip_and_bind_their_hash() {
+        let key = [4u8; KEY_LEN];
+        let cipher = ZeroshieldCipher::from_key(key).with_kdf_hash(KdfHash::Sha3_256);
+        let (mut ct, _m) = cipher.encrypt_with_meta(b"standardized on SHA-3").expect("encrypt");
+        assert!(ct.starts_with(KDF_EXT_MAGIC));
+        assert_eq!(Frame::decode(&ct).unwrap().kdf_hash(), KdfHash::Sha3_256);
+        let plain = ZeroshieldCipher::from_key(key);
+        assert_eq!(plain.decrypt(&ct).unwrap(), b"standardized on SHA-3");
+
+        ct[MAGIC_LEN + 1] = KdfHash::Sha256.id();
+        assert_eq!(cipher.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[cfg(not(feature = "sha3"))]
+    #[test]
+    fn sha3_frames_need_the_feature() {
+        let cipher = ZeroshieldCipher::new().with_kdf_hash(KdfHash::Sha384);
+        let (mut ct, _m) = cipher.encrypt_with_meta(b"x").expect("encrypt");
+        ct[MAGIC_LEN + 1] = 0x04;
+        assert_eq!(cipher.decrypt(&ct), Err(SqepError::UnsupportedAlgorithm(0x04)));
+    }
+
+    #[test]
+    fn every_mask_strength_roundtrips() {
+        let key = [4u8; KEY_LEN];
+        for strength in [MaskStrength::ChaCha20, MaskStrength::ChaCha8] {
+            for hash in [KdfHash::Sha256, KdfHash::Sha384] {
+                let cipher = ZeroshieldCipher::from_key(key)
+                    .with_kdf_hash(hash)
+                    .with_mask_strength(strength);
+                let (ct, _m) = cipher.encrypt_with_meta(b"masked under").expect("encrypt");
+                assert_eq!(ct.len(), cipher.frame_overhead() + 12);
+                let frame = Frame::decode(&ct).unwrap();
+                assert_eq!((frame.mask_strength(), frame.kdf_hash()), (strength, hash));
+
+                // The frame names its generator, so a default cipher opens it too
+                let plain = ZeroshieldCipher::from_key(key);
+                assert_eq!(plain.decrypt(&ct).unwrap(), b"masked under");
+            }
+        }
+
+        let fast = ZeroshieldCipher::new().with_mask_strength(MaskStrength::ChaCha8);
+        let (mut ct, _m) = fast.encrypt_with_meta(b"strength bound via AAD").expect("encrypt");
+        assert!(ct.starts_with(MASK_EXT_MAGIC));
+        ct[MAGIC_LEN + 2] = MaskStrength::ChaCha20.id();
+        assert_eq!(fast.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn every_tag_position_roundtrips() {
+        let key = [4u8; KEY_LEN];
+        for position in [TagPosition::Append, TagPosition::Prepend] {
+            let cipher = ZeroshieldCipher::from_key(key).with_tag_position(position);
+            let (ct, _m) = cipher.encrypt_with_meta(b"tag placement").expect("encrypt");
+            assert_eq!(ct.len(), cipher.frame_overhead() + 13);
+            assert_eq!(Frame::decode(&ct).unwrap().tag_position(), position);
+
+            // The frame records the position, so a default cipher follows it
+            let plain = ZeroshieldCipher::from_key(key);
+            assert_eq!(plain.decrypt(&ct).unwrap(), b"tag placement");
+            assert_eq!(plain.decrypt_owned(ct.clone()).unwrap(), b"tag placement");
+            let mut out = [0u8; 13];
+            assert_eq!(plain.decrypt_into_slice(&ct, &mut out), Ok(13));
+            assert_eq!(&out, b"tag placement");
+        }
+    }
+
+    #[test]
+    fn prepended_tag_leads_the_body_and_is_bound() {
+        let cipher = ZeroshieldCipher::new().with_tag_position(TagPosition::Prepend);
+        let (mut ct, _m) = cipher.encrypt_with_meta(b"tag first").expect("encrypt");
+        assert!(ct.starts_with(TAG_EXT_MAGIC));
+        let frame = Frame::decode(&ct).unwrap();
+        assert_eq!(frame.tag().unwrap(), frame.body()[..TAG_LEN]);
+
+        ct[MAGIC_LEN + 3] = TagPosition::Append.id();
+        assert_eq!(cipher.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn default_mask_is_the_20_round_generator() {
+        // Changing the default generator changes every mask: do it on purpose
+        let (key, nonce) = ([0u8; KEY_LEN], [0u8; NONCE_LEN]);
+        let mask = qt_xor_keyed(&[0u8; 16], &key, &nonce, MaskParams::DEFAULT);
+        let mut chacha20 = [0u8; 16];
+        ChaCha20Rng::from_seed(kdf::derive_xor_seed(&key, &nonce)).fill_bytes(&mut chacha20);
+        assert_eq!(mask, chacha20);
+
+        // ...and `ChaCha20Rng` is the 20-round ChaCha of ChaCha20-Poly1305
+        // (RFC 8439 A.1 test vector #1: all-zero key, nonce and counter)
+        let mut block = [0u8; 16];
+        ChaCha20Rng::from_seed([0u8; 32]).fill_bytes(&mut block);
+        let block: String = block.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(block, "76b8e0ada0f13d90405d6ae55386bd28");
+
+        let fast = MaskParams { strength: MaskStrength::ChaCha8, ..MaskParams::DEFAULT };
+        assert_ne!(qt_xor_keyed(&[0u8; 16], &key, &nonce, fast), mask);
+    }
+
+    #[test]
+    fn roundtrip_exact_len() {
+        let cipher = ZeroshieldCipher::new();
+        let msg = b"hello quantum world!";
+        let (ct, _m) = cipher.encrypt_with_meta(msg).expect("encrypt");
+        let pt = cipher.decrypt(&ct).expect("decrypt");
+        assert_eq!(pt, msg, "roundtrip mismatch");
+        // Also check UTF-8 path
+        let s = cipher.decrypt_utf8(&ct).expect("utf8");
+        assert_eq!(s.as_bytes(), msg);
+    }
+
+    #[test]
+    fn same_content_compares_plaintexts_under_the_key() {
+        let cipher = ZeroshieldCipher::new();
+        let seal = |msg: &[u8]| cipher.encrypt_with_meta(msg).expect("encrypt").0;
+        let (a, b) = (seal(b"same bytes"), seal(b"same bytes"));
+        assert_ne!(a, b);
+        assert_eq!(cipher.same_content(&a, &b), Ok(true));
+        assert_eq!(cipher.same_content(&a, seal(b"same bytez")), Ok(false));
+        assert_eq!(cipher.same_content(&a, seal(b"same")), Ok(false));
+
+        let other = ZeroshieldCipher::new();
+        assert_eq!(other.same_content(&a, &b), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn failing_rng_is_an_error_not_a_panic() {
+        let failed = ZeroshieldCipher::generate_with(|_| Err(ring::error::Unspecified));
+        assert_eq!(failed.err(), Some(SqepError::RngUnavailable));
+
+        let fixed = ZeroshieldCipher::generate_with(|key| {
+            key.fill(7);
+            Ok(())
+        });
+        assert_eq!(fixed.expect("generate").key_bytes(), &[7u8; KEY_LEN]);
+        assert_ne!(ZeroshieldCipher::try_new().unwrap().key_bytes(), &[0u8; KEY_LEN]);
+    }
+
+    #[test]
+    fn lossy_utf8_replaces_invalid_bytes_but_not_bad_tags() {
+        let cipher = ZeroshieldCipher::new();
+        let (mut ct, _m) = cipher.encrypt_with_meta(b"caf\xc3 au \xfflait").expect("encrypt");
+        assert_eq!(cipher.decrypt_utf8(&ct), Err(SqepError::InvalidUtf8));
+        assert_eq!(cipher.decrypt_utf8_lossy(&ct).unwrap(), "caf\u{fffd} au \u{fffd}lait");
+
+        *ct.last_mut().unwrap() ^= 1;
+        assert_eq!(cipher.decrypt_utf8_lossy(&ct), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn trailer_roundtrip_and_tamper() {
+        let cipher = ZeroshieldCipher::new();
+        let (mut ct, _m) = cipher.encrypt_with_trailer(b"payload", b"v1.2").expect("encrypt");
+
+        let (pt, trailer) = cipher.decrypt_with_trailer(&ct).expect("decrypt");
+        assert_eq!(pt, b"payload");
+        assert_eq!(trailer, b"v1.2");
+
+        // Flip a trailer byte (just before the 4-byte length)
+        let idx = ct.len() - LEN_SIZE - 1;
+        ct[idx] ^= 0x01;
+        assert_eq!(cipher.decrypt_with_trailer(&ct), Err(SqepError::DecryptionFailed));
+
+        // The trailer is AAD, capped like the context AAD on both sides
+        let capped = cipher.clone().with_max_aad_len(3);
+        let too_large = SqepError::AadTooLarge { len: 4, max: 3 };
+        assert_eq!(capped.encrypt_with_trailer(b"payload", b"v1.2").unwrap_err(), too_large);
+        let (ct, _m) = cipher.encrypt_with_trailer(b"payload", b"v1.2").expect("encrypt");
+        assert_eq!(capped.decrypt_with_trailer(&ct), Err(too_large));
+    }
+
+    #[test]
+    fn batch_roundtrip_with_unique_nonces() {
+        let cipher = ZeroshieldCipher::new();
+        let msgs: Vec<Vec<u8>> = (0..500u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        let refs: Vec<&[u8]> = msgs.iter().map(Vec::as_slice).collect();
+
+        let sealed = cipher.encrypt_many(&refs).expect("encrypt");
+        let nonces: std::collections::HashSet<&[u8]> = sealed
+            .iter()
+            .map(|(ct, _)| &ct[HEADER_MAGIC.len()..HEADER_MAGIC.len() + NONCE_LEN])
+            .collect();
+        assert_eq!(nonces.len(), msgs.len());
+
+        let frames: Vec<&[u8]> = sealed.iter().map(|(ct, _)| ct.as_slice()).collect();
+        let opened = cipher.decrypt_many(&frames).expect("decrypt");
+        for (pt, msg) in opened.into_iter().zip(&msgs) {
+            assert_eq!(&pt.expect("frame"), msg);
+        }
+    }
+
+    #[test]
+    fn try_keys_finds_second_key() {
+        let keys = [ZeroshieldCipher::new(), ZeroshieldCipher::new(), ZeroshieldCipher::new()];
+        let (ct, _m) = keys[1].encrypt_with_meta(b"migrated").expect("encrypt");
+
+        let (index, pt) = ZeroshieldCipher::decrypt_try_keys(&ct, &keys).expect("decrypt");
+        assert_eq!((index, pt.as_slice()), (1, &b"migrated"[..]));
+
+        let (index, _) = ZeroshieldCipher::decrypt_try_keys_with(&ct, &keys, true).expect("decrypt");
+        assert_eq!(index, 1);
+
+        let stranger = ZeroshieldCipher::new().encrypt_with_meta(b"x").expect("encrypt").0;
+        assert_eq!(
+            ZeroshieldCipher::decrypt_try_keys(&stranger, &keys),
+            Err(SqepError::NoKeyMatched)
+        );
+    }
+
+    #[test]
+    fn content_addressed_is_deterministic() {
+        let cipher = ZeroshieldCipher::new();
+        let (a, meta_a) = cipher.encrypt_content_addressed(b"same blob").expect("encrypt");
+        let (b, meta_b) = cipher.encrypt_content_addressed(b"same blob").expect("encrypt");
+        let (c, _) = cipher.encrypt_content_addressed(b"other blob").expect("encrypt");
+
+        assert_eq!(a, b);
+        assert_eq!(meta_a.hash, meta_b.hash);
+        assert_ne!(a, c);
+        assert!(a.starts_with(CAS_MAGIC));
+        assert_eq!(cipher.decrypt(&a).expect("decrypt"), b"same blob");
+
+        // Relabelling as a regular frame breaks authentication
+        let mut relabelled = a.clone();
+        relabelled[..HEADER_MAGIC.len()].copy_from_slice(HEADER_MAGIC);
+        assert_eq!(cipher.decrypt(&relabelled), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn reader_and_writer_file_helpers() {
+        let cipher = ZeroshieldCipher::new();
+        let path = std::env::temp_dir().join(format!("sqep-lite-{}.seal", nanoid::nanoid!()));
+        let path = path.to_str().expect("utf-8 temp path");
+
+        let mut input: &[u8] = b"piped through io traits";
+        cipher.encrypt_from_reader(&mut input, path).expect("encrypt");
+
+        let mut out: Vec<u8> = Vec::new();
+        cipher.decrypt_to_writer(path, &mut out).expect("decrypt");
+        assert_eq!(out, b"piped through io traits");
+
+        fs::remove_file(path).expect("cleanup");
+    }
+
+    #[test]
+    fn oversized_files_are_refused_before_reading() {
+        let cipher = ZeroshieldCipher::new();
+        let base = std::env::temp_dir().join(format!("sqep-lite-{}", nanoid::nanoid!()));
+        let (src, sealed) = (base.with_extension("src"), base.with_extension("seal"));
+        let (src, sealed) = (src.to_str().unwrap(), sealed.to_str().unwrap());
+        fs::write(src, [0u8; 100]).expect("write source");
+
+        let err = cipher.encrypt_file_limited(src, sealed, 99).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let inner = err.into_inner().unwrap().downcast::<SqepError>().unwrap();
+        assert_eq!(*inner, SqepError::FileTooLarge { size: 100, max: 99 });
+        assert!(fs::metadata(sealed).is_err());
+
+        cipher.encrypt_file_limited(src, sealed, 100).expect("at the cap");
+        assert_eq!(cipher.decrypt(fs::read(sealed).unwrap()).unwrap(), [0u8; 100]);
+        fs::remove_file(src).expect("cleanup");
+        fs::remove_file(sealed).expect("cleanup");
+    }
+
+    #[test]
+    fn verify_file_catches_a_flipped_byte_in_either_format() {
+        let cipher = ZeroshieldCipher::new();
+        let base = std::env::temp_dir().join(format!("sqep-lite-{}", nanoid::nanoid!()));
+        let (frame, stream) = (base.with_extension("seal"), base.with_extension("strm"));
+        let (frame, stream) = (frame.to_str().unwrap(), stream.to_str().unwrap());
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(frame, cipher.encrypt_with_meta(&data).expect("encrypt").0).expect("write");
+        let cancel = std::sync::atomic::AtomicBool::new(false);
+        cipher.encrypt_stream_to_file(&mut &data[..], stream, &cancel).expect("encrypt stream");
+
+        for path in [frame, stream] {
+            cipher.verify_file(path).expect("intact file verifies");
+            let mut bytes = fs::read(path).unwrap();
+            let middle = bytes.len() / 2;
+            bytes[middle] ^= 0x10;
+            fs::write(path, &bytes).unwrap();
+            assert_eq!(cipher.verify_file(path), Err(SqepError::DecryptionFailed));
+            assert!(ZeroshieldCipher::new().verify_file(path).is_err());
+            fs::remove_file(path).expect("cleanup");
+        }
+    }
+
+    #[test]
+    fn file_hash_is_computed_while_writing() {
+        let cipher = ZeroshieldCipher::new();
+        let base = std::env::temp_dir().join(format!("sqep-lite-{}", nanoid::nanoid!()));
+        let (src, sealed) = (base.with_extension("src"), base.with_extension("seal"));
+        let (src, sealed) = (src.to_str().unwrap(), sealed.to_str().unwrap());
+        let data: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 253) as u8).collect();
+        fs::write(src, &data).expect("write source");
+
+        let meta = cipher.encrypt_file(src, sealed).expect("encrypt");
+        let frame = fs::read(sealed).expect("read frame");
+        assert_eq!(meta.hash, format!("{:x}", Sha256::digest(&frame)));
+        assert_eq!(cipher.decrypt(&frame).unwrap(), data);
+
+        let strict = cipher.with_min_plaintext_len(1);
+        assert!(strict.encrypt_from_reader(&mut &b""[..], sealed).is_err());
+        fs::remove_file(src).expect("cleanup");
+        fs::remove_file(sealed).expect("cleanup");
+    }
+
+    #[test]
+    fn file_mtime_is_preserved_and_authenticated() {
+        let cipher = ZeroshieldCipher::new();
+        let base = std::env::temp_dir().join(format!("sqep-lite-{}", nanoid::nanoid!()));
+        let (src, sealed, restored) = (
+            base.with_extension("src"),
+            base.with_extension("seal"),
+            base.with_extension("out"),
+        );
+        fs::write(&src, b"backup me").expect("write source");
+        let original = FileTime::from_unix_time(1_600_000_000, 123_456_000);
+        filetime::set_file_mtime(&src, original).expect("set mtime");
+
+        let path = |p: &std::path::Path| p.to_str().expect("utf-8 temp path").to_owned();
+        cipher
+            .encrypt_file_preserving_metadata(&path(&src), &path(&sealed))
+            .expect("encrypt");
+        cipher.decrypt_file(&path(&sealed), &path(&restored)).expect("decrypt");
+        assert_eq!(fs::read(&restored).unwrap(), b"backup me");
+
+        let restored_meta = fs::metadata(&restored).unwrap();
+        let restored_mtime = FileTime::from_last_modification_time(&restored_meta);
+        assert_eq!(restored_mtime.unix_seconds(), original.unix_seconds());
+        assert!(restored_mtime.nanoseconds().abs_diff(original.nanoseconds()) < 1_000_000);
+
+        // The mtime is authenticated
+        let mut tampered = fs::read(&sealed).unwrap();
+        tampered[FILE_META_MAGIC.len() + 7] ^= 1;
+        assert_eq!(cipher.open_file_frame(&tampered).unwrap_err(), SqepError::DecryptionFailed);
+
+        for p in [src, sealed, restored] {
+            fs::remove_file(p).expect("cleanup");
+        }
+    }
+
+    #[test]
+    fn bounded_decrypt_at_and_over_cap() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, _m) = cipher.encrypt_with_meta([1u8; 32]).expect("encrypt");
+
+        assert_eq!(cipher.decrypt_bounded(&ct, 32).expect("decrypt").len(), 32);
+        assert_eq!(cipher.decrypt_bounded(&ct, 31), Err(SqepError::OutputTooLarge { max: 31 }));
+    }
+
+    #[test]
+    fn body_must_hold_a_tag() {
+        let cipher = ZeroshieldCipher::new();
+
+        // Empty plaintext: header + nonce + tag only, still valid
+        let (ct, _m) = cipher.encrypt_with_meta(b"").expect("encrypt");
+        assert_eq!(ct.len(), HEADER_MAGIC.len() + NONCE_LEN + TAG_LEN);
+        assert!(cipher.decrypt(&ct).expect("decrypt").is_empty());
+
+        // Header + nonce with no body, or too few bytes for a tag
+        let bare = &ct[..HEADER_MAGIC.len() + NONCE_LEN];
+        assert_eq!(cipher.decrypt(bare), Err(SqepError::CiphertextTooShort));
+        let short = &ct[..HEADER_MAGIC.len() + NONCE_LEN + 8];
+        assert_eq!(cipher.decrypt(short), Err(SqepError::CiphertextTooShort));
+    }
+
+    #[test]
+    fn injected_clock_stamps_meta_and_drives_expiry() {
+        let cipher = ZeroshieldCipher::new().with_clock(FixedClock(1_700_000_000));
+        let (ct, meta) = cipher.encrypt_with_meta(b"pinned").expect("encrypt");
+        assert_eq!(meta.timestamp, 1_700_000_000);
+        let (_ct, meta) = cipher.encrypt_with_clock(b"pinned", &FixedClock(42)).expect("encrypt");
+        assert_eq!(meta.timestamp, 42);
+        assert_eq!(cipher.decrypt(&ct).unwrap(), b"pinned");
+
+        let (frame, _m) = cipher.encrypt_with_expiry(b"ttl", 1_700_000_060).expect("encrypt");
+        assert_eq!(cipher.decrypt_check_expiry(&frame).unwrap(), b"ttl");
+        let later = cipher.with_clock(FixedClock(1_700_000_061));
+        assert_eq!(
+            later.decrypt_check_expiry(&frame),
+            Err(SqepError::Expired { expires_at: 1_700_000_060 })
+        );
+    }
+
+    #[test]
+    fn key_matches_frame_compares_fingerprints() {
+        let (a, b) = (ZeroshieldCipher::new(), ZeroshieldCipher::new());
+        let (frame, _m) = a.encrypt_with_fingerprint(b"routed").expect("encrypt");
+        assert!(a.key_matches_frame(&frame));
+        assert!(!b.key_matches_frame(&frame));
+        assert_eq!(a.decrypt(&frame).expect("decrypt"), b"routed");
+        assert_eq!(
+            ZeroshieldCipher::decrypt_try_keys(&frame, &[b.clone(), a.clone()]).expect("decrypt").0,
+            1
+        );
+
+        // Frames that don't name a key can't be ruled out
+        let (plain, _m) = a.encrypt_with_meta(b"anonymous").expect("encrypt");
+        assert!(b.key_matches_frame(&plain));
+
+        // The fingerprint is bound to the body
+        let mut renamed = frame.clone();
+        renamed[FINGERPRINT_MAGIC.len()..FINGERPRINT_MAGIC.len() + FINGERPRINT_LEN]
+            .copy_from_slice(&b.fingerprint_bytes());
+        assert!(b.key_matches_frame(&renamed));
+        assert_eq!(a.decrypt(&renamed), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn expiry_is_enforced() {
+        let cipher = ZeroshieldCipher::new();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let (fresh, _m) = cipher.encrypt_with_expiry(b"token", now + 3600).expect("encrypt");
+        assert_eq!(cipher.decrypt_check_expiry(&fresh).expect("decrypt"), b"token");
+        assert_eq!(cipher.decrypt(&fresh), Err(SqepError::InvalidHeader));
+
+        let (stale, _m) = cipher.encrypt_with_expiry(b"token", now - 1).expect("encrypt");
+        assert_eq!(
+            cipher.decrypt_check_expiry(&stale),
+            Err(SqepError::Expired { expires_at: now - 1 })
+        );
+
+        // Pushing the expiry forward breaks authentication
+        let mut extended = stale.clone();
+        extended[EXPIRY_MAGIC.len()..EXPIRY_MAGIC.len() + EXPIRY_LEN]
+            .copy_from_slice(&(now + 3600).to_be_bytes());
+        assert_eq!(cipher.decrypt_check_expiry(&extended), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn dedup_tags_match_only_for_equal_plaintexts() {
+        let cipher = ZeroshieldCipher::new();
+        assert_eq!(cipher.dedup_tag(b"report.pdf"), cipher.dedup_tag(b"report.pdf"));
+        assert_ne!(cipher.dedup_tag(b"report.pdf"), cipher.dedup_tag(b"report.pdf "));
+        assert_ne!(cipher.dedup_tag(b"x"), ZeroshieldCipher::new().dedup_tag(b"x"));
+
+        let (a, _m) = cipher.encrypt_with_dedup_tag(b"report.pdf").expect("encrypt");
+        let (b, _m) = cipher.encrypt_with_dedup_tag(b"report.pdf").expect("encrypt");
+        assert_ne!(a, b);
+        assert_eq!(crate::frame::dedup_tag_of(&a), Some(cipher.dedup_tag(b"report.pdf")));
+        assert_eq!(crate::frame::dedup_tag_of(&a), crate::frame::dedup_tag_of(&b));
+        assert_eq!(cipher.decrypt_dedup_tagged(&a).expect("decrypt"), b"report.pdf");
+        assert_eq!(cipher.decrypt(&a), Err(SqepError::InvalidHeader));
+
+        // The tag is authenticated
+        let mut swapped = a.clone();
+        swapped[DEDUP_MAGIC.len()..DEDUP_MAGIC.len() + DEDUP_TAG_LEN]
+            .copy_from_slice(&cipher.dedup_tag(b"other"));
+        assert_eq!(cipher.decrypt_dedup_tagged(&swapped), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn strict_rejects_trailing_bytes() {
+        let cipher = ZeroshieldCipher::new();
+        let (mut ct, _m) = cipher.encrypt_with_meta(b"exact").expect("encrypt");
+        assert_eq!(cipher.decrypt_strict(&ct).expect("decrypt"), b"exact");
+
+        ct.push(0x00);
+        assert_eq!(cipher.decrypt_strict(&ct), Err(SqepError::TrailingBytes { extra: 1 }));
+        // Plain decrypt only sees a generic authentication failure
+        assert_eq!(cipher.decrypt(&ct), Err(SqepError::DecryptionFailed));
+        // A forged frame is still just a failed tag
+        let last = ct.len() - 2;
+        ct[last] ^= 1;
+        assert_eq!(cipher.decrypt_strict(&ct[..=last]), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn strict_accepts_legacy_padding_and_reads_tlv_records() {
+        let key = [8u8; KEY_LEN];
+        let cipher = ZeroshieldCipher::from_key(key);
+        let mut old = legacy_frame(&key, b"padded");
+        assert_eq!(cipher.decrypt_strict(&old).expect("decrypt"), b"padded");
+        old.extend_from_slice(b"xyz");
+        assert_eq!(cipher.decrypt_strict(&old), Err(SqepError::TrailingBytes { extra: 3 }));
+
+        let tlv = cipher.clone().with_frame_encoding(FrameEncoding::Tlv);
+        let (mut ct, _m) = tlv.encrypt_with_meta(b"records").expect("encrypt");
+        assert_eq!(tlv.decrypt_strict(&ct).expect("decrypt"), b"records");
+        ct.extend_from_slice(&[0x7F, 0, 0, 0, 2, 0xAA, 0xBB]);
+        assert_eq!(cipher.decrypt(&ct).expect("unknown records are skipped"), b"records");
+        assert_eq!(cipher.decrypt_strict(&ct), Err(SqepError::TrailingBytes { extra: 7 }));
+    }
+
+    #[test]
+    fn incremental_hash_matches_one_shot() {
+        let cipher = ZeroshieldCipher::new();
+        let msg = vec![0x5Au8; 64 * 1024 + 3];
+
+        let (ct, meta) = cipher.encrypt_with_meta(&msg).expect("encrypt");
+        assert_eq!(meta.hash, format!("{:x}", Sha256::digest(&ct)));
+
+        let (ct, meta) = cipher.encrypt_with_trailer(&msg, b"footer").expect("encrypt");
+        assert_eq!(meta.hash, format!("{:x}", Sha256::digest(&ct)));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn encrypt_emits_a_span_with_safe_fields() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        type Spans = Arc<Mutex<Vec<(String, Vec<(String, String)>)>>>;
+
+        struct Fields<'a>(&'a mut Vec<(String, String)>);
+        impl Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.push((field.name().to_owned(), format!("{:?}", value)));
+            }
+        }
+
+        struct Recorder(Spans);
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut fields = Vec::new();
+                span.record(&mut Fields(&mut fields));
+                let mut spans = self.0.lock().unwrap();
+                spans.push((span.metadata().name().to_owned(), fields));
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let spans: Spans = Arc::default();
+        let cipher = ZeroshieldCipher::new();
+        tracing::subscriber::with_default(Recorder(spans.clone()), || {
+            cipher.encrypt_with_meta(b"traced").expect("encrypt");
+        });
+
+        let spans = spans.lock().unwrap();
+        let (name, fields) = &spans[0];
+        assert_eq!(name, "sqep.encrypt");
+        assert_eq!(
+            fields,
+            &vec![
+                ("plaintext_len".to_owned(), "6".to_owned()),
+                ("fingerprint".to_owned(), cipher.fingerprint()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn seeded_ciphers_are_reproducible() {
+        let a = ZeroshieldCipher::from_seed(42);
+        assert_eq!(a.fingerprint(), ZeroshieldCipher::from_seed(42).fingerprint());
+        assert_ne!(a.fingerprint(), ZeroshieldCipher::from_seed(43).fingerprint());
+
+        let (ct, _m) = a.encrypt_with_meta(b"golden").expect("encrypt");
+        assert_eq!(ZeroshieldCipher::from_seed(42).decrypt(&ct).unwrap(), b"golden");
+    }
+
+    #[test]
+    fn disabling_the_meta_hash_leaves_it_empty() {
+        let cipher = ZeroshieldCipher::new().with_clock(FixedClock(7)).with_meta_hash(false);
+        let (ct, meta) = cipher.encrypt_with_meta(b"no hash").expect("encrypt");
+        assert_eq!((meta.timestamp, meta.hash.as_str()), (7, ""));
+        assert_eq!(cipher.decrypt(&ct).unwrap(), b"no hash");
+
+        let (_ct, meta) = cipher.with_meta_hash(true).encrypt_with_meta(b"hash").expect("encrypt");
+        assert_eq!(meta.hash.len(), 64);
+    }
+
+    #[test]
+    fn bound_length_is_appended_to_the_aad() {
+        let bound = ZeroshieldCipher::new().with_bind_length(true);
+        let (ct, _m) = bound.encrypt_with_meta(b"hello").expect("encrypt");
+        assert_eq!(bound.decrypt(&ct).unwrap(), b"hello");
+        let mut out = [0u8; 5];
+        assert_eq!(bound.decrypt_into_slice(&ct, &mut out), Ok(5));
+
+        // Both sides must agree on the setting
+        let unbound = bound.clone().with_bind_length(false);
+        assert_eq!(unbound.decrypt(&ct), Err(SqepError::DecryptionFailed));
+
+        // What is authenticated: `AAD || PLAINTEXT_LEN (u64 BE)`
+        let (key, nonce) = (bound.aead_key().unwrap(), [4u8; NONCE_LEN]);
+        let body = unbound
+            .seal_body_using(&key, MaskParams::DEFAULT, &nonce, b"hello", &5u64.to_be_bytes())
+            .expect("seal");
+        let opened = bound.open_body_using(&key, MaskParams::DEFAULT, &nonce, &body, &[]);
+        assert_eq!(opened.unwrap(), b"hello");
+    }
+
+    #[test]
+    fn failed_open_zeroes_the_working_buffer() {
+        let cipher = ZeroshieldCipher::new();
+        let (key, nonce) = (cipher.aead_key().unwrap(), [7u8; NONCE_LEN]);
+        let mut body = cipher
+            .seal_body_using(&key, MaskParams::DEFAULT, &nonce, b"sensitive", &[])
+            .expect("seal");
+        *body.last_mut().unwrap() ^= 1;
+
+        let mut in_out = body.clone();
+        let opened = cipher.open_body_in(&key, MaskParams::DEFAULT, &nonce, &mut in_out, &[]);
+        assert_eq!(opened, Err(SqepError::DecryptionFailed));
+        assert_eq!(in_out, vec![0u8; body.len()]);
+    }
+
+    #[test]
+    fn clear_key_zeroes_the_key_bytes() {
+        let mut cipher = ZeroshieldCipher::from_key([0x5a; KEY_LEN]);
+        cipher.wipe_key();
+        assert_eq!(cipher.key_bytes(), &[0u8; KEY_LEN]);
+
+        // Consuming form: the value is moved out, so it compiles only once
+        ZeroshieldCipher::new().clear_key();
+    }
+
+    #[test]
+    fn minimum_plaintext_len_rejects_empty_messages() {
+        let cipher = ZeroshieldCipher::new().with_min_plaintext_len(1);
+        let too_short = SqepError::PlaintextTooShort { len: 0, min: 1 };
+        assert_eq!(cipher.encrypt_with_meta(b"").unwrap_err(), too_short);
+        let (ct, _m) = cipher.encrypt_with_meta(b"x").expect("encrypt");
+        assert_eq!(cipher.decrypt(&ct).unwrap(), b"x");
+
+        // Permissive by default
+        assert!(ZeroshieldCipher::new().encrypt_with_meta(b"").is_ok());
+    }
+
+    #[test]
+    fn minimum_plaintext_len_applies_to_every_seal_meta_method() {
+        let cipher = ZeroshieldCipher::new().with_min_plaintext_len(1);
+        let too_short = Err(SqepError::PlaintextTooShort { len: 0, min: 1 });
+
+        assert_eq!(cipher.encrypt_with_meta(b"").map(drop), too_short);
+        assert_eq!(cipher.encrypt_with_meta_detailed(b"").map(drop), too_short);
+        assert_eq!(cipher.encrypt_with_clock(b"", &FixedClock(7)).map(drop), too_short);
+        let nonce = crate::nonce::NonceCounter::new().next_nonce();
+        assert_eq!(cipher.encrypt_with_unique_nonce(nonce, b"").map(drop), too_short);
+        assert_eq!(cipher.encrypt_checked(b"").map(drop), too_short);
+        assert_eq!(cipher.encrypt_content_addressed(b"").map(drop), too_short);
+        assert_eq!(cipher.encrypt_with_fingerprint(b"").map(drop), too_short);
+        assert_eq!(cipher.encrypt_with_expiry(b"", u64::MAX).map(drop), too_short);
+        assert_eq!(cipher.encrypt_with_dedup_tag(b"").map(drop), too_short);
+        assert_eq!(cipher.encrypt_with_trailer(b"", b"trailer").map(drop), too_short);
+        assert_eq!(cipher.encrypt_many(&[b"x", b""]).map(drop), too_short);
+        assert_eq!(cipher.prepare().unwrap().encrypt(b"").map(drop), too_short);
+        let mut scratch = crate::scratch::ScratchBuffers::new();
+        assert_eq!(cipher.encrypt_reusing(b"", &mut scratch).map(drop), too_short);
+        #[cfg(feature = "bytes")]
+        assert_eq!(cipher.encrypt_bytes(b"").map(drop), too_short);
+        #[cfg(feature = "serde_json")]
+        assert_eq!(cipher.encrypt_with_context(b"", &"ctx").map(drop), too_short);
+
+        let mut ring = crate::keyring::Keyring::new();
+        ring.insert(1, cipher.clone());
+        assert_eq!(ring.encrypt(1, b"").map(drop), too_short);
+
+        let mut writer = crate::frame_writer::FrameWriter::new(&cipher);
+        let sealed = writer.push_header().push_nonce().append_sealed(b"").map(drop);
+        assert_eq!(sealed, too_short);
+
+        let base = std::env::temp_dir().join(format!("sqep-lite-{}", nanoid::nanoid!()));
+        let (src, sealed) = (base.with_extension("src"), base.with_extension("sqep"));
+        fs::write(&src, b"").expect("write source");
+        let (src, sealed) = (src.to_str().unwrap(), sealed.to_str().unwrap());
+        assert!(cipher.encrypt_file(src, sealed).is_err());
+        assert!(cipher.encrypt_file_preserving_metadata(src, sealed).is_err());
+        fs::remove_file(src).expect("cleanup");
+        let _ = fs::remove_file(sealed);
+    }
+
+    #[test]
+    fn detailed_encrypt_returns_the_embedded_nonce() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, meta, nonce) = cipher.encrypt_with_meta_detailed(b"audit me").expect("encrypt");
+        assert_eq!(Frame::decode(&ct).unwrap().nonce(), nonce);
+        assert_eq!(meta.hash, format!("{:x}", Sha256::digest(&ct)));
+        assert_eq!(cipher.decrypt(&ct).unwrap(), b"audit me");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn deterministic_test_frames_are_byte_identical() {
+        let seal = || {
+            ZeroshieldCipher::from_seed(7)
+                .encrypt_deterministic_test(b"golden", [9u8; NONCE_LEN], 1_700_000_000)
+                .expect("encrypt")
+        };
+        let (a, meta_a) = seal();
+        let (b, meta_b) = seal();
+        assert_eq!(a, b);
+        assert_eq!(meta_a, meta_b);
+        assert_eq!(meta_a.timestamp, 1_700_000_000);
+        assert_eq!(Frame::decode(&a).unwrap().nonce(), [9u8; NONCE_LEN]);
+        assert_eq!(ZeroshieldCipher::from_seed(7).decrypt(&a).unwrap(), b"golden");
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn bytes_output_decrypts() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, _m) = cipher.encrypt_bytes(b"zero-copy").expect("encrypt");
+        let shared = ct.clone();
+        assert_eq!(cipher.decrypt(shared).expect("decrypt"), b"zero-copy");
+        assert_eq!(cipher.decrypt(&ct[..]).expect("decrypt"), b"zero-copy");
+    }
+
+    /// Seal like the old `SQEP3.9` `encrypt_with_meta` did
+    fn legacy_frame(key: &[u8; KEY_LEN], plaintext: &[u8]) -> Vec<u8> {
+        use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+
+        let nonce_bytes = [4u8; NONCE_LEN];
+        let key = LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).unwrap());
+        let mut in_out = plaintext.to_vec();
+        in_out.resize(in_out.len() + CHACHA20_POLY1305.tag_len(), 0);
+        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
+        key.seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out).unwrap();
+        [LEGACY_MAGIC, &nonce_bytes, &in_out].concat()
+    }
+
+    #[test]
+    fn legacy_frames_open() {
+        let key = [8u8; KEY_LEN];
+        let cipher = ZeroshieldCipher::from_key(key);
+        let old = legacy_frame(&key, b"sealed by SQEP3.9");
+
+        assert_eq!(cipher.decrypt_legacy(&old).expect("decrypt"), b"sealed by SQEP3.9");
+        assert_eq!(
+            ZeroshieldCipher::new().decrypt_legacy(&old),
+            Err(SqepError::DecryptionFailed)
+        );
+        let (current, _m) = cipher.encrypt_with_meta(b"new").expect("encrypt");
+        assert_eq!(cipher.decrypt_legacy(&current), Err(SqepError::InvalidHeader));
+
+        // The slice needs room for the padding, which is then stripped
+        let mut out = [0u8; 17 + TAG_LEN];
+        assert_eq!(cipher.decrypt_into_slice(&old, &mut out).unwrap(), 17);
+        assert_eq!(&out[..17], b"sealed by SQEP3.9");
+    }
+
+    #[test]
+    fn both_shares_rebuild_the_same_key() {
+        let (a, b) = ([0x11u8; KEY_LEN], [0x22u8; KEY_LEN]);
+        let cipher = ZeroshieldCipher::from_shares(&a, &b);
+        assert_eq!(cipher.fingerprint(), ZeroshieldCipher::from_shares(&a, &b).fingerprint());
+        let (ct, _m) = cipher.encrypt_with_meta(b"dual control").expect("encrypt");
+        assert_eq!(ZeroshieldCipher::from_shares(&a, &b).decrypt(&ct).unwrap(), b"dual control");
+
+        for other in [
+            ZeroshieldCipher::from_key(a),
+            ZeroshieldCipher::from_key(b),
+            ZeroshieldCipher::from_shares(&b, &a),
+            ZeroshieldCipher::from_shares(&a, &[0u8; KEY_LEN]),
+        ] {
+            assert_ne!(other.fingerprint(), cipher.fingerprint());
+        }
+    }
+
+    #[test]
+    fn migrated_legacy_frames_decrypt_as_current_frames() {
+        let key = [8u8; KEY_LEN];
+        let cipher = ZeroshieldCipher::from_key(key);
+        let migrated = cipher.migrate_frame(legacy_frame(&key, b"moving to 4.0")).unwrap();
+
+        assert!(migrated.starts_with(HEADER_MAGIC));
+        assert_eq!(cipher.decrypt(&migrated).unwrap(), b"moving to 4.0");
+        assert_eq!(cipher.migrate_frame(&migrated), Err(SqepError::InvalidHeader));
+    }
+
+    #[test]
+    fn rekey_moves_frames_to_another_key_or_format() {
+        let (old, new) = (ZeroshieldCipher::new(), ZeroshieldCipher::new());
+        let frame = old.encrypt_with_meta(b"rotating").unwrap().0;
+
+        let moved = old.rekey(&frame, &new).unwrap();
+        assert_eq!(new.decrypt(&moved).unwrap(), b"rotating");
+        assert_eq!(old.decrypt(&moved), Err(SqepError::DecryptionFailed));
+        assert_eq!(new.rekey(&frame, &old), Err(SqepError::DecryptionFailed));
+
+        let sha384 = old.clone().with_kdf_hash(KdfHash::Sha384);
+        let relabelled = old.rekey(&frame, &sha384).unwrap();
+        assert!(relabelled.starts_with(KDF_EXT_MAGIC));
+        assert_eq!(sha384.decrypt(&relabelled).unwrap(), b"rotating");
+    }
+
+    #[test]
+    fn decrypt_detects_both_formats() {
+        let key = [8u8; KEY_LEN];
+        let cipher = ZeroshieldCipher::from_key(key);
+        let old = legacy_frame(&key, b"from 3.9");
+        let (new, _m) = cipher.encrypt_with_meta(b"from 4.0").expect("encrypt");
+
+        assert_eq!(cipher.decrypt(&old).unwrap(), b"from 3.9");
+        assert_eq!(cipher.decrypt(&new).unwrap(), b"from 4.0");
+        assert_eq!(legacy_frame(&key, b"").len(), LEGACY_MAGIC.len() + NONCE_LEN + 2 * TAG_LEN);
+        assert_eq!(cipher.decrypt(legacy_frame(&key, b"")).unwrap(), b"");
+
+        // Relabelling a 4.0 frame as 3.9 must not yield its masked bytes
+        let relabelled = [LEGACY_MAGIC, &new[HEADER_MAGIC.len()..]].concat();
+        assert_eq!(cipher.decrypt(&relabelled), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn unchecked_matches_checked() {
+        let cipher = ZeroshieldCipher::new();
+        let (ct, _m) = cipher.encrypt_with_meta_unchecked(b"legacy caller");
+        assert_eq!(cipher.decrypt(&ct).expect("decrypt"), b"legacy caller");
     }
 }
 
@@ -0,0 +1,84 @@
+//! Decrypting chunked streams into a memory-mapped file (feature `mmap`).
+//!
+//! The output file is sized up front from the stream's layout, and every
+//! chunk is authenticated and unmasked in place inside the mapping, so
+//! plaintext never passes through an intermediate buffer.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+
+use memmap2::MmapMut;
+
+use crate::error::SqepError;
+use crate::lite::ZeroshieldCipher;
+use crate::stream::{invalid, DecryptReader};
+
+impl ZeroshieldCipher {
+    /// Decrypt the chunked stream at `input_path` into `output_path`
+    /// through a memory map, returning the plaintext byte count.
+    ///
+    /// Produces the same file as `decrypt_stream`. The output is created (or
+    /// truncated), pre-sized to the plaintext length computed from the
+    /// stream's size, and truncated to exactly the bytes written at the end.
+    /// On error it is cut back to the verified prefix, like `decrypt_stream`
+    /// leaves its writer. The input must not change while it is read.
+    pub fn decrypt_file_mmap(&self, input_path: &str, output_path: &str) -> io::Result<u64> {
+        trace_span!("sqep.decrypt_file", fingerprint = %self.fingerprint());
+        let input = File::open(input_path)?;
+        let stream_len = input.metadata()?.len();
+        let mut source = DecryptReader::new(self, input);
+        let plaintext_len = source.plaintext_len(stream_len).map_err(invalid)?;
+        let map_len = usize::try_from(plaintext_len).map_err(|_| {
+            invalid(SqepError::FileTooLarge { size: plaintext_len, max: usize::MAX as u64 })
+        })?;
+
+        let output = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(output_path)?;
+        output.set_len(plaintext_len)?;
+        let (written, result) = if map_len == 0 {
+            let (written, filled) = fill(&mut source, &mut []);
+            (written, filled.map_err(invalid))
+        } else {
+            // SAFETY: the mapping only lives in this function and is dropped
+            // before `set_len`; another process truncating the file meanwhile
+            // is the caveat every writable mapping carries.
+            #[allow(unsafe_code)]
+            let mapped = unsafe { MmapMut::map_mut(&output) };
+            match mapped {
+                Ok(mut map) => {
+                    let (written, filled) = fill(&mut source, &mut map);
+                    let flushed = map.flush();
+                    drop(map);
+                    (written, filled.map_err(invalid).and(flushed))
+                }
+                Err(e) => (0, Err(e)),
+            }
+        };
+        // Cut back to the verified prefix even when filling or flushing
+        // failed, then report the first error
+        output.set_len(written as u64)?;
+        result.map(|()| written as u64)
+    }
+}
+
+/// Decrypt every chunk of `source` back to back into `out`, returning the
+/// bytes written. The stream must fill `out` exactly.
+fn fill<R: io::Read>(
+    source: &mut DecryptReader<R>,
+    out: &mut [u8],
+) -> (usize, Result<(), SqepError>) {
+    let mut written = 0;
+    loop {
+        match source.next_chunk_into(&mut out[written..]) {
+            Ok(Some(n)) => written += n,
+            Ok(None) if written == out.len() => return (written, Ok(())),
+            // The input grew or shrank since its length was read
+            Ok(None) => return (written, Err(SqepError::CiphertextTooShort)),
+            Err(e) => return (written, Err(e)),
+        }
+    }
+}
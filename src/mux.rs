@@ -0,0 +1,114 @@
+//! Several logical substreams multiplexed under one key.
+//!
+//! Each frame's nonce is `STREAM_ID (u32 BE) || COUNTER (u64 BE)`, so
+//! substreams draw from disjoint nonce spaces, and the AAD binds the
+//! stream id, so a frame sealed on stream A fails to open on stream B.
+//!
+//! Counters live in the `MuxCipher`, so exactly one sending `MuxCipher`
+//! may exist per key: a second one (or a restarted process) would reuse
+//! nonces. Give every channel or session its own key, e.g. via
+//! `derive_subkey`. For the same reason `MuxCipher` is not `Clone`:
+//!
+//! ```compile_fail
+//! use sqep_lite::{MuxCipher, ZeroshieldCipher};
+//!
+//! let mux = MuxCipher::new(ZeroshieldCipher::new());
+//! let copy = mux.clone(); // would hand out the same nonces again
+//! ```
+
+use std::collections::HashMap;
+
+use crate::error::SqepError;
+use crate::frame::NONCE_LEN;
+use crate::lite::ZeroshieldCipher;
+
+const MUX_DOMAIN: &[u8] = b"SQEP:LITE:MUX:v1";
+const STREAM_ID_LEN: usize = 4;
+
+/// Cipher sealing frames on numbered substreams of one key
+pub struct MuxCipher {
+    cipher: ZeroshieldCipher,
+    counters: HashMap<u32, u64>,
+}
+
+impl MuxCipher {
+    /// Multiplex over `cipher`, which must not be used by another sender
+    pub fn new(cipher: ZeroshieldCipher) -> Self {
+        Self {
+            cipher,
+            counters: HashMap::new(),
+        }
+    }
+
+    /// Seal `plaintext` as the next frame of substream `id`; fails with
+    /// `StreamTooLong` once the substream's counter is exhausted
+    pub fn encrypt_on_stream(
+        &mut self,
+        id: u32,
+        plaintext: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, SqepError> {
+        let plaintext = plaintext.as_ref();
+        let counter = self.counters.entry(id).or_insert(0);
+        let nonce = stream_nonce(id, *counter);
+        *counter = counter.checked_add(1).ok_or(SqepError::StreamTooLong)?;
+
+        self.cipher.seal_frame_with_nonce(nonce, plaintext, &stream_aad(id))
+    }
+
+    /// Open a frame sealed on substream `id`
+    pub fn decrypt_on_stream(
+        &self,
+        id: u32,
+        ciphertext: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        self.cipher.open_frame(ciphertext, &stream_aad(id))
+    }
+}
+
+fn stream_nonce(id: u32, counter: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..STREAM_ID_LEN].copy_from_slice(&id.to_be_bytes());
+    nonce[STREAM_ID_LEN..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+fn stream_aad(id: u32) -> Vec<u8> {
+    [MUX_DOMAIN, &id.to_be_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{Frame, HEADER_MAGIC};
+
+    #[test]
+    fn streams_roundtrip_and_reject_cross_stream_replay() {
+        let key = ZeroshieldCipher::new();
+        let mut tx = MuxCipher::new(key.clone());
+        let rx = MuxCipher::new(key);
+
+        let a = tx.encrypt_on_stream(1, b"on stream one").expect("encrypt");
+        let b = tx.encrypt_on_stream(2, b"on stream two").expect("encrypt");
+        assert_eq!(rx.decrypt_on_stream(1, &a).unwrap(), b"on stream one");
+        assert_eq!(rx.decrypt_on_stream(2, &b).unwrap(), b"on stream two");
+
+        assert_eq!(rx.decrypt_on_stream(2, &a), Err(SqepError::DecryptionFailed));
+        assert_eq!(rx.decrypt_on_stream(1, &b), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn nonces_carry_stream_id_and_counter() {
+        let mut tx = MuxCipher::new(ZeroshieldCipher::new());
+        tx.encrypt_on_stream(7, b"first").expect("encrypt");
+        let second = tx.encrypt_on_stream(7, b"second").expect("encrypt");
+        let other = tx.encrypt_on_stream(8, b"first").expect("encrypt");
+
+        assert!(second.starts_with(HEADER_MAGIC));
+        assert_eq!(Frame::decode(&second).unwrap().nonce(), &stream_nonce(7, 1));
+        assert_eq!(Frame::decode(&other).unwrap().nonce(), &stream_nonce(8, 0));
+
+        tx.counters.insert(9, u64::MAX);
+        assert_eq!(tx.encrypt_on_stream(9, b"last"), Err(SqepError::StreamTooLong));
+    }
+}
@@ -0,0 +1,109 @@
+//! Nonces that can only be made through vetted paths.
+//!
+//! Reusing a nonce under one key reveals the XOR of two plaintexts and lets
+//! an attacker forge frames. `UniqueNonce` is neither `Clone` nor `Copy`
+//! and is consumed by `ZeroshieldCipher::encrypt_with_unique_nonce`, so a
+//! value can seal at most one frame. It comes from the system RNG, from a
+//! `NonceCounter`, or from `assume_unique`, where the caller vouches for it.
+//!
+//! ```compile_fail
+//! use sqep_lite::UniqueNonce;
+//!
+//! let nonce = UniqueNonce::random();
+//! let copy = nonce.clone(); // UniqueNonce is not Clone
+//! ```
+
+use crate::frame::NONCE_LEN;
+use crate::lite::random_nonce;
+
+/// Random prefix bytes of counter nonces: `PREFIX (4) || COUNTER (u64 BE)`
+const COUNTER_PREFIX_LEN: usize = NONCE_LEN - 8;
+
+/// A nonce not used for any frame yet (see the module docs)
+#[derive(Debug, PartialEq, Eq)]
+pub struct UniqueNonce([u8; NONCE_LEN]);
+
+impl UniqueNonce {
+    /// Fresh nonce from the system CSPRNG
+    pub fn random() -> Self {
+        Self(random_nonce())
+    }
+
+    /// Wrap caller-chosen bytes. The caller guarantees they were never used
+    /// with the key they will seal under; prefer `random` or `NonceCounter`.
+    pub fn assume_unique(bytes: [u8; NONCE_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// The nonce bytes, e.g. for logging
+    pub fn as_bytes(&self) -> &[u8; NONCE_LEN] {
+        &self.0
+    }
+
+    pub(crate) fn into_bytes(self) -> [u8; NONCE_LEN] {
+        self.0
+    }
+}
+
+/// Sequential nonces `PREFIX || COUNTER`, with a random 4-byte prefix per
+/// counter. Not `Clone`: two copies would hand out the same nonces.
+#[derive(Debug)]
+pub struct NonceCounter {
+    prefix: [u8; COUNTER_PREFIX_LEN],
+    next: u64,
+}
+
+impl NonceCounter {
+    /// Counter starting at 0 behind a random prefix
+    pub fn new() -> Self {
+        let mut prefix = [0u8; COUNTER_PREFIX_LEN];
+        prefix.copy_from_slice(&random_nonce()[..COUNTER_PREFIX_LEN]);
+        Self { prefix, next: 0 }
+    }
+
+    /// Hand out the next nonce.
+    ///
+    /// # Panics
+    /// Panics after 2^64 nonces rather than wrapping around.
+    pub fn next_nonce(&mut self) -> UniqueNonce {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..COUNTER_PREFIX_LEN].copy_from_slice(&self.prefix);
+        nonce[COUNTER_PREFIX_LEN..].copy_from_slice(&self.next.to_be_bytes());
+        self.next = self.next.checked_add(1).expect("Nonce counter exhausted");
+        UniqueNonce(nonce)
+    }
+}
+
+impl Default for NonceCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    use crate::frame::Frame;
+    use crate::lite::ZeroshieldCipher;
+
+    #[test]
+    fn counter_nonces_are_distinct() {
+        let mut counter = NonceCounter::new();
+        let nonces: Vec<UniqueNonce> = (0..1000).map(|_| counter.next_nonce()).collect();
+        let distinct: HashSet<_> = nonces.iter().map(UniqueNonce::as_bytes).collect();
+        assert_eq!(distinct.len(), nonces.len());
+        assert_eq!(&nonces[1].as_bytes()[COUNTER_PREFIX_LEN..], &1u64.to_be_bytes());
+    }
+
+    #[test]
+    fn unique_nonce_seals_one_frame() {
+        let cipher = ZeroshieldCipher::new();
+        let nonce = NonceCounter::new().next_nonce();
+        let expected = *nonce.as_bytes();
+        let (ct, _m) = cipher.encrypt_with_unique_nonce(nonce, b"once").expect("encrypt");
+        assert_eq!(Frame::decode(&ct).unwrap().nonce(), expected);
+        assert_eq!(cipher.decrypt(&ct).unwrap(), b"once");
+    }
+}
@@ -0,0 +1,67 @@
+//! A cipher with its AEAD key set up once, for tight loops.
+//!
+//! `ZeroshieldCipher` builds the AEAD key (`ring`'s `LessSafeKey`, or the
+//! AES-GCM-SIV key schedule) on every call. `PreparedCipher` builds it once
+//! in `prepare()` and reuses it for every frame; the frames are identical
+//! in format to the cipher's own.
+//!
+//! Zeroization: the prepared key lives inside `ring` (or `aes-gcm-siv`),
+//! which does not wipe it on drop, next to a copy of the cipher that doesn't
+//! either. Keep prepared handles scoped to the loop that needs them; wiping
+//! the original with `clear_key` does not reach them.
+
+use crate::algorithm::AeadKey;
+use crate::error::SqepError;
+use crate::lite::{random_nonce, SealMeta, ZeroshieldCipher};
+
+/// `ZeroshieldCipher` with a ready-made AEAD key (see `ZeroshieldCipher::prepare`)
+pub struct PreparedCipher {
+    cipher: ZeroshieldCipher,
+    key: AeadKey,
+}
+
+impl ZeroshieldCipher {
+    /// Set up the AEAD key once for many `encrypt` / `decrypt` calls
+    pub fn prepare(&self) -> Result<PreparedCipher, SqepError> {
+        Ok(PreparedCipher {
+            cipher: self.clone(),
+            key: self.aead_key()?,
+        })
+    }
+}
+
+impl PreparedCipher {
+    /// `ZeroshieldCipher::encrypt_with_meta` with the prepared key
+    pub fn encrypt(&self, plaintext: impl AsRef<[u8]>) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let clock = self.cipher.clock();
+        self.cipher.seal_frame_with_meta_using(&self.key, random_nonce(), plaintext, &[], clock)
+    }
+
+    /// `ZeroshieldCipher::decrypt` with the prepared key (frames naming
+    /// another algorithm still fail with `AlgorithmMismatch`)
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        self.cipher.open_frame_using(&self.key, ciphertext.as_ref(), &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepared_frames_interoperate_with_the_cipher() {
+        let cipher = ZeroshieldCipher::new();
+        let prepared = cipher.prepare().expect("prepare");
+
+        let (ct, meta) = prepared.encrypt(b"hot loop").expect("encrypt");
+        assert_eq!(cipher.decrypt(&ct).unwrap(), b"hot loop");
+        assert_eq!(meta.hash.len(), 64);
+
+        let (ct, _m) = cipher.encrypt_with_meta(b"the other way").expect("encrypt");
+        assert_eq!(prepared.decrypt(&ct).unwrap(), b"the other way");
+
+        let other = ZeroshieldCipher::new().prepare().expect("prepare");
+        assert_eq!(other.decrypt(&ct), Err(SqepError::DecryptionFailed));
+    }
+}
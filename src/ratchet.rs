@@ -0,0 +1,153 @@
+//! Per-message keys from a one-way hash ratchet.
+//!
+//! ```text
+//! PRK_i       = HKDF-Extract(salt = "SQEP:LITE:RATCHET:v1", ikm = CHAIN_i)
+//! CHAIN_i+1   = HKDF-Expand(PRK_i, "chain")
+//! KEY_i       = HKDF-Expand(PRK_i, "message")
+//! NONCE_i     = HKDF-Expand(PRK_i, "nonce")
+//! ```
+//!
+//! `CHAIN_0` is derived from the shared cipher's key. Message `i` is a
+//! regular frame under `KEY_i` and `NONCE_i`, sealed with AAD
+//! `"SQEP:LITE:RATCHET:v1" || i (u64 BE)`. Each side keeps only the
+//! current chain key and overwrites it after every message, so a leaked
+//! state reveals no earlier message key. Chain and message keys live in
+//! zeroizing buffers, and each message's cipher is cleared once used.
+//! Sender and receiver advance in
+//! lockstep: messages must be opened in the order they were sealed.
+
+use ring::hkdf;
+use zeroize::Zeroizing;
+
+use crate::error::SqepError;
+use crate::frame::NONCE_LEN;
+use crate::lite::{OkmLen, ZeroshieldCipher, KEY_LEN};
+
+const RATCHET_DOMAIN: &[u8] = b"SQEP:LITE:RATCHET:v1";
+const INIT_LABEL: &[u8] = b"SQEP:LITE:RATCHET:init";
+
+/// One direction of a ratcheted session.
+///
+/// Deliberately not `Clone`: two copies of a sending ratchet would seal
+/// different messages under the same key and nonce.
+pub struct RatchetCipher {
+    /// Algorithm settings; the key is replaced by `KEY_i` for each message
+    settings: ZeroshieldCipher,
+    /// Wiped when replaced and on drop
+    chain: Zeroizing<[u8; KEY_LEN]>,
+    index: u64,
+}
+
+impl RatchetCipher {
+    /// Start a ratchet from `shared`, the same on the sending and the
+    /// receiving side. Use one ratchet per direction (e.g. via
+    /// `split_duplex`).
+    pub fn new(shared: &ZeroshieldCipher) -> Self {
+        let init = shared.derive_subkey(INIT_LABEL);
+        let chain = Zeroizing::new(*init.key_bytes());
+        init.clear_key();
+        Self {
+            settings: shared.with_key([0u8; KEY_LEN]),
+            chain,
+            index: 0,
+        }
+    }
+
+    /// Index of the next message to seal or open
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Seal `plaintext` as the next message and advance the ratchet
+    pub fn encrypt(&mut self, plaintext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let plaintext = plaintext.as_ref();
+        let (cipher, nonce, next) = self.step();
+        let frame = cipher.seal_frame_with_nonce(nonce, plaintext, &index_aad(self.index));
+        cipher.clear_key();
+        let frame = frame?;
+        self.advance(next);
+        Ok(frame)
+    }
+
+    /// Open the next message and advance the ratchet.
+    ///
+    /// A frame that isn't the next message (replayed, reordered, skipped or
+    /// forged) fails with `DecryptionFailed` and leaves the ratchet where it
+    /// was.
+    pub fn decrypt(&mut self, ciphertext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let ciphertext = ciphertext.as_ref();
+        let (cipher, _nonce, next) = self.step();
+        let plaintext = cipher.open_frame(ciphertext, &index_aad(self.index));
+        cipher.clear_key();
+        let plaintext = plaintext?;
+        self.advance(next);
+        Ok(plaintext)
+    }
+
+    /// Message cipher, nonce and next chain key for the current index
+    fn step(&self) -> (ZeroshieldCipher, [u8; NONCE_LEN], Zeroizing<[u8; KEY_LEN]>) {
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, RATCHET_DOMAIN).extract(&*self.chain);
+        let expand = |label: &[u8], out: &mut [u8]| {
+            let info = [label];
+            let okm = prk.expand(&info, OkmLen(out.len())).expect("HKDF expand (ratchet)");
+            okm.fill(out).expect("HKDF fill (ratchet)");
+        };
+
+        let mut next = Zeroizing::new([0u8; KEY_LEN]);
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        let mut nonce = [0u8; NONCE_LEN];
+        expand(b"chain", &mut *next);
+        expand(b"message", &mut *key);
+        expand(b"nonce", &mut nonce);
+        (self.settings.with_key(*key), nonce, next)
+    }
+
+    fn advance(&mut self, next: Zeroizing<[u8; KEY_LEN]>) {
+        *self.chain = *next;
+        self.index = self.index.checked_add(1).expect("Ratchet index exhausted");
+    }
+}
+
+fn index_aad(index: u64) -> Vec<u8> {
+    [RATCHET_DOMAIN, &index.to_be_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Frame;
+
+    #[test]
+    fn messages_open_in_order_only() {
+        let shared = ZeroshieldCipher::new();
+        let mut tx = RatchetCipher::new(&shared);
+        let mut rx = RatchetCipher::new(&shared);
+
+        let first = tx.encrypt(b"first").expect("encrypt");
+        let second = tx.encrypt(b"second").expect("encrypt");
+        let third = tx.encrypt(b"third").expect("encrypt");
+        assert_eq!(tx.index(), 3);
+
+        // Out of order: the failure leaves the receiver at message 0
+        assert_eq!(rx.decrypt(&second), Err(SqepError::DecryptionFailed));
+        assert_eq!(rx.index(), 0);
+
+        assert_eq!(rx.decrypt(&first).unwrap(), b"first");
+        assert_eq!(rx.decrypt(&first), Err(SqepError::DecryptionFailed));
+        assert_eq!(rx.decrypt(&second).unwrap(), b"second");
+        assert_eq!(rx.decrypt(&third).unwrap(), b"third");
+
+        // Not a frame of the shared key itself
+        assert_eq!(shared.decrypt(&first), Err(SqepError::DecryptionFailed));
+    }
+
+    #[test]
+    fn every_message_gets_a_fresh_key_and_nonce() {
+        let mut tx = RatchetCipher::new(&ZeroshieldCipher::new());
+        let a = tx.encrypt(b"same plaintext").expect("encrypt");
+        let b = tx.encrypt(b"same plaintext").expect("encrypt");
+        let (a, b) = (Frame::decode(&a).unwrap(), Frame::decode(&b).unwrap());
+        assert_ne!(a.nonce(), b.nonce());
+        assert_ne!(a.body(), b.body());
+    }
+}
@@ -0,0 +1,130 @@
+//! Fixed-size encrypted records.
+//!
+//! `FixedRecordCipher` pads every plaintext (under the AEAD) to the same
+//! record size, so each sealed record has an identical length and every
+//! decrypt processes exactly the same number of bytes.
+//!
+//! Timing guarantee: allocation, XOR masking and the AEAD open always run
+//! over `4 + record_size` bytes, independent of the true plaintext length.
+//! Limits: the returned `Vec` is truncated to the true length (an O(1)
+//! operation), so what the *caller* does with the plaintext afterwards is
+//! not covered. Allocator and OS behaviour are also outside the guarantee.
+
+use crate::error::SqepError;
+use crate::frame::{read_len, write_len, LEN_SIZE};
+use crate::lite::ZeroshieldCipher;
+
+const RECORD_DOMAIN: &[u8] = b"SQEP:LITE:RECORD:v1";
+
+/// Cipher producing equally sized records for a fixed `record_size`
+#[derive(Clone)]
+pub struct FixedRecordCipher {
+    cipher: ZeroshieldCipher,
+    record_size: usize,
+}
+
+impl FixedRecordCipher {
+    /// Wrap `cipher` for records holding up to `record_size` plaintext bytes
+    pub fn new(cipher: ZeroshieldCipher, record_size: usize) -> Self {
+        assert!(record_size <= u32::MAX as usize, "record_size must fit in a u32");
+        Self { cipher, record_size }
+    }
+
+    /// Maximum plaintext bytes per record
+    pub fn record_size(&self) -> usize {
+        self.record_size
+    }
+
+    /// Exact length of every sealed record
+    pub fn sealed_len(&self) -> usize {
+        self.cipher.frame_overhead() + LEN_SIZE + self.record_size
+    }
+
+    /// Pad `plaintext` to the record size and seal it
+    pub fn encrypt(&self, plaintext: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let plaintext = plaintext.as_ref();
+        if plaintext.len() > self.record_size {
+            return Err(SqepError::RecordTooLarge {
+                max: self.record_size,
+            });
+        }
+
+        // Inner record: LEN (u32 BE) || PLAINTEXT || ZERO PADDING
+        let mut inner = vec![0u8; LEN_SIZE + self.record_size];
+        inner[..LEN_SIZE].copy_from_slice(&write_len(plaintext.len())?);
+        inner[LEN_SIZE..LEN_SIZE + plaintext.len()].copy_from_slice(plaintext);
+
+        self.cipher.seal_frame(&inner, &self.aad())
+    }
+
+    /// Open a sealed record and strip its padding
+    pub fn decrypt(&self, record: impl AsRef<[u8]>) -> Result<Vec<u8>, SqepError> {
+        let record = record.as_ref();
+        if record.len() != self.sealed_len() {
+            return Err(SqepError::RecordSizeMismatch {
+                expected: self.sealed_len(),
+                found: record.len(),
+            });
+        }
+
+        let inner = self.cipher.open_frame(record, &self.aad())?;
+        let len = read_len(&inner)?;
+        if len > self.record_size {
+            return Err(SqepError::DecryptionFailed);
+        }
+
+        // Copy the full padded region (uniform work), then truncate in O(1)
+        let mut out = inner[LEN_SIZE..].to_vec();
+        out.truncate(len);
+        Ok(out)
+    }
+
+    /// AAD binds the record size so records can't move between configurations
+    fn aad(&self) -> Vec<u8> {
+        let record_size = write_len(self.record_size).expect("record_size fits in a u32");
+        [RECORD_DOMAIN, &record_size].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_have_uniform_size() {
+        let records = FixedRecordCipher::new(ZeroshieldCipher::new(), 64);
+
+        let short = records.encrypt(b"a").expect("encrypt");
+        let long = records.encrypt([9u8; 64]).expect("encrypt");
+        let empty = records.encrypt(b"").expect("encrypt");
+        assert_eq!(short.len(), records.sealed_len());
+        assert_eq!(long.len(), records.sealed_len());
+        assert_eq!(empty.len(), records.sealed_len());
+
+        assert_eq!(records.decrypt(&short).expect("decrypt"), b"a");
+        assert_eq!(records.decrypt(&long).expect("decrypt"), vec![9u8; 64]);
+        assert!(records.decrypt(&empty).expect("decrypt").is_empty());
+    }
+
+    #[test]
+    fn tlv_records_match_their_sealed_len() {
+        let cipher = ZeroshieldCipher::new().with_frame_encoding(crate::FrameEncoding::Tlv);
+        let records = FixedRecordCipher::new(cipher, 32);
+        let sealed = records.encrypt(b"tlv").expect("encrypt");
+        assert_eq!(sealed.len(), records.sealed_len());
+        assert_eq!(records.decrypt(&sealed).expect("decrypt"), b"tlv");
+    }
+
+    #[test]
+    fn oversized_input_is_rejected() {
+        let records = FixedRecordCipher::new(ZeroshieldCipher::new(), 16);
+        assert_eq!(records.encrypt([0u8; 17]), Err(SqepError::RecordTooLarge { max: 16 }));
+
+        let mut sealed = records.encrypt(b"ok").expect("encrypt");
+        sealed.push(0);
+        assert!(matches!(
+            records.decrypt(&sealed),
+            Err(SqepError::RecordSizeMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! Chunk-aligned resumable decryption of chunked streams.
+//!
+//! A single frame has one tag over its whole body, so nothing of it can be
+//! authenticated before all of it has arrived: there is no point to resume
+//! from. Chunked streams (see [`crate::stream`]) authenticate each chunk on
+//! its own, so a `ResumableDecryptor` records how many chunks it has
+//! verified and written in a small `ResumeState`. After a dropped
+//! connection, persist the state, fetch the stream again from
+//! `ResumeState::stream_offset` and continue with
+//! `ResumableDecryptor::resume`.
+//!
+//! Only chunk-aligned resumption is possible: bytes of a chunk that wasn't
+//! verified before the interruption are fetched again. As with
+//! `AuthPolicy::StreamUnverified`, each chunk's plaintext is written as soon
+//! as its tag checks out; truncation and a bad final MAC only show up at
+//! the end of the stream, in which case everything written must be
+//! discarded. For streams with a final MAC the state also keeps the tag of
+//! every verified chunk (16 bytes each), since the MAC covers them.
+
+use std::io::{self, ErrorKind, Read, Write};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::SqepError;
+use crate::frame::{read_len, MAGIC_LEN, TAG_LEN};
+use crate::lite::ZeroshieldCipher;
+use crate::stream::{invalid, open_sealed, ChunkCodec, HEADER_LEN};
+
+/// Progress of a `ResumableDecryptor`, to persist across interruptions
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResumeState {
+    /// Stream header, once read
+    header: Option<[u8; HEADER_LEN]>,
+    /// Chunks verified and written so far
+    chunks: u64,
+    plaintext_len: u64,
+    /// Tags of the verified chunks, kept for streams with a final MAC
+    tags: Vec<u8>,
+    complete: bool,
+}
+
+impl ResumeState {
+    /// Offset into the stream where decryption continues: past the header
+    /// and every verified chunk
+    pub fn stream_offset(&self) -> u64 {
+        let Some(header) = self.header else { return 0 };
+        let chunk_size = read_len(&header[MAGIC_LEN + 1..]).expect("header holds the chunk size");
+        let sealed_chunk = (chunk_size + TAG_LEN) as u64;
+        HEADER_LEN as u64 + self.chunks.saturating_mul(sealed_chunk)
+    }
+
+    /// Plaintext bytes verified and written so far
+    pub fn plaintext_len(&self) -> u64 {
+        self.plaintext_len
+    }
+
+    /// Whether the last chunk (and the final MAC, if any) has been verified
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// Decrypts a chunked stream that may arrive over several connections
+pub struct ResumableDecryptor {
+    cipher: ZeroshieldCipher,
+    state: ResumeState,
+}
+
+impl ResumableDecryptor {
+    /// Decrypt a stream from its start
+    pub fn new(cipher: &ZeroshieldCipher) -> Self {
+        Self::resume(cipher, ResumeState::default())
+    }
+
+    /// Continue from `state`, saved from an earlier decryptor of the same
+    /// stream and key
+    pub fn resume(cipher: &ZeroshieldCipher, state: ResumeState) -> Self {
+        Self {
+            cipher: cipher.clone(),
+            state,
+        }
+    }
+
+    /// Progress so far; save it to resume after an interruption
+    pub fn state(&self) -> &ResumeState {
+        &self.state
+    }
+
+    /// Decrypt from `reader`, which must yield the stream from
+    /// `state().stream_offset()` on, into `writer` until the stream ends,
+    /// returning the plaintext bytes written by this call.
+    ///
+    /// On any error (an I/O failure of either side, or `InvalidData` for a
+    /// chunk that fails authentication) the state still points just past
+    /// the last chunk that was both verified and written in full.
+    pub fn decrypt(&mut self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<u64> {
+        let mut codec = self.codec(reader)?;
+        let mut lookahead = Vec::new();
+        let mut written = 0u64;
+        while !self.state.complete {
+            let missing = codec.window() - lookahead.len();
+            reader.take(missing as u64).read_to_end(&mut lookahead)?;
+            let chunk = codec.split_chunk(&mut lookahead).map_err(invalid)?;
+            let plaintext = open_sealed(&codec.cipher, &codec.key, &chunk).map_err(invalid)?;
+            writer.write_all(&plaintext)?;
+
+            if codec.has_final_mac() {
+                self.state.tags.extend_from_slice(chunk.tag());
+            }
+            self.state.chunks += 1;
+            self.state.plaintext_len += plaintext.len() as u64;
+            self.state.complete = chunk.last;
+            written += plaintext.len() as u64;
+        }
+        Ok(written)
+    }
+
+    /// Codec positioned after the verified chunks, reading the header off
+    /// `reader` first if the state doesn't hold it yet
+    fn codec(&mut self, reader: &mut dyn Read) -> io::Result<ChunkCodec> {
+        let header = match self.state.header {
+            Some(header) => header,
+            None => {
+                let mut header = [0u8; HEADER_LEN];
+                reader.read_exact(&mut header).map_err(|e| match e.kind() {
+                    ErrorKind::UnexpectedEof => invalid(SqepError::CiphertextTooShort),
+                    _ => e,
+                })?;
+                header
+            }
+        };
+        let mut codec = ChunkCodec::from_header(&self.cipher, header).map_err(invalid)?;
+        codec.skip_verified(self.state.chunks, &self.state.tags).map_err(invalid)?;
+        self.state.header = Some(header);
+        Ok(codec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::EncryptWriter;
+
+    /// Reader over `data` that fails once `limit` bytes have been read
+    struct Dropping<'a> {
+        data: &'a [u8],
+        limit: usize,
+    }
+
+    impl Read for Dropping<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.limit == 0 {
+                return Err(io::Error::new(ErrorKind::ConnectionReset, "connection dropped"));
+            }
+            let n = buf.len().min(self.limit).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            self.limit -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn interrupted_stream_resumes_at_a_chunk_boundary() {
+        let cipher = ZeroshieldCipher::new();
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        for final_mac in [false, true] {
+            let mut enc = EncryptWriter::new(&cipher, Vec::new())
+                .expect("writer")
+                .with_chunk_size(64)
+                .with_final_mac(final_mac);
+            enc.write_all(&data).expect("write");
+            let stream = enc.finish().expect("finish");
+
+            // The connection drops partway into the sixth chunk
+            let mut out = Vec::new();
+            let mut first = ResumableDecryptor::new(&cipher);
+            let mut source = Dropping { data: &stream, limit: HEADER_LEN + 5 * 80 + 30 };
+            let err = first.decrypt(&mut source, &mut out).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+            let state = first.state().clone();
+            assert_eq!((state.plaintext_len(), state.is_complete()), (out.len() as u64, false));
+            assert_eq!(state.stream_offset(), (HEADER_LEN + out.len() / 64 * 80) as u64);
+
+            let mut resumed = ResumableDecryptor::resume(&cipher, state);
+            let offset = resumed.state().stream_offset() as usize;
+            resumed.decrypt(&mut &stream[offset..], &mut out).expect("resume");
+            assert!(resumed.state().is_complete());
+            assert_eq!(out, data);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Caller-owned working buffers for allocation-light encryption.
+//!
+//! `encrypt_with_meta` allocates the header, AAD and sealed body afresh for
+//! every frame before copying them into the frame. `encrypt_reusing` builds
+//! them in a `ScratchBuffers` instead, which keeps its capacity between
+//! calls: once it has grown to the largest message, only the returned frame
+//! (and its `SealMeta` hash) is allocated per call.
+//!
+//! The buffers only ever hold the header and the masked, sealed body at
+//! rest, never the plaintext, so they are not zeroized.
+
+use crate::error::SqepError;
+use crate::frame::NONCE_LEN;
+use crate::lite::{header_aad_into, random_nonce, SealMeta, ZeroshieldCipher};
+
+/// Working buffers reused across `encrypt_reusing` calls.
+///
+/// One per thread: the buffers are overwritten by every call.
+#[derive(Debug, Default)]
+pub struct ScratchBuffers {
+    header: Vec<u8>,
+    aad: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl ScratchBuffers {
+    /// Empty buffers; they grow to fit on first use
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ZeroshieldCipher {
+    /// `encrypt_with_meta` building its intermediates in `scratch`.
+    ///
+    /// Produces the same frames, in the configured `FrameEncoding`; only the
+    /// returned frame gets a fresh buffer (two for TLV frames, which are
+    /// re-encoded from the compact one).
+    pub fn encrypt_reusing(
+        &self,
+        plaintext: impl AsRef<[u8]>,
+        scratch: &mut ScratchBuffers,
+    ) -> Result<(Vec<u8>, SealMeta), SqepError> {
+        let plaintext = plaintext.as_ref();
+        let ScratchBuffers { header, aad, body } = scratch;
+        header.clear();
+        self.write_frame_header(header);
+
+        header_aad_into(header, &[], aad);
+
+        let nonce_bytes = random_nonce();
+        let key = self.aead_key()?;
+        let mask = self.config().mask();
+        self.seal_body_into(&key, mask, &nonce_bytes, plaintext, aad, body)?;
+        self.config().tag_position.place(body);
+
+        let mut frame = Vec::with_capacity(header.len() + NONCE_LEN + body.len());
+        frame.extend_from_slice(header);
+        frame.extend_from_slice(&nonce_bytes);
+        frame.extend_from_slice(body);
+        let frame = self.config().frame_encoding.encode(frame)?;
+        let meta = self.frame_meta(plaintext.len(), &frame, self.clock())?;
+        Ok((frame, meta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KdfHash;
+
+    #[test]
+    fn reused_buffers_produce_ordinary_frames() {
+        let mut scratch = ScratchBuffers::new();
+        // A classic header and a `SQEP4.2-LITE` one, which is bound via AAD
+        let extended = ZeroshieldCipher::new().with_kdf_hash(KdfHash::Sha512);
+        for cipher in [ZeroshieldCipher::new(), extended] {
+            let (big, _m) = cipher.encrypt_reusing([7u8; 4096], &mut scratch).expect("encrypt");
+            let (small, meta) = cipher.encrypt_reusing(b"small", &mut scratch).expect("encrypt");
+            assert_eq!(cipher.decrypt(&big).unwrap(), [7u8; 4096]);
+            assert_eq!(cipher.decrypt(&small).unwrap(), b"small");
+            assert_eq!(meta.hash.len(), 64);
+        }
+        assert!(scratch.body.capacity() >= 4096);
+    }
+}
@@ -0,0 +1,1015 @@
+//! Chunked streaming encryption for inputs too large to buffer.
+//!
+//! ```text
+//! HEADER  = "SQEP4.0-STRM" (12) || FLAGS (1) || CHUNK_SIZE (u32 BE) || NONCE_PREFIX (8)
+//! STREAM  = HEADER || CHUNK_0 || ... || CHUNK_n [|| MAC (32)]
+//! CHUNK_i = CIPHERTEXT+TAG of CHUNK_SIZE plaintext bytes (fewer for CHUNK_n)
+//! ```
+//!
+//! Chunk `i` is sealed with nonce `NONCE_PREFIX || i (u32 BE)` and AAD
+//! `HEADER || LAST`, where `LAST` is 1 for the final chunk and 0 otherwise.
+//! Every chunk but the last holds exactly `CHUNK_SIZE` bytes and the last
+//! holds fewer (possibly none), so reordered, dropped or truncated chunks
+//! fail authentication. Streams always use ChaCha20-Poly1305.
+//!
+//! With `with_final_mac(true)` the stream ends with
+//! `MAC = HMAC-SHA256(mac_key, SHA-256(HEADER || TAG_0 || ... || TAG_n))`,
+//! a single whole-stream integrity check verified after the last chunk.
+//! `mac_key` is a subkey of the cipher's key.
+//!
+//! A stream can only be verified as a whole once it has been read to the
+//! end: every chunk carries its own tag, but truncation after a full chunk
+//! and a bad final MAC only show up at the last chunk. `DecryptReader`
+//! therefore buffers the whole stream before releasing any plaintext unless
+//! `AuthPolicy::StreamUnverified` is chosen.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use ring::hmac;
+use sha2::{Digest, Sha256};
+
+use crate::algorithm::{AeadKey, Algorithm};
+use crate::error::SqepError;
+use crate::frame::{read_len, write_len, LEN_SIZE, MAGIC_LEN, NONCE_LEN, STREAM_MAGIC, TAG_LEN};
+use crate::kdf::MaskParams;
+#[cfg(feature = "mmap")]
+use crate::lite::qt_xor_in_place;
+use crate::lite::{random_nonce, ZeroshieldCipher};
+use crate::telemetry;
+
+/// Plaintext bytes per chunk unless configured otherwise
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+/// Largest chunk size accepted from a stream header
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+const FLAG_FINAL_MAC: u8 = 0x01;
+const PREFIX_LEN: usize = 8;
+pub(crate) const HEADER_LEN: usize = MAGIC_LEN + 1 + LEN_SIZE + PREFIX_LEN;
+const MAC_LEN: usize = 32;
+const MAC_LABEL: &[u8] = b"SQEP:LITE:STREAM-MAC:v1";
+
+impl ZeroshieldCipher {
+    /// Encrypt everything from `reader` into `writer` as a chunked stream
+    /// with default settings, returning the plaintext byte count
+    pub fn encrypt_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<u64> {
+        let mut enc = EncryptWriter::new(self, writer).map_err(other)?;
+        match io::copy(reader, &mut enc) {
+            Ok(copied) => enc.finish().map(|_| copied),
+            Err(e) => {
+                enc.abandon();
+                Err(e)
+            }
+        }
+    }
+
+    /// `encrypt_stream`, checking `cancel` before reading each chunk.
+    ///
+    /// Once `cancel` is set (e.g. by a GUI thread), the stream is abandoned
+    /// without its final chunk and the call fails with `Cancelled`; what
+    /// `writer` received so far will never decrypt.
+    pub fn encrypt_stream_cancellable(
+        &self,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        cancel: &AtomicBool,
+    ) -> io::Result<u64> {
+        let mut enc = EncryptWriter::new(self, writer).map_err(other)?;
+        match copy_cancellable(reader, &mut enc, cancel) {
+            Ok(copied) => enc.finish().map(|_| copied),
+            Err(e) => {
+                enc.abandon();
+                Err(e)
+            }
+        }
+    }
+
+    /// Encrypt everything from `reader` into a chunked stream file at
+    /// `output_path`, cancellable like `encrypt_stream_cancellable`.
+    ///
+    /// The stream is written to `<output_path>.partial` and only renamed to
+    /// `output_path` once complete and synced. On any error, `Cancelled`
+    /// included, the partial file is removed and `output_path` is untouched.
+    pub fn encrypt_stream_to_file(
+        &self,
+        reader: &mut dyn Read,
+        output_path: &str,
+        cancel: &AtomicBool,
+    ) -> io::Result<u64> {
+        trace_span!("sqep.encrypt_file", fingerprint = %self.fingerprint());
+        let partial = format!("{}.partial", output_path);
+        let written = File::create(&partial).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            let copied = self.encrypt_stream_cancellable(reader, &mut out, cancel)?;
+            out.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+            Ok(copied)
+        });
+        match written.and_then(|copied| fs::rename(&partial, output_path).map(|()| copied)) {
+            Ok(copied) => Ok(copied),
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                Err(e)
+            }
+        }
+    }
+
+    /// Decrypt a chunked stream from `reader` into `writer`, returning the
+    /// plaintext byte count.
+    ///
+    /// Chunks are written as they authenticate; on error, `writer` may
+    /// already hold a verified prefix of the plaintext.
+    pub fn decrypt_stream(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> io::Result<u64> {
+        let mut source =
+            DecryptReader::new(self, reader).with_auth_policy(AuthPolicy::StreamUnverified);
+        io::copy(&mut source, writer)
+    }
+
+    /// `decrypt_stream_parallel_with` using one worker per available CPU
+    pub fn decrypt_stream_parallel(
+        &self,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+    ) -> io::Result<u64> {
+        let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        self.decrypt_stream_parallel_with(reader, writer, workers)
+    }
+
+    /// Decrypt a chunked stream like `decrypt_stream`, opening chunks on
+    /// `in_flight` worker threads.
+    ///
+    /// At most `in_flight` chunks are held between being read and being
+    /// written, so memory stays around `in_flight × chunk_size`. Plaintext
+    /// is still written in order and only once authenticated; on error,
+    /// `writer` may hold a verified prefix.
+    ///
+    /// # Panics
+    /// Panics if `in_flight` is 0.
+    pub fn decrypt_stream_parallel_with(
+        &self,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        in_flight: usize,
+    ) -> io::Result<u64> {
+        assert!(in_flight > 0, "in_flight must be at least 1");
+        let mut source = DecryptReader::new(self, reader);
+        let key = self.aead_key_for(Algorithm::ChaCha20Poly1305).map_err(other)?;
+        let (job_tx, job_rx) = mpsc::channel::<(u64, SealedChunk)>();
+        let (done_tx, done_rx) = mpsc::channel();
+        let job_rx = Mutex::new(job_rx);
+
+        thread::scope(|scope| {
+            for _ in 0..in_flight {
+                let (job_rx, done_tx, key) = (&job_rx, done_tx.clone(), &key);
+                scope.spawn(move || loop {
+                    let job = job_rx.lock().expect("job queue poisoned").recv();
+                    let Ok((index, chunk)) = job else { break };
+                    if done_tx.send((index, open_sealed(self, key, &chunk))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(done_tx);
+
+            // Dropping the job queue on return stops the workers
+            let jobs = job_tx;
+            pump_in_order(&mut source, writer, &jobs, &done_rx, in_flight as u64)
+        })
+    }
+}
+
+/// A chunk read off the stream with its nonce and AAD, not yet opened
+pub(crate) struct SealedChunk {
+    nonce: [u8; NONCE_LEN],
+    aad: Vec<u8>,
+    body: Vec<u8>,
+    pub(crate) last: bool,
+}
+
+impl SealedChunk {
+    /// The chunk's AEAD tag
+    pub(crate) fn tag(&self) -> &[u8] {
+        &self.body[self.body.len() - TAG_LEN..]
+    }
+}
+
+/// Authenticate and decrypt one chunk
+pub(crate) fn open_sealed(
+    cipher: &ZeroshieldCipher,
+    key: &AeadKey,
+    chunk: &SealedChunk,
+) -> Result<Vec<u8>, SqepError> {
+    let opened =
+        cipher.open_body_using(key, MaskParams::DEFAULT, &chunk.nonce, &chunk.body, &chunk.aad);
+    telemetry::opened(opened)
+}
+
+/// Authenticate and decrypt one chunk straight into the front of `out`,
+/// returning its plaintext length. `out` is zeroed again on failure.
+#[cfg(feature = "mmap")]
+fn open_sealed_into(
+    cipher: &ZeroshieldCipher,
+    key: &AeadKey,
+    chunk: &SealedChunk,
+    out: &mut [u8],
+) -> Result<usize, SqepError> {
+    let (ciphertext, tag) = chunk.body.split_at(chunk.body.len() - TAG_LEN);
+    let needed = ciphertext.len();
+    let out = out.get_mut(..needed).ok_or(SqepError::BufferTooSmall { needed })?;
+    out.copy_from_slice(ciphertext);
+
+    let aad = cipher.body_aad(&chunk.aad, needed);
+    let opened = key.open_in_place_detached(&chunk.nonce, &aad, out, tag).map(|()| {
+        qt_xor_in_place(out, cipher.key_bytes(), &chunk.nonce, MaskParams::DEFAULT);
+        needed
+    });
+    if opened.is_err() {
+        out.fill(0);
+    }
+    telemetry::opened(opened)
+}
+
+/// Feed chunks from `source` to the workers, keeping at most `in_flight`
+/// outstanding, and write their plaintext back in stream order
+fn pump_in_order<R: Read>(
+    source: &mut DecryptReader<R>,
+    writer: &mut dyn Write,
+    jobs: &mpsc::Sender<(u64, SealedChunk)>,
+    done: &mpsc::Receiver<(u64, Result<Vec<u8>, SqepError>)>,
+    in_flight: u64,
+) -> io::Result<u64> {
+    let mut pending = BTreeMap::new();
+    let (mut next_read, mut next_write, mut written) = (0u64, 0u64, 0u64);
+    let mut eof = false;
+    while !eof || next_write < next_read {
+        while !eof && next_read - next_write < in_flight {
+            let chunk = source.next_sealed().map_err(invalid)?;
+            eof = chunk.last;
+            jobs.send((next_read, chunk)).expect("stream workers alive");
+            next_read += 1;
+        }
+
+        let (index, plaintext) = done.recv().expect("stream workers alive");
+        pending.insert(index, plaintext);
+        while let Some(plaintext) = pending.remove(&next_write) {
+            let plaintext = plaintext.map_err(invalid)?;
+            writer.write_all(&plaintext)?;
+            written += plaintext.len() as u64;
+            next_write += 1;
+        }
+    }
+    Ok(written)
+}
+
+/// Per-stream sealing/opening state shared by the writers and readers
+pub(crate) struct ChunkCodec {
+    pub(crate) cipher: ZeroshieldCipher,
+    pub(crate) key: AeadKey,
+    pub(crate) header: [u8; HEADER_LEN],
+    counter: u64,
+    /// `SHA-256(HEADER || TAG_0 || ...)` when the final MAC is enabled
+    tags: Option<Sha256>,
+}
+
+impl ChunkCodec {
+    fn new(cipher: &ZeroshieldCipher, header: [u8; HEADER_LEN]) -> Result<Self, SqepError> {
+        Ok(Self {
+            cipher: cipher.clone(),
+            key: cipher.aead_key_for(Algorithm::ChaCha20Poly1305)?,
+            header,
+            counter: 0,
+            tags: tag_hasher(&header),
+        })
+    }
+
+    /// Codec for a new stream with a random nonce prefix and default settings
+    pub(crate) fn for_new_stream(cipher: &ZeroshieldCipher) -> Result<Self, SqepError> {
+        let prefix = random_nonce();
+        let mut header = [0u8; HEADER_LEN];
+        header[..MAGIC_LEN].copy_from_slice(STREAM_MAGIC);
+        header[HEADER_LEN - PREFIX_LEN..].copy_from_slice(&prefix[..PREFIX_LEN]);
+
+        let mut codec = Self::new(cipher, header)?;
+        codec.configure(DEFAULT_CHUNK_SIZE, false);
+        Ok(codec)
+    }
+
+    /// Codec for a stream read off the wire, after checking its header
+    pub(crate) fn from_header(
+        cipher: &ZeroshieldCipher,
+        header: [u8; HEADER_LEN],
+    ) -> Result<Self, SqepError> {
+        if &header[..MAGIC_LEN] != STREAM_MAGIC || header[MAGIC_LEN] & !FLAG_FINAL_MAC != 0 {
+            return Err(SqepError::InvalidHeader);
+        }
+        let codec = Self::new(cipher, header)?;
+        if !(1..=MAX_CHUNK_SIZE).contains(&codec.chunk_size()) {
+            return Err(SqepError::InvalidHeader);
+        }
+        Ok(codec)
+    }
+
+    /// Record the chunk size and final MAC flag in the header
+    pub(crate) fn configure(&mut self, chunk_size: usize, final_mac: bool) {
+        self.header[MAGIC_LEN] = if final_mac { FLAG_FINAL_MAC } else { 0 };
+        self.header[MAGIC_LEN + 1..MAGIC_LEN + 1 + LEN_SIZE]
+            .copy_from_slice(&write_len(chunk_size).expect("chunk size fits in a u32"));
+        self.tags = tag_hasher(&self.header);
+    }
+
+    pub(crate) fn chunk_size(&self) -> usize {
+        read_len(&self.header[MAGIC_LEN + 1..]).expect("header holds the chunk size")
+    }
+
+    pub(crate) fn has_final_mac(&self) -> bool {
+        self.tags.is_some()
+    }
+
+    /// Continue after `chunks` chunks that were already verified; `tags`
+    /// are their tags, in order, for the final MAC (empty without one)
+    pub(crate) fn skip_verified(&mut self, chunks: u64, tags: &[u8]) -> Result<(), SqepError> {
+        let expected = if self.has_final_mac() { chunks.saturating_mul(TAG_LEN as u64) } else { 0 };
+        if self.counter != 0 || tags.len() as u64 != expected {
+            return Err(SqepError::InvalidHeader);
+        }
+        self.counter = chunks;
+        if let Some(hasher) = self.tags.as_mut() {
+            hasher.update(tags);
+        }
+        Ok(())
+    }
+
+    /// Plaintext length of a whole stream of `stream_len` bytes, from its
+    /// layout alone (`CiphertextTooShort` if no stream has that length)
+    #[cfg(feature = "mmap")]
+    fn plaintext_len(&self, stream_len: u64) -> Result<u64, SqepError> {
+        let tag = TAG_LEN as u64;
+        let body = stream_len
+            .checked_sub((HEADER_LEN + self.mac_len()) as u64)
+            .ok_or(SqepError::CiphertextTooShort)?;
+        let sealed_chunk = self.chunk_size() as u64 + tag;
+        if body % sealed_chunk < tag {
+            return Err(SqepError::CiphertextTooShort);
+        }
+        Ok(body - (body / sealed_chunk + 1) * tag)
+    }
+
+    /// Bytes to read ahead before a chunk can be split off: one sealed
+    /// chunk plus the MAC, since only the shorter last chunk leaves less
+    pub(crate) fn window(&self) -> usize {
+        self.chunk_size() + TAG_LEN + self.mac_len()
+    }
+
+    /// Split the next chunk off `lookahead`, which holds a full `window`
+    /// unless the input ended. The final MAC is checked with the last chunk.
+    pub(crate) fn split_chunk(
+        &mut self,
+        lookahead: &mut Vec<u8>,
+    ) -> Result<SealedChunk, SqepError> {
+        let filled = lookahead.len();
+        if filled == self.window() {
+            let body = lookahead.drain(..self.chunk_size() + TAG_LEN).collect();
+            return self.take_next(body, false);
+        }
+        let mac_len = self.mac_len();
+        if filled < TAG_LEN + mac_len {
+            return Err(SqepError::CiphertextTooShort);
+        }
+        let mac = lookahead.split_off(filled - mac_len);
+        let chunk = self.take_next(std::mem::take(lookahead), true)?;
+        self.verify_final_mac(&mac)?;
+        Ok(chunk)
+    }
+
+    /// Seal the last chunk, followed by the final MAC if enabled
+    pub(crate) fn seal_last(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, SqepError> {
+        let mut sealed = self.seal(plaintext, true)?;
+        if let Some(mac) = self.final_mac() {
+            sealed.extend_from_slice(mac.as_ref());
+        }
+        Ok(sealed)
+    }
+
+    /// Nonce and AAD of the next chunk
+    fn next_params(&mut self, last: bool) -> Result<([u8; NONCE_LEN], Vec<u8>), SqepError> {
+        let counter = u32::try_from(self.counter).map_err(|_| SqepError::StreamTooLong)?;
+        self.counter += 1;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..PREFIX_LEN].copy_from_slice(&self.header[HEADER_LEN - PREFIX_LEN..]);
+        nonce[PREFIX_LEN..].copy_from_slice(&counter.to_be_bytes());
+        Ok((nonce, [&self.header[..], &[last as u8]].concat()))
+    }
+
+    pub(crate) fn seal(&mut self, plaintext: &[u8], last: bool) -> Result<Vec<u8>, SqepError> {
+        let (nonce, aad) = self.next_params(last)?;
+        let body = self.cipher.seal_body_using(
+            &self.key,
+            MaskParams::DEFAULT,
+            &nonce,
+            plaintext,
+            &aad,
+        )?;
+        self.absorb_tag(&body);
+        Ok(body)
+    }
+
+    /// Assign the next nonce and AAD to a chunk read off the stream
+    fn take_next(&mut self, body: Vec<u8>, last: bool) -> Result<SealedChunk, SqepError> {
+        let (nonce, aad) = self.next_params(last)?;
+        self.absorb_tag(&body);
+        Ok(SealedChunk {
+            nonce,
+            aad,
+            body,
+            last,
+        })
+    }
+
+    fn absorb_tag(&mut self, body: &[u8]) {
+        if let Some(tags) = self.tags.as_mut() {
+            tags.update(&body[body.len() - TAG_LEN..]);
+        }
+    }
+
+    fn mac_len(&self) -> usize {
+        if self.tags.is_some() {
+            MAC_LEN
+        } else {
+            0
+        }
+    }
+
+    fn mac_key(&self) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, self.cipher.derive_subkey(MAC_LABEL).key_bytes())
+    }
+
+    fn final_mac(&self) -> Option<hmac::Tag> {
+        let digest = self.tags.clone()?.finalize();
+        Some(hmac::sign(&self.mac_key(), &digest))
+    }
+
+    fn verify_final_mac(&self, mac: &[u8]) -> Result<(), SqepError> {
+        match self.tags.clone() {
+            Some(tags) => hmac::verify(&self.mac_key(), &tags.finalize(), mac)
+                .map_err(|_| SqepError::DecryptionFailed),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `Write` adapter that encrypts into a chunked stream.
+///
+/// Call `finish` once all data is written: it seals the final chunk (and
+/// the MAC), without which the stream fails to decrypt. Dropping an
+/// unfinished writer is a bug caught by a debug assertion; use `abandon`
+/// to discard a stream on purpose.
+pub struct EncryptWriter<W: Write> {
+    /// `None` once `finish` or `abandon` took it
+    inner: Option<W>,
+    codec: ChunkCodec,
+    chunk_size: usize,
+    buf: Vec<u8>,
+    header_written: bool,
+    /// A write failed, so the stream is unusable anyway
+    failed: bool,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    /// Start a stream over `inner` with `DEFAULT_CHUNK_SIZE` and no final MAC
+    pub fn new(cipher: &ZeroshieldCipher, inner: W) -> Result<Self, SqepError> {
+        Ok(Self {
+            inner: Some(inner),
+            codec: ChunkCodec::for_new_stream(cipher)?,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            buf: Vec::new(),
+            header_written: false,
+            failed: false,
+        })
+    }
+
+    /// Plaintext bytes per chunk (call before writing).
+    ///
+    /// # Panics
+    /// Panics if `chunk_size` is 0 or above `MAX_CHUNK_SIZE`.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(
+            (1..=MAX_CHUNK_SIZE).contains(&chunk_size),
+            "chunk_size must be between 1 and MAX_CHUNK_SIZE"
+        );
+        let final_mac = self.codec.has_final_mac();
+        self.set_header(chunk_size, final_mac);
+        self
+    }
+
+    /// Append a whole-stream HMAC after the last chunk (call before writing)
+    pub fn with_final_mac(mut self, enabled: bool) -> Self {
+        let chunk_size = self.chunk_size;
+        self.set_header(chunk_size, enabled);
+        self
+    }
+
+    /// Seal the final chunk and MAC, flush, and return the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let last = std::mem::take(&mut self.buf);
+        self.emit(&last, true)?;
+        let mut inner = self.inner.take().expect("writer not finished yet");
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    /// Give up on the stream without sealing the final chunk, e.g. on an
+    /// error path. What was written so far will not decrypt.
+    pub fn abandon(mut self) -> W {
+        self.inner.take().expect("writer not finished yet")
+    }
+
+    fn inner(&mut self) -> &mut W {
+        self.inner.as_mut().expect("writer not finished yet")
+    }
+
+    fn set_header(&mut self, chunk_size: usize, final_mac: bool) {
+        assert!(
+            !self.header_written && self.buf.is_empty(),
+            "stream settings must be chosen before writing"
+        );
+        self.codec.configure(chunk_size, final_mac);
+        self.chunk_size = chunk_size;
+    }
+
+    fn emit(&mut self, plaintext: &[u8], last: bool) -> io::Result<()> {
+        let result = self.try_emit(plaintext, last);
+        self.failed |= result.is_err();
+        result
+    }
+
+    fn try_emit(&mut self, plaintext: &[u8], last: bool) -> io::Result<()> {
+        if !self.header_written {
+            let header = self.codec.header;
+            self.inner().write_all(&header)?;
+            self.header_written = true;
+        }
+        let body = if last {
+            self.codec.seal_last(plaintext)
+        } else {
+            self.codec.seal(plaintext, false)
+        };
+        let body = body.map_err(other)?;
+        self.inner().write_all(&body)
+    }
+}
+
+impl<W: Write> Drop for EncryptWriter<W> {
+    fn drop(&mut self) {
+        debug_assert!(
+            self.inner.is_none() || self.failed || std::thread::panicking(),
+            "EncryptWriter dropped without finish(): the final chunk was never sealed"
+        );
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let take = data.len().min(self.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..take]);
+
+        // A full buffer is never the final chunk: that one is sealed by `finish`
+        if self.buf.len() == self.chunk_size {
+            let mut chunk = std::mem::take(&mut self.buf);
+            self.emit(&chunk, false)?;
+            chunk.clear();
+            self.buf = chunk;
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner().flush()
+    }
+}
+
+/// When `DecryptReader` releases plaintext
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AuthPolicy {
+    /// Read and verify the whole stream, including the last chunk and the
+    /// final MAC, before the first byte is returned (default). Memory grows
+    /// with the plaintext.
+    #[default]
+    BufferThenAuth,
+    /// Return each chunk as soon as its own tag checks out. The stream as a
+    /// whole is not verified yet: a reader may consume a prefix of a stream
+    /// that later turns out truncated or fails its final MAC, and must
+    /// discard everything if a later read errors.
+    StreamUnverified,
+}
+
+/// `Read` adapter that decrypts a chunked stream.
+///
+/// By default (`AuthPolicy::BufferThenAuth`) nothing is returned until the
+/// entire stream has authenticated, so corrupted input never yields a
+/// byte. `AuthPolicy::StreamUnverified` releases plaintext chunk by chunk
+/// for throughput and constant memory, with the final MAC (if present)
+/// only checked after the last chunk.
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    cipher: ZeroshieldCipher,
+    policy: AuthPolicy,
+    codec: Option<ChunkCodec>,
+    lookahead: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    done: bool,
+    failed: Option<SqepError>,
+}
+
+impl<R: Read> DecryptReader<R> {
+    /// Decrypt the stream read from `inner`
+    pub fn new(cipher: &ZeroshieldCipher, inner: R) -> Self {
+        Self {
+            inner,
+            cipher: cipher.clone(),
+            policy: AuthPolicy::default(),
+            codec: None,
+            lookahead: Vec::new(),
+            out: Vec::new(),
+            out_pos: 0,
+            done: false,
+            failed: None,
+        }
+    }
+
+    /// Choose when plaintext is released (call before reading)
+    pub fn with_auth_policy(mut self, policy: AuthPolicy) -> Self {
+        assert!(self.codec.is_none(), "auth policy must be chosen before reading");
+        self.policy = policy;
+        self
+    }
+
+    /// Read and check the header on first use
+    fn codec(&mut self) -> Result<&mut ChunkCodec, SqepError> {
+        if self.codec.is_none() {
+            let mut header = [0u8; HEADER_LEN];
+            self.inner.read_exact(&mut header).map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => SqepError::CiphertextTooShort,
+                _ => e.into(),
+            })?;
+            self.codec = Some(ChunkCodec::from_header(&self.cipher, header)?);
+        }
+        Ok(self.codec.as_mut().expect("codec initialised above"))
+    }
+
+    /// Plaintext length of the stream if it is `stream_len` bytes long
+    #[cfg(feature = "mmap")]
+    pub(crate) fn plaintext_len(&mut self, stream_len: u64) -> Result<u64, SqepError> {
+        self.codec()?.plaintext_len(stream_len)
+    }
+
+    /// Decrypt the next chunk into the front of `out`, returning its
+    /// length, or `None` once the last chunk has been read
+    #[cfg(feature = "mmap")]
+    pub(crate) fn next_chunk_into(&mut self, out: &mut [u8]) -> Result<Option<usize>, SqepError> {
+        if self.done {
+            return Ok(None);
+        }
+        let chunk = self.next_sealed()?;
+        let codec = self.codec.as_ref().expect("codec initialised by next_sealed");
+        open_sealed_into(&codec.cipher, &codec.key, &chunk, out).map(Some)
+    }
+
+    /// Authenticate the rest of the stream, final MAC included, discarding
+    /// the plaintext
+    pub(crate) fn verify_to_end(&mut self) -> Result<(), SqepError> {
+        while !self.done {
+            self.next_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Decrypt the next chunk, or the whole stream under `BufferThenAuth`,
+    /// into `out`
+    fn fill(&mut self) -> Result<(), SqepError> {
+        if self.policy == AuthPolicy::StreamUnverified {
+            return self.next_chunk();
+        }
+        let mut all = Vec::new();
+        while !self.done {
+            self.next_chunk()?;
+            all.append(&mut self.out);
+        }
+        self.out = all;
+        self.out_pos = 0;
+        Ok(())
+    }
+
+    /// Decrypt the next chunk into `out`
+    fn next_chunk(&mut self) -> Result<(), SqepError> {
+        let chunk = self.next_sealed()?;
+        let codec = self.codec.as_ref().expect("codec initialised by next_sealed");
+        self.out = open_sealed(&codec.cipher, &codec.key, &chunk)?;
+        self.out_pos = 0;
+        Ok(())
+    }
+
+    /// Read the next chunk; the final MAC is checked along with the last one
+    fn next_sealed(&mut self) -> Result<SealedChunk, SqepError> {
+        let window = self.codec()?.window();
+        let filled = self.lookahead.len();
+        self.lookahead.resize(window, 0);
+        let filled = filled + read_full(&mut self.inner, &mut self.lookahead[filled..])?;
+        self.lookahead.truncate(filled);
+
+        let codec = self.codec.as_mut().expect("codec initialised above");
+        let chunk = codec.split_chunk(&mut self.lookahead)?;
+        self.done = chunk.last;
+        Ok(chunk)
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(e) = &self.failed {
+                return Err(invalid(e.clone()));
+            }
+            if self.out_pos < self.out.len() {
+                let n = buf.len().min(self.out.len() - self.out_pos);
+                buf[..n].copy_from_slice(&self.out[self.out_pos..self.out_pos + n]);
+                self.out_pos += n;
+                return Ok(n);
+            }
+            if self.done {
+                return Ok(0);
+            }
+            if let Err(e) = self.fill() {
+                self.failed = Some(e);
+            }
+        }
+    }
+}
+
+/// Running hash of the header and chunk tags, if the final MAC is enabled
+fn tag_hasher(header: &[u8; HEADER_LEN]) -> Option<Sha256> {
+    (header[MAGIC_LEN] & FLAG_FINAL_MAC != 0).then(|| {
+        let mut hasher = Sha256::new();
+        hasher.update(header);
+        hasher
+    })
+}
+
+/// `io::copy` one chunk at a time, failing with `Cancelled` once `cancel` is set
+fn copy_cancellable(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    cancel: &AtomicBool,
+) -> io::Result<u64> {
+    let mut buf = vec![0u8; DEFAULT_CHUNK_SIZE];
+    let mut copied = 0u64;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(other(SqepError::Cancelled));
+        }
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(copied),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buf[..n])?;
+        copied += n as u64;
+    }
+}
+
+/// Read until `buf` is full or EOF, returning the bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, SqepError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+pub(crate) fn other(e: SqepError) -> io::Error {
+    io::Error::new(ErrorKind::Other, e)
+}
+
+pub(crate) fn invalid(e: SqepError) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seal(cipher: &ZeroshieldCipher, data: &[u8], chunk_size: usize, mac: bool) -> Vec<u8> {
+        let mut enc = EncryptWriter::new(cipher, Vec::new())
+            .expect("writer")
+            .with_chunk_size(chunk_size)
+            .with_final_mac(mac);
+        enc.write_all(data).expect("write");
+        enc.finish().expect("finish")
+    }
+
+    fn open(cipher: &ZeroshieldCipher, stream: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        cipher.decrypt_stream(&mut &stream[..], &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn stream_roundtrip_across_chunk_boundaries() {
+        let cipher = ZeroshieldCipher::new();
+        for len in [0, 1, 63, 64, 65, 128, 1000] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            for mac in [false, true] {
+                let stream = seal(&cipher, &data, 64, mac);
+                assert_eq!(open(&cipher, &stream).expect("decrypt"), data, "len {}", len);
+            }
+        }
+
+        let mut sealed = Vec::new();
+        let data = vec![7u8; 200_000];
+        cipher.encrypt_stream(&mut &data[..], &mut sealed).expect("encrypt");
+        assert_eq!(open(&cipher, &sealed).unwrap(), data);
+    }
+
+    #[test]
+    fn finish_seals_a_partial_final_chunk() {
+        let cipher = ZeroshieldCipher::new();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        let mut enc = EncryptWriter::new(&cipher, Vec::new()).expect("writer").with_chunk_size(64);
+        for piece in data.chunks(37) {
+            enc.write_all(piece).expect("write");
+        }
+        let stream = enc.finish().expect("finish");
+
+        // 15 full chunks and a 40-byte final one
+        assert_eq!(stream.len(), HEADER_LEN + 15 * (64 + TAG_LEN) + 40 + TAG_LEN);
+        assert_eq!(open(&cipher, &stream).expect("decrypt"), data);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "EncryptWriter dropped without finish()")]
+    fn dropping_an_unfinished_writer_panics_in_debug() {
+        let cipher = ZeroshieldCipher::new();
+        let mut enc = EncryptWriter::new(&cipher, Vec::new()).expect("writer");
+        enc.write_all(b"never sealed").expect("write");
+    }
+
+    #[test]
+    fn parallel_decrypt_keeps_order_with_two_in_flight() {
+        let cipher = ZeroshieldCipher::new();
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        for mac in [false, true] {
+            let stream = seal(&cipher, &data, 4096, mac);
+            let mut out = Vec::new();
+            let n = cipher
+                .decrypt_stream_parallel_with(&mut &stream[..], &mut out, 2)
+                .expect("decrypt");
+            assert_eq!(n, data.len() as u64);
+            assert_eq!(out, data);
+        }
+
+        // A bad chunk stops the output at the verified prefix before it
+        let mut stream = seal(&cipher, &data, 4096, false);
+        stream[HEADER_LEN + 100 * (4096 + TAG_LEN) + 7] ^= 1;
+        let mut out = Vec::new();
+        let err = cipher.decrypt_stream_parallel_with(&mut &stream[..], &mut out, 2).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(out, data[..100 * 4096]);
+    }
+
+    #[test]
+    fn truncation_and_reordering_fail() {
+        let cipher = ZeroshieldCipher::new();
+        let stream = seal(&cipher, &[1u8; 256], 64, false);
+        let sealed_chunk = 64 + TAG_LEN;
+
+        // Drop the final (empty) chunk: the stream now ends on a full chunk
+        assert!(open(&cipher, &stream[..stream.len() - TAG_LEN]).is_err());
+        // Cut the stream right after a full chunk
+        assert!(open(&cipher, &stream[..HEADER_LEN + sealed_chunk]).is_err());
+
+        let mut swapped = stream.clone();
+        let (a, b) = (HEADER_LEN, HEADER_LEN + sealed_chunk);
+        let first = stream[a..b].to_vec();
+        swapped.copy_within(b..b + sealed_chunk, a);
+        swapped[b..b + sealed_chunk].copy_from_slice(&first);
+        assert!(open(&cipher, &swapped).is_err());
+    }
+
+    #[test]
+    fn buffer_then_auth_never_yields_corrupted_streams() {
+        let cipher = ZeroshieldCipher::new();
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let stream = seal(&cipher, &data, 64, true);
+        let mut out = Vec::new();
+        DecryptReader::new(&cipher, &stream[..]).read_to_end(&mut out).expect("decrypt");
+        assert_eq!(out, data);
+
+        // Only the final MAC is wrong: every chunk still opens on its own
+        let mut bad_mac = stream.clone();
+        *bad_mac.last_mut().unwrap() ^= 0x01;
+        let truncated = &stream[..HEADER_LEN + 3 * (64 + TAG_LEN)];
+        for corrupted in [&bad_mac[..], truncated] {
+            let mut reader = DecryptReader::new(&cipher, corrupted);
+            let mut buf = [0u8; 16];
+            let err = reader.read(&mut buf).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+            // Unverified streaming hands out the chunks before failing
+            let mut out = Vec::new();
+            let unverified = DecryptReader::new(&cipher, corrupted)
+                .with_auth_policy(AuthPolicy::StreamUnverified)
+                .read_to_end(&mut out);
+            assert!(unverified.is_err());
+            assert_eq!(out, data[..out.len()]);
+            assert!(!out.is_empty());
+        }
+    }
+
+    /// Serves `data` in 1000-byte reads, raising `cancel` once `after` bytes are out
+    struct Tripwire<'a> {
+        data: &'a [u8],
+        after: usize,
+        cancel: &'a AtomicBool,
+    }
+
+    impl Read for Tripwire<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(1000).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            self.after = self.after.saturating_sub(n);
+            if self.after == 0 {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn cancelling_mid_stream_removes_the_partial_file() {
+        let cipher = ZeroshieldCipher::new();
+        let data = vec![3u8; 500_000];
+        let out = std::env::temp_dir().join(format!("sqep-lite-{}.strm", nanoid::nanoid!()));
+        let out = out.to_str().expect("utf-8 temp path");
+
+        let cancel = AtomicBool::new(false);
+        let mut reader = Tripwire { data: &data, after: 50_000, cancel: &cancel };
+        let err = cipher.encrypt_stream_to_file(&mut reader, out, &cancel).unwrap_err();
+        let inner = err.get_ref().and_then(|e| e.downcast_ref::<SqepError>());
+        assert_eq!(inner, Some(&SqepError::Cancelled));
+        // Stopped at the flag instead of draining the input
+        assert_eq!(reader.data.len(), data.len() - 50_000);
+        assert!(fs::metadata(out).is_err());
+        assert!(fs::metadata(format!("{}.partial", out)).is_err());
+
+        let cancel = AtomicBool::new(false);
+        let n = cipher.encrypt_stream_to_file(&mut &data[..], out, &cancel).expect("encrypt");
+        assert_eq!(n, data.len() as u64);
+        assert_eq!(open(&cipher, &fs::read(out).expect("read")).unwrap(), data);
+        assert!(fs::metadata(format!("{}.partial", out)).is_err());
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    fn final_mac_covers_every_chunk() {
+        let cipher = ZeroshieldCipher::new();
+        let stream = seal(&cipher, &[9u8; 200], 64, true);
+        let sealed_chunk = 64 + TAG_LEN;
+        let chunks = (stream.len() - HEADER_LEN - MAC_LEN + sealed_chunk - 1) / sealed_chunk;
+        assert_eq!(chunks, 4);
+
+        let mac = &stream[stream.len() - MAC_LEN..];
+        for i in 0..chunks {
+            let mut tampered = stream.clone();
+            let tag_end = (HEADER_LEN + (i + 1) * sealed_chunk).min(stream.len() - MAC_LEN);
+            tampered[tag_end - 1] ^= 0x01;
+            assert!(open(&cipher, &tampered).is_err(), "chunk {}", i);
+
+            // Independently of the chunk's own tag, the final MAC no longer matches
+            let mut hasher = tag_hasher(tampered[..HEADER_LEN].try_into().unwrap()).unwrap();
+            for c in 0..chunks {
+                let end = (HEADER_LEN + (c + 1) * sealed_chunk).min(stream.len() - MAC_LEN);
+                hasher.update(&tampered[end - TAG_LEN..end]);
+            }
+            let mac_key = cipher.derive_subkey(MAC_LABEL);
+            let key = hmac::Key::new(hmac::HMAC_SHA256, mac_key.key_bytes());
+            assert!(hmac::verify(&key, &hasher.finalize(), mac).is_err());
+        }
+
+        let mut bad_mac = stream.clone();
+        *bad_mac.last_mut().unwrap() ^= 0x01;
+        assert!(open(&cipher, &bad_mac).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! `metrics` counters and histograms (feature `metrics`).
+//!
+//! * `sqep_encrypt_total`: AEAD seals, one per frame or stream chunk
+//! * `sqep_encrypt_duration`: seconds spent masking and sealing each one
+//! * `sqep_decrypt_failures_total`: frames or chunks that failed to open,
+//!   labelled `error` with the `SqepError` variant name
+//!
+//! Only counts and durations are recorded. Without the feature every hook
+//! here is an empty inline function.
+
+use crate::error::SqepError;
+
+/// Start time of a seal (zero-sized without the feature)
+pub(crate) struct Started(#[cfg(feature = "metrics")] std::time::Instant);
+
+/// Start timing a seal; pass the result to `encrypted`
+#[inline]
+pub(crate) fn seal_started() -> Started {
+    #[cfg(feature = "metrics")]
+    let started = Started(std::time::Instant::now());
+    #[cfg(not(feature = "metrics"))]
+    let started = Started();
+    started
+}
+
+/// Count one successful seal and record how long it took
+#[inline]
+pub(crate) fn encrypted(started: Started) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!("sqep_encrypt_total").increment(1);
+        metrics::histogram!("sqep_encrypt_duration").record(started.0.elapsed().as_secs_f64());
+    }
+    let _ = started;
+}
+
+/// Pass `result` through, counting it if opening failed
+#[inline]
+pub(crate) fn opened<T>(result: Result<T, SqepError>) -> Result<T, SqepError> {
+    #[cfg(feature = "metrics")]
+    if let Err(e) = &result {
+        metrics::counter!("sqep_decrypt_failures_total", "error" => e.variant_name()).increment(1);
+    }
+    result
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::sync::atomic::AtomicU64;
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+
+    use crate::lite::ZeroshieldCipher;
+
+    /// Hands out one shared counter per metric key
+    #[derive(Default)]
+    struct TestRecorder(Mutex<Vec<(Key, Arc<AtomicU64>)>>);
+
+    impl TestRecorder {
+        fn count(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+            let counters = self.0.lock().unwrap();
+            let found = counters.iter().find(|(key, _)| {
+                let key_labels: Vec<_> = key.labels().map(|l| (l.key(), l.value())).collect();
+                key.name() == name && key_labels == labels
+            });
+            found.map_or(0, |(_, c)| c.load(std::sync::atomic::Ordering::SeqCst))
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let mut counters = self.0.lock().unwrap();
+            let counter = match counters.iter().find(|(k, _)| k == key) {
+                Some((_, c)) => c.clone(),
+                None => {
+                    let c = Arc::new(AtomicU64::new(0));
+                    counters.push((key.clone(), c.clone()));
+                    c
+                }
+            };
+            Counter::from_arc(counter)
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn encrypts_and_failures_are_counted() {
+        let recorder = TestRecorder::default();
+        let cipher = ZeroshieldCipher::new();
+        metrics::with_local_recorder(&recorder, || {
+            let (ct, _m) = cipher.encrypt_with_meta(b"counted").expect("encrypt");
+            cipher.encrypt_with_meta(b"counted twice").expect("encrypt");
+            assert!(ZeroshieldCipher::new().decrypt(&ct).is_err());
+            assert!(cipher.decrypt(b"short").is_err());
+        });
+
+        assert_eq!(recorder.count("sqep_encrypt_total", &[]), 2);
+        let failures = "sqep_decrypt_failures_total";
+        assert_eq!(recorder.count(failures, &[("error", "DecryptionFailed")]), 1);
+        assert_eq!(recorder.count(failures, &[("error", "CiphertextTooShort")]), 1);
+    }
+}
@@ -0,0 +1,233 @@
+//! Optional type-length-value (TLV) encoding of frames.
+//!
+//! ```text
+//! TLV frame = SQEP4.0-LTLV (12) || RECORD*
+//! RECORD    = TYPE (1) || LEN (u32 BE) || VALUE (LEN)
+//!
+//! 0x01 HEADER      compact frame header: magic (naming the format version)
+//!                  and id bytes (algorithm, KDF hash, ...)
+//! 0x02 NONCE       12 bytes
+//! 0x03 CIPHERTEXT  masked, AEAD-encrypted plaintext
+//! 0x04 TAG         16 bytes
+//! 0x05 META        optional caller bytes, unauthenticated
+//! ```
+//!
+//! A TLV frame is a compact frame ([`crate::frame`]) re-encoded, a few
+//! bytes longer: `decode_tlv` reassembles the compact bytes, which then open
+//! as usual. Magic, version and algorithm therefore share the HEADER record,
+//! since that header is the AAD prefix the tag covers byte for byte.
+//!
+//! Records may come in any order, each type at most once. Decoders skip
+//! types they don't know, so later versions can add fields without
+//! breaking older parsers. Skipped records and META are not authenticated:
+//! treat them as hints, never as proof of anything about the plaintext.
+
+use std::borrow::Cow;
+
+use crate::error::SqepError;
+use crate::frame::{read_len, write_len, Frame, LEN_SIZE, NONCE_LEN, TAG_LEN, TLV_MAGIC};
+
+const HEADER: u8 = 0x01;
+const NONCE: u8 = 0x02;
+const CIPHERTEXT: u8 = 0x03;
+const TAG: u8 = 0x04;
+const META: u8 = 0x05;
+/// Bytes of a record before its value: TYPE and LEN
+const RECORD_HEADER_LEN: usize = 1 + LEN_SIZE;
+
+/// Byte layout of the frames a cipher writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FrameEncoding {
+    /// Fixed layout of [`crate::frame`] (default)
+    #[default]
+    Compact,
+    /// TLV records, see [`encode_tlv`]
+    Tlv,
+}
+
+impl FrameEncoding {
+    /// Bytes this encoding adds to a compact frame without META
+    pub(crate) fn overhead(self) -> usize {
+        match self {
+            FrameEncoding::Compact => 0,
+            FrameEncoding::Tlv => TLV_MAGIC.len() + 4 * RECORD_HEADER_LEN,
+        }
+    }
+
+    /// Re-encode the freshly sealed compact `frame` in this encoding
+    pub(crate) fn encode(self, frame: Vec<u8>) -> Result<Vec<u8>, SqepError> {
+        match self {
+            FrameEncoding::Compact => Ok(frame),
+            FrameEncoding::Tlv => encode_tlv(&frame, None),
+        }
+    }
+}
+
+/// Re-encode the compact `frame` as TLV records, with a META record if
+/// `meta` is given; bytes that don't decode as a frame fail like
+/// `Frame::decode`
+pub fn encode_tlv(frame: &[u8], meta: Option<&[u8]>) -> Result<Vec<u8>, SqepError> {
+    let frame = Frame::decode(frame)?;
+    let (ciphertext, tag) = frame.split_tag();
+    let records = [
+        (HEADER, frame.header()),
+        (NONCE, frame.nonce()),
+        (CIPHERTEXT, ciphertext),
+        (TAG, tag),
+    ];
+    let meta_len = meta.map_or(0, |meta| RECORD_HEADER_LEN + meta.len());
+    let mut out = Vec::with_capacity(frame.len() + FrameEncoding::Tlv.overhead() + meta_len);
+    out.extend_from_slice(TLV_MAGIC);
+    for (kind, value) in records.into_iter().chain(meta.map(|meta| (META, meta))) {
+        out.push(kind);
+        out.extend_from_slice(&write_len(value.len())?);
+        out.extend_from_slice(value);
+    }
+    Ok(out)
+}
+
+/// Reassemble the compact frame behind the TLV frame `bytes`, returning it
+/// with the META value, if any.
+///
+/// Unknown record types are skipped. A missing, repeated or wrongly sized
+/// HEADER, NONCE, CIPHERTEXT or TAG record fails with `InvalidHeader`, a
+/// record running past the end with `CiphertextTooShort`.
+pub fn decode_tlv(bytes: &[u8]) -> Result<(Vec<u8>, Option<Vec<u8>>), SqepError> {
+    let mut records = match bytes.strip_prefix(TLV_MAGIC) {
+        Some(records) => records,
+        None if bytes.len() < TLV_MAGIC.len() => return Err(SqepError::CiphertextTooShort),
+        None => return Err(SqepError::InvalidHeader),
+    };
+
+    let mut fields: [Option<&[u8]>; 5] = [None; 5];
+    while let Some((&kind, rest)) = records.split_first() {
+        let len = read_len(rest)?;
+        let value = rest[LEN_SIZE..].get(..len).ok_or(SqepError::CiphertextTooShort)?;
+        records = &rest[LEN_SIZE + len..];
+        let slot = match kind {
+            HEADER..=META => &mut fields[usize::from(kind - HEADER)],
+            _ => continue,
+        };
+        if slot.replace(value).is_some() {
+            return Err(SqepError::InvalidHeader);
+        }
+    }
+
+    let [Some(header), Some(nonce), Some(ciphertext), Some(tag), meta] = fields else {
+        return Err(SqepError::InvalidHeader);
+    };
+    if nonce.len() != NONCE_LEN || tag.len() != TAG_LEN {
+        return Err(SqepError::InvalidHeader);
+    }
+    let mut frame = [header, nonce, ciphertext, tag].concat();
+    let parsed = Frame::decode(&frame)?;
+    // HEADER must be exactly the compact header, not part of one
+    if parsed.header().len() != header.len() {
+        return Err(SqepError::InvalidHeader);
+    }
+    let position = parsed.tag_position();
+    position.place(&mut frame[header.len() + NONCE_LEN..]);
+    Ok((frame, meta.map(<[u8]>::to_vec)))
+}
+
+/// `bytes` as a compact frame: reassembled by `decode_tlv` if it is a TLV
+/// frame, borrowed as is otherwise
+pub(crate) fn compact_frame(bytes: &[u8]) -> Result<Cow<'_, [u8]>, SqepError> {
+    if bytes.starts_with(TLV_MAGIC) {
+        Ok(Cow::Owned(decode_tlv(bytes)?.0))
+    } else {
+        Ok(Cow::Borrowed(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::TagPosition;
+    use crate::lite::ZeroshieldCipher;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn tlv_frames_roundtrip_through_the_builder_and_by_hand() {
+        for position in [TagPosition::Append, TagPosition::Prepend] {
+            let cipher = ZeroshieldCipher::new()
+                .with_tag_position(position)
+                .with_frame_encoding(FrameEncoding::Tlv);
+            let (tlv, _m) = cipher.encrypt_with_meta(b"self-describing").expect("encrypt");
+            assert!(tlv.starts_with(TLV_MAGIC));
+            assert_eq!(tlv.len(), b"self-describing".len() + cipher.frame_overhead());
+            assert_eq!(cipher.decrypt(&tlv).unwrap(), b"self-describing");
+
+            let (compact, meta) = decode_tlv(&tlv).expect("decode");
+            assert_eq!(meta, None);
+            assert_eq!(cipher.decrypt(&compact).unwrap(), b"self-describing");
+            assert_eq!(encode_tlv(&compact, None).unwrap(), tlv);
+
+            let with_meta = encode_tlv(&compact, Some(b"label")).expect("encode");
+            assert_eq!(decode_tlv(&with_meta).unwrap(), (compact, Some(b"label".to_vec())));
+        }
+
+        // Decryption follows the frame, whatever the cipher writes
+        let compact_writer = ZeroshieldCipher::new();
+        let tlv_writer = compact_writer.clone().with_frame_encoding(FrameEncoding::Tlv);
+        let (tlv, _m) = tlv_writer.encrypt_with_meta(b"either way").expect("encrypt");
+        assert_eq!(compact_writer.decrypt(&tlv).unwrap(), b"either way");
+        let mut out = [0u8; 16];
+        let len = compact_writer.decrypt_into_slice(&tlv, &mut out).expect("into slice");
+        assert_eq!(&out[..len], b"either way");
+        assert_eq!(compact_writer.decrypt_owned(tlv.clone()).unwrap(), b"either way");
+        assert_eq!(compact_writer.decrypt_bounded(&tlv, 10).unwrap(), b"either way");
+    }
+
+    #[test]
+    fn every_seal_path_honours_the_encoding() {
+        let cipher = ZeroshieldCipher::new().with_frame_encoding(FrameEncoding::Tlv);
+        let mut scratch = crate::ScratchBuffers::new();
+        let (reused, _m) = cipher.encrypt_reusing(b"scratch", &mut scratch).expect("encrypt");
+        let mut writer = crate::FrameWriter::new(&cipher);
+        writer.push_header().push_nonce().append_sealed(b"piecemeal").expect("seal");
+        let (written, meta) = writer.finish();
+        let (trailed, _m) = cipher.encrypt_with_trailer(b"trailed", b"trailer").expect("encrypt");
+        for frame in [&reused, &written, &trailed] {
+            assert!(frame.starts_with(TLV_MAGIC));
+        }
+
+        assert_eq!(cipher.decrypt(&reused).unwrap(), b"scratch");
+        assert_eq!(cipher.decrypt(&written).unwrap(), b"piecemeal");
+        assert_eq!(meta.hash, format!("{:x}", Sha256::digest(&written)));
+        let (plaintext, trailer) = cipher.decrypt_with_trailer(&trailed).expect("decrypt");
+        assert_eq!((&plaintext[..], &trailer[..]), (&b"trailed"[..], &b"trailer"[..]));
+        let fragments = cipher.encrypt_fragmented(b"in pieces", 76).expect("fragment");
+        assert!(fragments.iter().all(|frame| frame.starts_with(TLV_MAGIC)));
+        assert_eq!(cipher.reassemble(&fragments).unwrap(), b"in pieces");
+    }
+
+    #[test]
+    fn unknown_records_are_skipped_and_bad_layouts_refused() {
+        let cipher = ZeroshieldCipher::new();
+        let (compact, _m) = cipher.encrypt_with_meta(b"forward compatible").expect("encrypt");
+        let tlv = encode_tlv(&compact, None).expect("encode");
+
+        // A record from a newer version, before and after the known ones
+        let unknown = [&[0x7F][..], &write_len(3).unwrap(), b"new"].concat();
+        let first = [TLV_MAGIC, &unknown, &tlv[TLV_MAGIC.len()..]].concat();
+        let last = [&tlv[..], &unknown].concat();
+        for extended in [first, last] {
+            assert_eq!(decode_tlv(&extended).unwrap(), (compact.clone(), None));
+            assert_eq!(cipher.decrypt(&extended).unwrap(), b"forward compatible");
+        }
+
+        let repeated = [&tlv[..], &tlv[TLV_MAGIC.len()..]].concat();
+        assert_eq!(decode_tlv(&repeated), Err(SqepError::InvalidHeader));
+        let no_tag = &tlv[..tlv.len() - RECORD_HEADER_LEN - TAG_LEN];
+        assert_eq!(decode_tlv(no_tag), Err(SqepError::InvalidHeader));
+        assert_eq!(decode_tlv(&tlv[..tlv.len() - 1]), Err(SqepError::CiphertextTooShort));
+        assert_eq!(decode_tlv(&compact), Err(SqepError::InvalidHeader));
+
+        // Records are re-encoded, not re-sealed: tampering still shows
+        let mut forged = tlv.clone();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert_eq!(cipher.decrypt(&forged), Err(SqepError::DecryptionFailed));
+    }
+}
@@ -0,0 +1,88 @@
+//! Compact URL-safe tokens with a freshness check.
+//!
+//! A token is the unpadded base64url encoding of a regular frame whose
+//! plaintext is `ISSUED_AT (u64 BE) || CLAIMS`, sealed with a token-only
+//! AAD so ordinary frames can't be replayed as tokens (or vice versa).
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::error::SqepError;
+use crate::lite::ZeroshieldCipher;
+
+const TOKEN_DOMAIN: &[u8] = b"SQEP:LITE:TOKEN:v1";
+const ISSUED_AT_LEN: usize = 8;
+
+impl ZeroshieldCipher {
+    /// Seal `claims` into a URL-safe token stamped with the current time
+    pub fn seal_token(&self, claims: impl AsRef<[u8]>) -> Result<String, SqepError> {
+        let claims = claims.as_ref();
+        self.seal_token_at(claims, self.clock().now_unix_secs())
+    }
+
+    /// Decode and decrypt a token from `seal_token`, returning its claims.
+    ///
+    /// Fails with `InvalidBase64` for malformed tokens, with the usual
+    /// decryption errors for forged ones, and with `Expired` once the token
+    /// is older than `max_age`.
+    pub fn open_token(&self, token: &str, max_age: Duration) -> Result<Vec<u8>, SqepError> {
+        let frame = URL_SAFE_NO_PAD.decode(token).map_err(|_| SqepError::InvalidBase64)?;
+        let inner = self.open_frame(&frame, TOKEN_DOMAIN)?;
+        if inner.len() < ISSUED_AT_LEN {
+            return Err(SqepError::DecryptionFailed);
+        }
+
+        let (issued, claims) = inner.split_at(ISSUED_AT_LEN);
+        let mut issued_arr = [0u8; ISSUED_AT_LEN];
+        issued_arr.copy_from_slice(issued);
+        let expires_at = u64::from_be_bytes(issued_arr).saturating_add(max_age.as_secs());
+        if self.clock().now_unix_secs() > expires_at {
+            return Err(SqepError::Expired { expires_at });
+        }
+        Ok(claims.to_vec())
+    }
+
+    fn seal_token_at(&self, claims: &[u8], issued_at: u64) -> Result<String, SqepError> {
+        let inner = [&issued_at.to_be_bytes()[..], claims].concat();
+        let frame = self.seal_frame(&inner, TOKEN_DOMAIN)?;
+        Ok(URL_SAFE_NO_PAD.encode(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+
+    #[test]
+    fn token_roundtrip_and_expiry() {
+        let cipher = ZeroshieldCipher::new();
+        let token = cipher.seal_token(b"user=42").expect("seal");
+        assert!(token.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+
+        let max_age = Duration::from_secs(60);
+        assert_eq!(cipher.open_token(&token, max_age).unwrap(), b"user=42");
+
+        // Both the stamp and the freshness check follow the cipher's clock
+        let stale = cipher.clone().with_clock(FixedClock(1_000)).seal_token(b"user=42").unwrap();
+        let later = cipher.with_clock(FixedClock(1_120));
+        assert_eq!(
+            later.open_token(&stale, max_age).unwrap_err(),
+            SqepError::Expired { expires_at: 1_060 }
+        );
+    }
+
+    #[test]
+    fn bad_base64_is_not_a_crypto_error() {
+        let cipher = ZeroshieldCipher::new();
+        let max_age = Duration::from_secs(60);
+        let malformed = cipher.open_token("not/valid+b64=", max_age);
+        assert_eq!(malformed.unwrap_err(), SqepError::InvalidBase64);
+
+        // A plain frame is not a token
+        let (ct, _m) = cipher.encrypt_with_meta(b"user=42").expect("encrypt");
+        let forged = URL_SAFE_NO_PAD.encode(ct);
+        assert_eq!(cipher.open_token(&forged, max_age).unwrap_err(), SqepError::DecryptionFailed);
+    }
+}
@@ -0,0 +1,166 @@
+//! An encrypted folder of named entries.
+//!
+//! Each entry is one file in the vault's directory, named after a keyed
+//! hash of the entry name so neither the name nor a path built from it ever
+//! reaches the filesystem:
+//!
+//! ```text
+//! <ID>.sqep = NAME_LEN (u32 BE) || NAME_FRAME || DATA_FRAME
+//! ID        = hex(first 16 bytes of the name's dedup tag under a vault subkey)
+//! ```
+//!
+//! Both frames are regular frames of the vault's cipher, sealed with AAD
+//! `"SQEP:LITE:VAULT:v1" || "name" | "data" || ID`, so an entry renamed to
+//! another entry's file, or a name frame spliced onto foreign data, fails
+//! to open. `list` only reads and opens the name frames.
+//!
+//! Names are plain strings but must look like a single file name anyway
+//! (non-empty, at most `MAX_NAME_LEN` bytes, no `/`, `\` or NUL, not `.` or
+//! `..`), so names handed back by `list` are safe to turn into paths, e.g.
+//! when exporting a vault.
+
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use crate::error::SqepError;
+use crate::frame::{read_len, write_len, LEN_SIZE};
+use crate::lite::ZeroshieldCipher;
+use crate::stream::{invalid, other};
+
+/// Longest entry name accepted, in bytes
+pub const MAX_NAME_LEN: usize = 255;
+
+const VAULT_DOMAIN: &[u8] = b"SQEP:LITE:VAULT:v1";
+const NAMES_LABEL: &[u8] = b"SQEP:LITE:VAULT:names";
+const ENTRY_EXT: &str = "sqep";
+/// Bytes of the name tag kept in the file name
+const ID_LEN: usize = 16;
+
+/// Named, encrypted entries stored as files under one directory
+pub struct Vault {
+    cipher: ZeroshieldCipher,
+    /// Subkey hashing entry names into file names
+    names: ZeroshieldCipher,
+    dir: PathBuf,
+}
+
+impl Vault {
+    /// Vault of `cipher` in `dir`, creating the directory if needed
+    pub fn open(cipher: ZeroshieldCipher, dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            names: cipher.derive_subkey(NAMES_LABEL),
+            cipher,
+            dir,
+        })
+    }
+
+    /// The vault's directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Encrypt `data` as entry `name`, replacing any previous entry of
+    /// that name. The file is written aside and renamed into place, so a
+    /// failed write leaves the previous entry intact.
+    pub fn store(&self, name: &str, data: impl AsRef<[u8]>) -> io::Result<()> {
+        let data = data.as_ref();
+        check_name(name)?;
+        let id = self.entry_id(name);
+        let name_frame = self.cipher.seal_frame(name.as_bytes(), &entry_aad(b"name", &id));
+        let data_frame = self.cipher.seal_frame(data, &entry_aad(b"data", &id));
+        let (name_frame, data_frame) = (name_frame.map_err(other)?, data_frame.map_err(other)?);
+        let name_len = write_len(name_frame.len()).map_err(other)?;
+
+        let path = self.entry_path(&id);
+        let partial = path.with_extension("partial");
+        let written = fs::write(&partial, [&name_len[..], &name_frame, &data_frame].concat())
+            .and_then(|()| fs::rename(&partial, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        written
+    }
+
+    /// Decrypt entry `name`; `NotFound` if there is none, `InvalidData`
+    /// if its file doesn't authenticate as that entry
+    pub fn load(&self, name: &str) -> io::Result<Vec<u8>> {
+        check_name(name)?;
+        let id = self.entry_id(name);
+        let file = fs::read(self.entry_path(&id))?;
+
+        let len = read_len(&file).map_err(invalid)?;
+        let name_end = LEN_SIZE.saturating_add(len);
+        let name_frame = file
+            .get(LEN_SIZE..name_end)
+            .ok_or_else(|| invalid(SqepError::CiphertextTooShort))?;
+        let stored =
+            self.cipher.open_frame(name_frame, &entry_aad(b"name", &id)).map_err(invalid)?;
+        if stored != name.as_bytes() {
+            return Err(invalid(SqepError::DecryptionFailed));
+        }
+        self.cipher.open_frame(&file[name_end..], &entry_aad(b"data", &id)).map_err(invalid)
+    }
+
+    /// Names of all entries, sorted. Opens each entry's name frame (not its
+    /// data); a file in the directory that doesn't authenticate as an entry
+    /// of this vault fails with `InvalidData`.
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.extension().map_or(true, |ext| ext != ENTRY_EXT) {
+                continue;
+            }
+            let id = path.file_stem().and_then(|id| id.to_str()).unwrap_or_default();
+            names.push(self.read_name(&path, id)?);
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Delete entry `name`; `NotFound` if there is none
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        check_name(name)?;
+        fs::remove_file(self.entry_path(&self.entry_id(name)))
+    }
+
+    /// Open just the name frame at the front of the entry file at `path`
+    fn read_name(&self, path: &Path, id: &str) -> io::Result<String> {
+        let mut file = fs::File::open(path)?;
+        let mut len_bytes = [0u8; LEN_SIZE];
+        file.read_exact(&mut len_bytes)?;
+        let len = read_len(&len_bytes).map_err(invalid)?;
+
+        // Don't trust `len` for allocation: read at most that many bytes
+        let mut name_frame = Vec::new();
+        file.take(len as u64).read_to_end(&mut name_frame)?;
+        let name = self.cipher.open_frame(&name_frame, &entry_aad(b"name", id)).map_err(invalid)?;
+        String::from_utf8(name).map_err(|_| invalid(SqepError::InvalidUtf8))
+    }
+
+    fn entry_id(&self, name: &str) -> String {
+        let tag = self.names.dedup_tag(name.as_bytes());
+        tag[..ID_LEN].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn entry_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id).with_extension(ENTRY_EXT)
+    }
+}
+
+/// `InvalidInput` unless `name` is usable as a single file name
+fn check_name(name: &str) -> io::Result<()> {
+    let bad_char = name.contains(['/', '\\', '\0']);
+    if name.is_empty() || name.len() > MAX_NAME_LEN || bad_char || name == "." || name == ".." {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "invalid vault entry name"));
+    }
+    Ok(())
+}
+
+/// `VAULT_DOMAIN || ROLE || ID`; both roles are four bytes long
+fn entry_aad(role: &[u8; 4], id: &str) -> Vec<u8> {
+    [VAULT_DOMAIN, role, id.as_bytes()].concat()
+}
@@ -0,0 +1,83 @@
+//! Counting global allocator shared by the allocation tests and benches.
+//!
+//! A test or bench binary installs it by declaring this module (benches via
+//! `#[path]`), which is why those checks live in binaries of their own.
+//! Counts are kept per thread, so tests running in parallel don't see
+//! each other's allocations.
+
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Allocations made on this thread while a `measure`d closure ran
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Allocations {
+    /// Calls to `alloc`
+    pub count: usize,
+    /// Bytes requested in total
+    pub bytes: usize,
+    /// Most bytes live at once, above what was live before
+    pub peak: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Totals {
+    count: usize,
+    bytes: usize,
+    live: usize,
+    peak: usize,
+}
+
+thread_local! {
+    static TOTALS: Cell<Totals> = const {
+        Cell::new(Totals { count: 0, bytes: 0, live: 0, peak: 0 })
+    };
+}
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = TOTALS.try_with(|totals| {
+            let mut t = totals.get();
+            t.count += 1;
+            t.bytes += layout.size();
+            t.live += layout.size();
+            t.peak = t.peak.max(t.live);
+            totals.set(t);
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = TOTALS.try_with(|totals| {
+            let mut t = totals.get();
+            // Memory allocated on another thread may be freed on this one
+            t.live = t.live.saturating_sub(layout.size());
+            totals.set(t);
+        });
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+/// Run `op`, returning its result and this thread's allocations meanwhile
+pub fn measure<T>(op: impl FnOnce() -> T) -> (T, Allocations) {
+    let before = TOTALS.with(|totals| {
+        let mut t = totals.get();
+        t.peak = t.live;
+        totals.set(t);
+        t
+    });
+    let result = op();
+    let after = TOTALS.with(Cell::get);
+    let allocations = Allocations {
+        count: after.count - before.count,
+        bytes: after.bytes - before.bytes,
+        peak: after.peak.saturating_sub(before.live),
+    };
+    (result, allocations)
+}
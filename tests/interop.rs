@@ -0,0 +1,79 @@
+//! Frames from this crate (`ring` AEAD) opened with RustCrypto instead.
+//!
+//! Everything below the frame is rebuilt from the published format: the
+//! header and nonce are split off by hand, the mask seed comes from the
+//! `hkdf` crate, the mask keystream from the `chacha20` crate and the AEAD
+//! from `chacha20poly1305`. A framing, AAD or mask mismatch between this
+//! crate and the documented format fails here.
+
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use sha2::{Sha256, Sha384};
+
+use sqep_lite::{KdfHash, ZeroshieldCipher};
+
+const KEY: [u8; 32] = [0x42; 32];
+const MAGIC_LEN: usize = 12;
+const NONCE_LEN: usize = 12;
+const QT_DOMAIN: &[u8] = b"SQEP:LITE:QT:v1";
+
+/// `header || nonce || body` at `header_len`, opened and unmasked
+fn open_independently(frame: &[u8], header_len: usize, aad: &[u8], seed: [u8; 32]) -> Vec<u8> {
+    let (nonce, body) = frame[header_len..].split_at(NONCE_LEN);
+    let aead = ChaCha20Poly1305::new(&KEY.into());
+    let mut masked = aead
+        .decrypt(nonce.into(), Payload { msg: body, aad })
+        .expect("RustCrypto open");
+
+    // The mask is the ChaCha20 keystream under the seed, nonce 0, counter 0
+    let mut keystream = chacha20::ChaCha20::new(&seed.into(), &[0u8; 12].into());
+    keystream.apply_keystream(&mut masked);
+    masked
+}
+
+#[test]
+fn classic_frame_opens_with_rustcrypto() {
+    let plaintext: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+    let (frame, _meta) = ZeroshieldCipher::from_key(KEY).encrypt_with_meta(&plaintext).unwrap();
+    assert_eq!(&frame[..MAGIC_LEN], b"SQEP4.0-LITE");
+
+    // Classic frames don't bind their magic: empty AAD
+    let nonce = &frame[MAGIC_LEN..MAGIC_LEN + NONCE_LEN];
+    let mut seed = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(nonce), &KEY).expand(QT_DOMAIN, &mut seed).unwrap();
+    assert_eq!(open_independently(&frame, MAGIC_LEN, &[], seed), plaintext);
+}
+
+#[test]
+fn extended_frame_opens_with_rustcrypto() {
+    let cipher = ZeroshieldCipher::from_key(KEY).with_kdf_hash(KdfHash::Sha384);
+    let (frame, _meta) = cipher.encrypt_with_meta(b"header bound via AAD").unwrap();
+
+    // `SQEP4.2-LITE || ALG || KDF`, the whole header authenticated
+    let header_len = MAGIC_LEN + 2;
+    let header = &frame[..header_len];
+    assert_eq!(header, &[&b"SQEP4.2-LITE"[..], &[0x01, KdfHash::Sha384.id()]].concat()[..]);
+
+    let nonce = &frame[header_len..header_len + NONCE_LEN];
+    let mut seed = [0u8; 32];
+    Hkdf::<Sha384>::new(Some(nonce), &KEY).expand(QT_DOMAIN, &mut seed).unwrap();
+    assert_eq!(open_independently(&frame, header_len, header, seed), b"header bound via AAD");
+}
+
+#[test]
+fn rustcrypto_frame_opens_with_this_crate() {
+    let plaintext = b"sealed by the other implementation";
+    let nonce = [9u8; NONCE_LEN];
+    let mut seed = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&nonce), &KEY).expand(QT_DOMAIN, &mut seed).unwrap();
+
+    let mut masked = plaintext.to_vec();
+    let mut keystream = chacha20::ChaCha20::new(&seed.into(), &[0u8; 12].into());
+    keystream.apply_keystream(&mut masked);
+    let body = ChaCha20Poly1305::new(&KEY.into()).encrypt(&nonce.into(), &masked[..]).unwrap();
+
+    let frame = [&b"SQEP4.0-LITE"[..], &nonce, &body].concat();
+    assert_eq!(ZeroshieldCipher::from_key(KEY).decrypt(&frame).unwrap(), plaintext);
+}
@@ -0,0 +1,26 @@
+//! Checks `encrypt_memory_estimate` against the real peak allocation.
+
+mod common;
+
+use sqep_lite::{Config, ZeroshieldCipher};
+
+#[test]
+fn estimate_is_within_a_small_factor_of_actual() {
+    let cipher = ZeroshieldCipher::new();
+    for len in [4 * 1024, 64 * 1024, 1024 * 1024] {
+        let plaintext = vec![0x5Au8; len];
+
+        let (sealed, allocations) = common::measure(|| cipher.encrypt_with_meta(&plaintext));
+        sealed.expect("encrypt");
+        let actual = allocations.peak;
+
+        let estimate = ZeroshieldCipher::encrypt_memory_estimate(len, &Config::default());
+        assert!(
+            estimate <= 2 * actual && actual <= 2 * estimate,
+            "len {}: estimate {} vs actual {}",
+            len,
+            estimate,
+            actual
+        );
+    }
+}
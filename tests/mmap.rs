@@ -0,0 +1,48 @@
+//! `decrypt_file_mmap` against the buffered `decrypt_stream` path.
+
+#![cfg(feature = "mmap")]
+
+use std::fs;
+use std::io::Write;
+
+use sqep_lite::{EncryptWriter, ZeroshieldCipher};
+
+#[test]
+fn mapped_output_matches_buffered_decrypt() {
+    let cipher = ZeroshieldCipher::new();
+    let data: Vec<u8> = (0..3 * 1024 * 1024 + 12_345u32).map(|i| (i % 251) as u8).collect();
+
+    let base = std::env::temp_dir().join(format!("sqep-lite-{}", nanoid::nanoid!()));
+    let (sealed, restored) = (base.with_extension("strm"), base.with_extension("out"));
+    let (sealed, restored) = (sealed.to_str().unwrap(), restored.to_str().unwrap());
+
+    for mac in [false, true] {
+        let mut enc = EncryptWriter::new(&cipher, Vec::new())
+            .expect("writer")
+            .with_chunk_size(64 * 1024)
+            .with_final_mac(mac);
+        enc.write_all(&data).expect("write");
+        let stream = enc.finish().expect("finish");
+        fs::write(sealed, &stream).expect("write stream");
+
+        // A longer stale file at the output path is truncated
+        fs::write(restored, vec![0xffu8; data.len() + 100]).expect("write stale output");
+        let n = cipher.decrypt_file_mmap(sealed, restored).expect("decrypt");
+        assert_eq!(n, data.len() as u64);
+
+        let mut buffered = Vec::new();
+        cipher.decrypt_stream(&mut &stream[..], &mut buffered).expect("decrypt");
+        assert_eq!(fs::read(restored).expect("read output"), buffered);
+    }
+
+    // Tampering leaves only the verified prefix
+    let mut stream = fs::read(sealed).expect("read stream");
+    let sealed_chunk = 64 * 1024 + 16;
+    stream[25 + 2 * sealed_chunk + 5] ^= 1;
+    fs::write(sealed, &stream).expect("write stream");
+    assert!(cipher.decrypt_file_mmap(sealed, restored).is_err());
+    assert_eq!(fs::read(restored).expect("read output"), data[..2 * 64 * 1024]);
+
+    let _ = fs::remove_file(sealed);
+    let _ = fs::remove_file(restored);
+}
@@ -0,0 +1,29 @@
+//! Checks that `decrypt_into_slice` and `decrypt_owned` never touch the heap.
+
+mod common;
+
+use sqep_lite::ZeroshieldCipher;
+
+#[test]
+fn decrypt_into_slice_does_not_allocate() {
+    let cipher = ZeroshieldCipher::new();
+    let (ct, _meta) = cipher.encrypt_with_meta(b"small embedded frame").expect("encrypt");
+    let mut out = [0u8; 64];
+
+    let (len, allocations) = common::measure(|| cipher.decrypt_into_slice(&ct, &mut out));
+    let len = len.expect("decrypt");
+
+    assert_eq!(&out[..len], b"small embedded frame");
+    assert_eq!(allocations.count, 0, "decrypt_into_slice allocated");
+}
+
+#[test]
+fn decrypt_owned_reuses_the_frame_allocation() {
+    let cipher = ZeroshieldCipher::new();
+    let (ct, _meta) = cipher.encrypt_with_meta(b"decrypted where it lies").expect("encrypt");
+
+    let (pt, allocations) = common::measure(|| cipher.decrypt_owned(ct));
+
+    assert_eq!(pt.expect("decrypt"), b"decrypted where it lies");
+    assert_eq!(allocations.count, 0, "decrypt_owned allocated");
+}
@@ -0,0 +1,74 @@
+//! `Vault` end to end on a real directory.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use sqep_lite::{Vault, ZeroshieldCipher};
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("sqep-lite-{}", nanoid::nanoid!()))
+}
+
+#[test]
+fn store_list_load_remove() {
+    let dir = temp_dir();
+    let cipher = ZeroshieldCipher::new();
+    let vault = Vault::open(cipher.clone(), &dir).expect("open");
+
+    vault.store("notes.txt", b"remember the milk").expect("store");
+    vault.store("empty", b"").expect("store");
+    vault.store("notes.txt", b"remember the eggs").expect("overwrite");
+    assert_eq!(vault.list().unwrap(), ["empty", "notes.txt"]);
+    assert_eq!(vault.load("notes.txt").unwrap(), b"remember the eggs");
+    assert_eq!(vault.load("empty").unwrap(), b"");
+
+    // Names never reach the filesystem
+    for entry in fs::read_dir(&dir).unwrap() {
+        let file_name = entry.unwrap().file_name();
+        assert!(!file_name.to_string_lossy().contains("notes"));
+    }
+
+    // The same key and directory reopen the vault
+    let reopened = Vault::open(cipher, &dir).expect("open");
+    assert_eq!(reopened.load("notes.txt").unwrap(), b"remember the eggs");
+
+    vault.remove("notes.txt").expect("remove");
+    assert_eq!(vault.list().unwrap(), ["empty"]);
+    assert_eq!(vault.load("notes.txt").unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(vault.remove("notes.txt").unwrap_err().kind(), ErrorKind::NotFound);
+    fs::remove_dir_all(&dir).expect("cleanup");
+}
+
+#[test]
+fn path_traversal_names_are_rejected() {
+    let dir = temp_dir();
+    let vault = Vault::open(ZeroshieldCipher::new(), dir.join("vault")).expect("open");
+
+    for name in ["../escape", "..", ".", "a/b", "a\\b", "nul\0byte", ""] {
+        let err = vault.store(name, b"x").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput, "{:?}", name);
+        assert_eq!(vault.load(name).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(vault.remove(name).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+    assert!(vault.list().unwrap().is_empty());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).expect("cleanup");
+}
+
+#[test]
+fn swapped_entry_files_fail_to_open() {
+    let dir = temp_dir();
+    let vault = Vault::open(ZeroshieldCipher::new(), &dir).expect("open");
+    vault.store("a", b"alpha").expect("store");
+    vault.store("b", b"bravo").expect("store");
+
+    // Copy one entry's file over the other's, as anyone with disk access could
+    let files: Vec<PathBuf> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+    fs::copy(&files[1], &files[0]).expect("copy");
+    let failed: Vec<_> = ["a", "b"].iter().filter_map(|n| vault.load(n).err()).collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].kind(), ErrorKind::InvalidData);
+    assert_eq!(vault.list().unwrap_err().kind(), ErrorKind::InvalidData);
+    fs::remove_dir_all(&dir).expect("cleanup");
+}